@@ -0,0 +1,231 @@
+//! The columns of task lists, shared by all output formats
+
+use date;
+use model::Model;
+use task::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Column {
+    Id,
+    Uuid,
+    ShortUuid,
+    Active,
+    Priority,
+    Age,
+    Description,
+    Tags,
+    Urgency,
+    Status,
+    Created,
+    Modified,
+    End,
+    Wait,
+    Scheduled,
+    Due,
+    Notes,
+}
+
+pub const DEFAULT_COLUMNS: &'static [Column] = &[
+    Column::Id,
+    Column::Priority,
+    Column::Age,
+    Column::Description,
+    Column::Tags,
+    Column::Urgency,
+];
+
+/// A comparable cell value, values of the same column always have
+/// the same variant
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum SortValue {
+    Number(f64),
+    Priority(Priority),
+    Text(String),
+    Time(Time),
+    Uuid(Uuid),
+}
+
+#[derive(Debug, PartialEq, Eq, Fail)]
+#[fail(display = "Unknown column {:?}", _0)]
+pub struct ColumnError(String);
+
+/// Everything besides the task itself needed to fill in a cell
+pub struct CellContext<'a> {
+    pub model: &'a Model,
+    pub scope: &'a str,
+    pub tag_separator: &'a str,
+}
+
+impl Column {
+    pub fn title(&self) -> &'static str {
+        match *self {
+            Column::Id => "id",
+            Column::Uuid => "uuid",
+            Column::ShortUuid => "short",
+            Column::Active => "act",
+            Column::Priority => "pri",
+            Column::Age => "age",
+            Column::Description => "desc",
+            Column::Tags => "tags",
+            Column::Urgency => "urg",
+            Column::Status => "status",
+            Column::Created => "created",
+            Column::Modified => "modified",
+            Column::End => "end",
+            Column::Wait => "wait",
+            Column::Scheduled => "sched",
+            Column::Due => "due",
+            Column::Notes => "notes",
+        }
+    }
+
+    pub fn value(&self, task: &Task, context: &CellContext) -> String {
+        let date = |t: Option<Time>| t.as_ref().map_or(String::new(), date::format_local);
+
+        match *self {
+            Column::Id => context
+                .model
+                .short_task_id(context.scope, &task.uuid)
+                .map(|n| n.to_string())
+                .unwrap_or(task.short_id()),
+            Column::Uuid => task.uuid.hyphenated().to_string(),
+            Column::ShortUuid => task.short_id(),
+            Column::Active => task.active_for().map_or(String::new(), |a| a.to_string()),
+            Column::Priority => task.priority.to_string(),
+            Column::Age => task.age().to_string(),
+            Column::Description => task.description.clone(),
+            Column::Tags => {
+                let mut tags: Vec<&str> = task.tags.iter().map(|t| &t[..]).collect();
+                tags.sort();
+                tags.join(context.tag_separator)
+            }
+            Column::Urgency => format!("{:.2}", task.urgency()),
+            Column::Status => task.status.to_string(),
+            Column::Created => date::format_local(&task.created),
+            Column::Modified => date::format_local(&task.modified),
+            Column::End => date(task.closed()),
+            Column::Wait => date(task.wait),
+            Column::Scheduled => date(task.scheduled),
+            Column::Due => date(task.due),
+            Column::Notes => match notes_count(task) {
+                0 => String::new(),
+                n => n.to_string(),
+            },
+        }
+    }
+
+    /// The value tasks get sorted by in this column. Tasks without a
+    /// value, e.g. without a due date or numerical id, return `None`.
+    pub fn sort_value(&self, task: &Task, model: &Model, scope: &str) -> Option<SortValue> {
+        match *self {
+            Column::Id => model
+                .short_task_id(scope, &task.uuid)
+                .map(|n| SortValue::Number(n as f64)),
+            Column::Uuid | Column::ShortUuid => Some(SortValue::Uuid(task.uuid)),
+            Column::Active => task.active_since().map(SortValue::Time),
+            Column::Priority => Some(SortValue::Priority(task.priority)),
+            // Older tasks have a larger age
+            Column::Age => Some(SortValue::Number(-task.created.timestamp() as f64)),
+            Column::Description => Some(SortValue::Text(task.description.to_lowercase())),
+            Column::Tags => {
+                let mut tags: Vec<String> = task.tags.iter().map(|t| t.to_lowercase()).collect();
+                tags.sort();
+                tags.into_iter().next().map(SortValue::Text)
+            }
+            Column::Urgency => Some(SortValue::Number(task.urgency() as f64)),
+            Column::Status => Some(SortValue::Text(task.status.to_string())),
+            Column::Created => Some(SortValue::Time(task.created)),
+            Column::Modified => Some(SortValue::Time(task.modified)),
+            Column::End => task.closed().map(SortValue::Time),
+            Column::Wait => task.wait.map(SortValue::Time),
+            Column::Scheduled => task.scheduled.map(SortValue::Time),
+            Column::Due => task.due.map(SortValue::Time),
+            Column::Notes => Some(SortValue::Number(notes_count(task) as f64)),
+        }
+    }
+
+    /// Parses a comma separated list of columns
+    pub fn parse_list(s: &str) -> Result<Vec<Column>, ColumnError> {
+        s.split(',')
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+            .map(Column::from_str)
+            .collect()
+    }
+}
+
+fn notes_count(task: &Task) -> usize {
+    task.extras
+        .get(&ExtraData::Notes)
+        .map_or(0, |notes| notes.lines().filter(|l| !l.trim().is_empty()).count())
+}
+
+impl FromStr for Column {
+    type Err = ColumnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "id" => Ok(Column::Id),
+            "uuid" => Ok(Column::Uuid),
+            "short" | "short-uuid" => Ok(Column::ShortUuid),
+            "act" | "active" => Ok(Column::Active),
+            "pri" | "priority" => Ok(Column::Priority),
+            "age" => Ok(Column::Age),
+            "desc" | "description" => Ok(Column::Description),
+            "tags" => Ok(Column::Tags),
+            "urg" | "urgency" => Ok(Column::Urgency),
+            "status" => Ok(Column::Status),
+            "created" => Ok(Column::Created),
+            "modified" => Ok(Column::Modified),
+            "end" => Ok(Column::End),
+            "wait" => Ok(Column::Wait),
+            "sched" | "scheduled" => Ok(Column::Scheduled),
+            "due" => Ok(Column::Due),
+            "notes" => Ok(Column::Notes),
+            _ => Err(ColumnError(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.title())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            Ok(vec![Column::Id, Column::Description, Column::Scheduled]),
+            Column::parse_list("id, description,sched")
+        );
+        assert_eq!(Err(ColumnError("foo".into())), Column::parse_list("id,foo"));
+    }
+
+    #[test]
+    fn test_value() {
+        let mut model = Model::new();
+        let mut task = Task::new("foo");
+        task.tags.insert("b".into());
+        task.tags.insert("a".into());
+        model.apply_effect(&::Effect::AddTask(task.clone()));
+        model.recalculate_numerical_ids("default", &[task.uuid]);
+
+        let context = CellContext {
+            model: &model,
+            scope: "default",
+            tag_separator: " ",
+        };
+        assert_eq!("1", Column::Id.value(&task, &context));
+        assert_eq!("a b", Column::Tags.value(&task, &context));
+        assert_eq!("open", Column::Status.value(&task, &context));
+        assert_eq!("", Column::Due.value(&task, &context));
+    }
+}
@@ -0,0 +1,347 @@
+//! Turns the commands changing tasks into effects. Every frontend goes
+//! through here, so commands are checked the same way everywhere.
+
+use chrono;
+
+use task::*;
+use task_ref::TaskRefs;
+use {Effect, FindTaskError, Model, Scope};
+
+use super::{filter_tasks, Command, Flag};
+
+#[derive(Debug, PartialEq, Eq, Fail)]
+pub enum CommandError {
+    #[fail(display = "{}", _0)]
+    FindTask(FindTaskError),
+    #[fail(display = "{}", _0)]
+    Invalid(String),
+}
+
+impl From<FindTaskError> for CommandError {
+    fn from(e: FindTaskError) -> Self {
+        CommandError::FindTask(e)
+    }
+}
+
+/// What a command changes
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub effects: Vec<Effect>,
+    /// Why parts of the command were left out, like starting a task
+    /// that is already active
+    pub notes: Vec<String>,
+}
+
+/// Tags are single words, virtual tags can't be set
+pub fn validate_tag(tag: &str) -> Result<(), CommandError> {
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        Err(CommandError::Invalid(format!("Invalid tag {:?}", tag)))
+    } else if VIRTUAL_TAGS.contains(&tag) {
+        Err(CommandError::Invalid(format!("{} is set automatically", tag)))
+    } else {
+        Ok(())
+    }
+}
+
+impl Command {
+    /// The effects of a command changing tasks in `scope`, commands
+    /// which only show something have none
+    pub fn effects(self, model: &Model, scope: &Scope) -> Result<Changes, CommandError> {
+        let now = chrono::Utc::now();
+        let mut changes = Changes::default();
+
+        match self.resolve(model, scope)? {
+            Command::Add(title, flags) => {
+                if title.trim().is_empty() {
+                    return Err(CommandError::Invalid("Tasks need a description".into()));
+                }
+
+                // If in a scope, add scope-tag to `tags`
+                let tags = scope.as_tag().into_iter().collect();
+
+                info!("Got flags: {:?}", flags);
+
+                let mut task = Task::new_with_tags(title.trim(), tags);
+                for flag in flags {
+                    if let Flag::TagPositive(ref tag) = flag {
+                        validate_tag(tag)?;
+                    }
+                    flag.apply_to(&mut task);
+                }
+
+                changes.effects.push(Effect::AddTask(task));
+            }
+            Command::Delete(refs) => {
+                changes.effects = refs.iter()
+                    .flat_map(|tr| model.find_task(scope, tr))
+                    .map(|t| Effect::DeleteTask(t.uuid.clone()))
+                    .collect();
+            }
+            Command::MarkDone(refs) => {
+                let state = TaskState::Done(now);
+                for task in refs.iter().flat_map(|tr| model.find_task(scope, tr)) {
+                    if task.is_active() {
+                        changes.effects.push(Effect::StopTask(task.uuid, now));
+                    }
+                    changes.effects.push(Effect::ChangeTaskState(task.uuid.clone(), state.clone()));
+                }
+            }
+            Command::MarkCanceled(refs) => {
+                let state = TaskState::Canceled(now);
+                for task in refs.iter().flat_map(|tr| model.find_task(scope, tr)) {
+                    if task.is_active() {
+                        changes.effects.push(Effect::StopTask(task.uuid, now));
+                    }
+                    changes.effects.push(Effect::ChangeTaskState(task.uuid.clone(), state.clone()));
+                }
+            }
+            Command::Start {
+                task_refs,
+                stop_others,
+            } => {
+                let mut uuids = vec![];
+                for task_ref in task_refs {
+                    let task = model.find_task(scope, &task_ref)?;
+                    if task.is_active() {
+                        changes.notes.push(format!("Task '{}' is already active", task.description));
+                    } else {
+                        uuids.push(task.uuid);
+                    }
+                }
+
+                if stop_others {
+                    changes.effects.extend(
+                        model
+                            .all_tasks()
+                            .filter(|t| t.is_active() && !uuids.contains(&t.uuid))
+                            .map(|t| Effect::StopTask(t.uuid, now)),
+                    );
+                }
+                changes.effects.extend(uuids.into_iter().map(|u| Effect::StartTask(u, now)));
+            }
+            Command::Stop(refs) => {
+                for task_ref in refs {
+                    let task = model.find_task(scope, &task_ref)?;
+                    if task.is_active() {
+                        changes.effects.push(Effect::StopTask(task.uuid, now));
+                    } else {
+                        changes.notes.push(format!("Task '{}' isn't active", task.description));
+                    }
+                }
+            }
+            Command::ChangeTaskProperties {
+                task_refs,
+                added_tags,
+                removed_tags,
+                priority,
+                dates,
+            } => {
+                for tag in added_tags.iter() {
+                    validate_tag(tag)?;
+                }
+
+                for task_ref in task_refs {
+                    let task = model.find_task(scope, &task_ref)?;
+
+                    if let Some(p) = priority {
+                        changes.effects.push(Effect::ChangeTaskPriority(task.uuid.clone(), p));
+                    }
+
+                    for (kind, date) in dates.iter() {
+                        changes.effects.push(Effect::ChangeTaskDate(task.uuid.clone(), *kind, *date));
+                    }
+
+                    if !added_tags.is_empty() || !removed_tags.is_empty() {
+                        changes.effects.push(Effect::ChangeTaskTags {
+                            uuid: task.uuid.clone(),
+                            added: added_tags.clone(),
+                            removed: removed_tags.clone(),
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        Ok(changes)
+    }
+
+    /// Turns a `Filtered` command into the command on the matching
+    /// tasks, all other commands stay as they are. Given task ids have
+    /// to match the filter as well.
+    pub fn resolve(self, model: &Model, scope: &Scope) -> Result<Command, CommandError> {
+        let (flags, command) = match self {
+            Command::Filtered(flags, command) => (flags, *command),
+            command => return Ok(command),
+        };
+
+        command.map_task_refs(|refs| {
+            let mut tasks: Vec<&Task> = vec![];
+            if refs.is_empty() {
+                let mut flags = flags.clone();
+                flags.extend(scope.as_tag().map(Flag::TagPositive));
+                tasks = filter_tasks(model, &flags);
+            }
+            for task_ref in refs.iter() {
+                let task = model.find_task(scope, task_ref)?;
+                if flags.iter().all(|f| f.matches(task)) && !tasks.iter().any(|t| t.uuid == task.uuid) {
+                    tasks.push(task);
+                }
+            }
+
+            if tasks.is_empty() {
+                return Err(FindTaskError::TaskNotFound.into());
+            }
+            Ok(tasks.iter().map(|t| t.uuid.into()).collect())
+        })
+    }
+
+    fn map_task_refs<F>(self, f: F) -> Result<Command, CommandError>
+    where
+        F: FnOnce(TaskRefs) -> Result<TaskRefs, CommandError>,
+    {
+        Ok(match self {
+            Command::Show(refs) => Command::Show(f(refs)?),
+            Command::MarkDone(refs) => Command::MarkDone(f(refs)?),
+            Command::MarkCanceled(refs) => Command::MarkCanceled(f(refs)?),
+            Command::Delete(refs) => Command::Delete(f(refs)?),
+            Command::History(refs) => Command::History(f(refs)?),
+            Command::Start {
+                task_refs,
+                stop_others,
+            } => Command::Start {
+                task_refs: f(task_refs)?,
+                stop_others: stop_others,
+            },
+            Command::Stop(refs) => Command::Stop(f(refs)?),
+            Command::ChangeTaskProperties {
+                task_refs,
+                added_tags,
+                removed_tags,
+                priority,
+                dates,
+            } => Command::ChangeTaskProperties {
+                task_refs: f(task_refs)?,
+                added_tags: added_tags,
+                removed_tags: removed_tags,
+                priority: priority,
+                dates: dates,
+            },
+            command => {
+                return Err(CommandError::Invalid(format!(
+                    "{:?} doesn't work on selected tasks",
+                    command
+                )))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effects() {
+        let mut m = Model::new();
+        let scope = Scope(Some("work".into()));
+
+        let add = Command::Add("foo".into(), vec![Flag::TagPositive("bar".into())]);
+        let changes = add.effects(&m, &scope).unwrap();
+        let task = match changes.effects[..] {
+            [Effect::AddTask(ref task)] => task.clone(),
+            ref effects => panic!("Unexpected effects {:?}", effects),
+        };
+        assert_eq!(2, task.tags.len());
+        assert!(task.has_tag("work"));
+        m.apply_effect(&changes.effects[0]);
+        m.recalculate_numerical_ids(&scope, &[task.uuid]);
+
+        let start = |m: &Model| {
+            Command::Start {
+                task_refs: vec![task.uuid.into()],
+                stop_others: false,
+            }.effects(m, &scope)
+                .unwrap()
+        };
+        m.apply_effect(&start(&m).effects[0]);
+        let changes = start(&m);
+        assert!(changes.effects.is_empty());
+        assert_eq!(vec!["Task 'foo' is already active"], changes.notes);
+
+        // Tasks are stopped when they're done
+        let changes = Command::MarkDone(vec!["1".parse().unwrap()]).effects(&m, &scope).unwrap();
+        assert_eq!(2, changes.effects.len());
+
+        assert_eq!(
+            Err(CommandError::FindTask(FindTaskError::TaskNotFound)),
+            Command::Stop(vec!["2".parse().unwrap()]).effects(&m, &scope)
+        );
+        assert_eq!(Ok(Changes::default()), Command::Tui.effects(&m, &scope));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut m = Model::new();
+        let scope = Scope(None);
+        let mut uuids = vec![];
+        for (description, tag) in vec![("foo", "work"), ("bar", "home"), ("baz", "work")] {
+            let mut task = Task::new(description);
+            task.tags.insert(tag.into());
+            m.apply_effect(&Effect::AddTask(task.clone()));
+            uuids.push(task.uuid);
+        }
+        m.recalculate_numerical_ids(&scope, &uuids);
+        let work = || vec![Flag::TagPositive("work".into())];
+
+        match Command::Filtered(work(), Box::new(Command::MarkDone(vec![]))).resolve(&m, &scope) {
+            Ok(Command::MarkDone(refs)) => {
+                assert_eq!(2, refs.len());
+                assert!(refs.contains(&uuids[0].into()) && refs.contains(&uuids[2].into()));
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+
+        // Task ids have to match the filter as well
+        let refs = vec!["1".parse().unwrap(), "2".parse().unwrap(), "1".parse().unwrap()];
+        assert_eq!(
+            Ok(Command::Show(vec![uuids[0].into()])),
+            Command::Filtered(work(), Box::new(Command::Show(refs))).resolve(&m, &scope)
+        );
+        assert_eq!(
+            Err(CommandError::FindTask(FindTaskError::TaskNotFound)),
+            Command::Filtered(work(), Box::new(Command::Stop(vec!["2".parse().unwrap()]))).effects(&m, &scope)
+        );
+
+        let changes = Command::Filtered(work(), Box::new(Command::MarkCanceled(vec![])))
+            .effects(&m, &scope)
+            .unwrap();
+        assert_eq!(2, changes.effects.len());
+        assert_eq!(Ok(Command::Tui), Command::Tui.resolve(&m, &scope));
+    }
+
+    #[test]
+    fn test_validation() {
+        let m = Model::new();
+        let scope = Scope(None);
+        let invalid = |command: Command| match command.effects(&m, &scope) {
+            Err(CommandError::Invalid(_)) => true,
+            _ => false,
+        };
+
+        assert!(invalid(Command::Add("  ".into(), vec![])));
+        assert!(invalid(Command::Add("foo".into(), vec![Flag::TagPositive("two words".into())])));
+        assert!(invalid(Command::Add("foo".into(), vec![Flag::TagPositive("ACTIVE".into())])));
+        assert!(!invalid(Command::Add("foo".into(), vec![Flag::TagNegative("ACTIVE".into())])));
+
+        let mut tags = Tags::new();
+        tags.insert("".into());
+        assert!(invalid(Command::ChangeTaskProperties {
+            task_refs: vec![],
+            added_tags: tags,
+            removed_tags: Tags::new(),
+            priority: None,
+            dates: Default::default(),
+        }));
+    }
+}
@@ -1,14 +1,67 @@
-use task::{Tag, Task, Priority};
+use task::{Tag, Task, TaskDate, TaskState, Time, Priority};
+use date;
+use Model;
 
+use chrono::Local;
 use std::str::FromStr;
 use std::fmt;
 use regex::Regex;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Flag {
     Priority(Priority),
     TagPositive(Tag),
     TagNegative(Tag),
+    // `None` removes the date
+    Date(TaskDate, Option<Time>),
+    Status(Status),
+}
+
+/// Selects tasks by their state, regardless of when it was entered.
+/// Lists only show open tasks unless a status is given.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Open,
+    Done,
+    Canceled,
+    Any,
+}
+
+impl Status {
+    pub fn matches(&self, state: &TaskState) -> bool {
+        match (*self, *state) {
+            (Status::Any, _)
+            | (Status::Open, TaskState::Open)
+            | (Status::Done, TaskState::Done(_))
+            | (Status::Canceled, TaskState::Canceled(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "open" | "pending" => Ok(Status::Open),
+            "done" | "completed" => Ok(Status::Done),
+            "canceled" | "cancelled" | "deleted" => Ok(Status::Canceled),
+            "any" | "all" => Ok(Status::Any),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Status::Open => f.write_str("open"),
+            Status::Done => f.write_str("done"),
+            Status::Canceled => f.write_str("canceled"),
+            Status::Any => f.write_str("any"),
+        }
+    }
 }
 
 impl Flag {
@@ -17,6 +70,8 @@ impl Flag {
             static ref PRIORITY_RE: Regex = Regex::new("^p(?:riority)?:(.+)$").unwrap();
             static ref TAG_POS_RE: Regex = Regex::new("^\\+(.+)$").unwrap();
             static ref TAG_NEG_RE: Regex = Regex::new("^-(.+)$").unwrap();
+            static ref DATE_RE: Regex = Regex::new("^(wait|sched(?:uled)?|due):(.*)$").unwrap();
+            static ref STATUS_RE: Regex = Regex::new("^status:(.+)$").unwrap();
         }
 
         let s = s.as_ref();
@@ -43,15 +98,38 @@ impl Flag {
             .map(String::from)
             .map(Flag::TagNegative);
 
-        priority.or(pos_tag).or(neg_tag)
+        let task_date = DATE_RE.captures(s).and_then(|cs| {
+            let kind = match &cs[1] {
+                "wait" => TaskDate::Wait,
+                "due" => TaskDate::Due,
+                _ => TaskDate::Scheduled,
+            };
+            match &cs[2] {
+                "" | "none" => Some(Flag::Date(kind, None)),
+                d => date::parse_date(d).map(|d| Flag::Date(kind, Some(d))),
+            }
+        });
+
+        let status = STATUS_RE
+            .captures(s)
+            .and_then(|cs| Status::from_str(&cs[1]).ok())
+            .map(Flag::Status);
+
+        priority.or(task_date).or(status).or(pos_tag).or(neg_tag)
     }
 
     pub fn matches(&self, t: &Task) -> bool {
         use self::Flag::*;
         match *self {
             Priority(p) => t.priority == p,
-            TagPositive(ref tag) => t.tags.contains(tag),
-            TagNegative(ref tag) => !t.tags.contains(tag),
+            TagPositive(ref tag) => t.has_tag(tag),
+            TagNegative(ref tag) => !t.has_tag(tag),
+            // Dates match if they fall on the same day
+            Date(kind, date) => {
+                let day = |d: Time| d.with_timezone(&Local).date();
+                t.date(kind).map(&day) == date.map(&day)
+            }
+            Status(status) => status.matches(&t.status),
         }
     }
 
@@ -67,6 +145,11 @@ impl Flag {
             TagNegative(ref tag) => {
                 t.tags.remove(tag);
             }
+            Date(kind, date) => {
+                t.set_date(kind, date);
+            }
+            // New tasks are always open
+            Status(_) => (),
         }
     }
 }
@@ -78,6 +161,118 @@ impl fmt::Display for Flag {
             Priority(ref p) => write!(f, "priority:{}", p),
             TagPositive(ref t) => write!(f, "+{}", t),
             TagNegative(ref t) => write!(f, "-{}", t),
+            Date(kind, None) => write!(f, "{}:none", kind),
+            Date(kind, Some(ref d)) => write!(
+                f,
+                "{}:{}",
+                kind,
+                d.with_timezone(&Local).format("%Y-%m-%dT%H:%M")
+            ),
+            Status(status) => write!(f, "status:{}", status),
         }
     }
 }
+
+/// The tasks matching all `flags`, ordered by urgency
+pub fn filter_tasks<'a>(model: &'a Model, flags: &[Flag]) -> Vec<&'a Task> {
+    // Waiting tasks are hidden unless explicitly asked for, by the tag
+    // or a wait date
+    let show_waiting = flags.iter().any(|f| match *f {
+        Flag::TagPositive(ref t) => t == "WAITING",
+        Flag::Date(TaskDate::Wait, Some(_)) => true,
+        _ => false,
+    });
+
+    // Only open tasks are shown unless a status is given
+    let any_status = flags.iter().any(|f| match *f {
+        Flag::Status(_) => true,
+        _ => false,
+    });
+
+    model
+        .all_tasks()
+        .into_iter()
+        .filter(|t| any_status || t.is_open())
+        .filter(|t| show_waiting || !t.is_waiting())
+        .filter(|t| flags.is_empty() || flags.iter().all(|f| f.matches(&t)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_flags() {
+        let tomorrow = date::parse_date("tomorrow").unwrap();
+        assert_eq!(
+            Flag::from_str("wait:tomorrow"),
+            Some(Flag::Date(TaskDate::Wait, Some(tomorrow)))
+        );
+        assert_eq!(
+            Flag::from_str("sched:tomorrow"),
+            Some(Flag::Date(TaskDate::Scheduled, Some(tomorrow)))
+        );
+        assert_eq!(
+            Flag::from_str("scheduled:none"),
+            Some(Flag::Date(TaskDate::Scheduled, None))
+        );
+        assert_eq!(
+            Flag::from_str("due:tomorrow"),
+            Some(Flag::Date(TaskDate::Due, Some(tomorrow)))
+        );
+        assert_eq!(Flag::from_str("wait:someday"), None);
+
+        // Display output can be parsed again
+        let flag = Flag::Date(TaskDate::Wait, Some(tomorrow));
+        assert_eq!(Flag::from_str(flag.to_string()), Some(flag));
+    }
+
+    #[test]
+    fn test_date_flag_matches() {
+        let mut t = Task::new("foo");
+        let tomorrow = date::parse_date("tomorrow").unwrap();
+        assert!(Flag::Date(TaskDate::Wait, None).matches(&t));
+
+        Flag::Date(TaskDate::Wait, Some(tomorrow)).apply_to(&mut t);
+        assert_eq!(t.wait, Some(tomorrow));
+        assert!(Flag::from_str("wait:tomorrow").unwrap().matches(&t));
+        assert!(!Flag::from_str("wait:today").unwrap().matches(&t));
+        assert!(!Flag::Date(TaskDate::Wait, None).matches(&t));
+    }
+
+    #[test]
+    fn test_filter_waiting() {
+        let mut model = Model::new();
+        let tomorrow = date::parse_date("tomorrow").unwrap();
+        let mut waiting = Task::new("waiting");
+        waiting.wait = Some(tomorrow);
+        model.apply_effect(&::Effect::AddTask(Task::new("open")));
+        model.apply_effect(&::Effect::AddTask(waiting.clone()));
+
+        let descriptions = |flags: &[Flag]| {
+            filter_tasks(&model, flags)
+                .iter()
+                .map(|t| t.description.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["open"], descriptions(&[]));
+        assert_eq!(vec!["waiting"], descriptions(&[Flag::TagPositive("WAITING".into())]));
+        assert_eq!(vec!["waiting"], descriptions(&[Flag::Date(TaskDate::Wait, Some(tomorrow))]));
+        assert_eq!(vec!["open"], descriptions(&[Flag::Date(TaskDate::Wait, None)]));
+    }
+
+    #[test]
+    fn test_status_flag() {
+        let mut t = Task::new("foo");
+        assert_eq!(Flag::from_str("status:done"), Some(Flag::Status(Status::Done)));
+        assert_eq!(Flag::from_str("status:foo"), None);
+
+        assert!(Flag::Status(Status::Open).matches(&t));
+        assert!(!Flag::Status(Status::Done).matches(&t));
+
+        t.status = TaskState::Canceled(::chrono::Utc::now());
+        assert!(Flag::from_str("status:deleted").unwrap().matches(&t));
+        assert!(Flag::from_str("status:any").unwrap().matches(&t));
+    }
+}
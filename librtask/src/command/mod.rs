@@ -1,11 +1,22 @@
+use column::Column;
+use formats::Format;
+use group::Grouping;
+use history::AsOf;
+use sort::SortSpec;
 use task::*;
-use task_ref::{TaskRef, TaskRefError, TaskRefs};
+use task_ref::{TaskRefError, TaskRefs};
 
+use std::collections::BTreeMap;
+use std::env;
+use std::ops::Range;
 use std::str::FromStr;
-use std::{env, fmt};
 
+mod effects;
 mod flag;
+mod parse;
+pub use self::effects::*;
 pub use self::flag::*;
+pub use self::parse::*;
 
 // TODO: Use a proper enum
 #[derive(Debug, PartialEq, Eq, Fail)]
@@ -18,14 +29,100 @@ impl From<TaskRefError> for ParseError {
     }
 }
 
+/// Options controlling how `Command::List` displays tasks, as
+/// opposed to the `Flag`s that select them
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct ListOptions {
+    /// Show the task list as it was at some point in the past
+    pub as_of: Option<AsOf>,
+    /// The columns to show, the defaults if empty
+    pub columns: Vec<Column>,
+    /// Print comma separated values instead of a table
+    pub csv: bool,
+    /// Joins the tags of a task, `,` if unset
+    pub tag_separator: Option<String>,
+    /// Sort by urgency if unset
+    pub sort: Option<SortSpec>,
+    /// Show at most this many tasks
+    pub limit: Option<usize>,
+    /// Show the n-th page of `limit` tasks, starting at 1
+    pub page: Option<usize>,
+    /// Show all tasks, regardless of any limit
+    pub all: bool,
+    /// Split the list into sections
+    pub group: Option<Grouping>,
+}
+
+impl ListOptions {
+    /// Applies a `key:value` option given among the flags, like
+    /// `sort:due+`. Returns false if `arg` isn't such an option.
+    pub fn parse_arg(&mut self, arg: &str) -> Result<bool, ParseError> {
+        if arg.starts_with("sort:") {
+            let sort = SortSpec::from_str(&arg[5..]).map_err(|e| ParseError(format!("{}", e)))?;
+            self.sort = Some(sort);
+            return Ok(true);
+        }
+        if arg.starts_with("group:") {
+            let group = Grouping::from_str(&arg[6..]).map_err(|e| ParseError(format!("{}", e)))?;
+            self.group = Some(group);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// The part of `total` tasks to show. Without a limit pages have
+    /// `page_size` tasks, `None` shows everything.
+    pub fn visible_range(&self, total: usize, page_size: Option<usize>) -> Range<usize> {
+        let size = match self.limit.or(page_size) {
+            Some(size) if !self.all => size,
+            _ => return 0..total,
+        };
+
+        let page = self.page.unwrap_or(1).max(1);
+        let start = size.saturating_mul(page - 1).min(total);
+        start..start.saturating_add(size).min(total)
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum Command {
-    List(Vec<Flag>),
+    List(Vec<Flag>, ListOptions),
     Show(TaskRefs),
     Add(Title, Vec<Flag>),
     MarkDone(TaskRefs),
     MarkCanceled(TaskRefs),
     Delete(TaskRefs),
+    History(TaskRefs),
+    Start {
+        task_refs: TaskRefs,
+        // Stop all other active tasks
+        stop_others: bool,
+    },
+    Stop(TaskRefs),
+    // Sum up time spent on tasks since the given time
+    Timesheet(Time),
+    Import {
+        format: Format,
+        path: String,
+    },
+    Export {
+        format: Format,
+        flags: Vec<Flag>,
+        // Write to this file instead of stdout
+        output: Option<String>,
+    },
+    // Show all changes made after the given time
+    Log(Option<Time>),
+    // Full-screen interactive task list
+    Tui,
+    // Serve commands from memory on a socket, or stop doing so
+    Daemon { stop: bool },
+    // Print the completion script for a shell
+    Completions(String),
+    // Print what the last of the given arguments could be completed to
+    CompletionCandidates(Vec<String>),
+    // Runs the command on the tasks matching the flags as well
+    Filtered(Vec<Flag>, Box<Command>),
     // This Command is used to apply multiple state changes coming from
     // a set of CLI flags (`Flag`)
     ChangeTaskProperties {
@@ -33,6 +130,8 @@ pub enum Command {
         added_tags: Tags,
         removed_tags: Tags,
         priority: Option<Priority>,
+        // `None` removes the date
+        dates: BTreeMap<TaskDate, Option<Time>>,
     },
 }
 
@@ -41,183 +140,4 @@ impl Command {
         let args: Vec<String> = env::args().skip(1).collect();
         Self::from_slice(&args)
     }
-
-    fn from_slice<S: fmt::Debug + AsRef<str>>(args: &[S]) -> Result<Self, ParseError> {
-        let task_refs = args.iter()
-            .take_while(|s| TaskRef::from_str(s.as_ref()).is_ok())
-            .map(|s| TaskRef::from_str(s.as_ref()).unwrap())
-            .collect::<Vec<TaskRef>>();
-
-        debug!("Got task_refs: {:?}", task_refs);
-
-        let args = args.into_iter().skip(task_refs.len()).collect::<Vec<_>>();
-
-        if !task_refs.is_empty() {
-            match args.get(0).map(|s| s.as_ref()) {
-                None => Ok(Command::Show(task_refs)),
-                Some("show") => Ok(Command::Show(task_refs)),
-                Some("done") => Ok(Command::MarkDone(task_refs)),
-                Some("cancel") => Ok(Command::MarkCanceled(task_refs)),
-                Some("delete") => Ok(Command::Delete(task_refs)),
-                Some("edit") => {
-                    // Parse 'args' as flags
-                    let flags = args.iter()
-                        .skip(1)
-                        .map(Flag::from_str)
-                        .collect::<Vec<Option<Flag>>>();
-
-                    if !flags.is_empty() {
-                        let mut added = Tags::new();
-                        let mut removed = Tags::new();
-                        let mut priority = None;
-
-                        info!("Got flags for tasks {:?}: {:?}", task_refs, flags);
-                        for flag in flags.into_iter() {
-                            match flag {
-                                None => {
-                                    return Err(ParseError(format!("Invalid flag set '{:?}'", args)))
-                                }
-                                Some(Flag::Priority(p)) => priority = Some(p),
-                                Some(Flag::TagPositive(t)) => {
-                                    added.insert(t);
-                                }
-                                Some(Flag::TagNegative(t)) => {
-                                    removed.insert(t);
-                                }
-                            }
-                        }
-
-                        Ok(Command::ChangeTaskProperties {
-                            task_refs: task_refs,
-                            added_tags: added,
-                            removed_tags: removed,
-                            priority: priority,
-                        })
-                    } else {
-                        Err(ParseError(format!(
-                            "Got no changes for task(s) {:?}",
-                            task_refs
-                        )))
-                    }
-                }
-                Some(cmd) => Err(ParseError(format!("Unknown command {}", cmd))),
-            }
-        } else {
-            match args.get(0).map(|s| s.as_ref()) {
-                Some("add") => {
-                    // TODO: Get rid of all this pesky cloning
-                    let params = &args[1..];
-
-                    let flags = params.iter().flat_map(|s| Flag::from_str(&s)).collect();
-
-                    let title = params.iter()
-            .filter(|p| Flag::from_str(p).is_none()) // Ugh
-            .fold(String::new(), |acc, arg| acc + " " + arg.as_ref())
-            .trim()
-            .to_string();
-
-                    if title != "" {
-                        debug!("title: {:?}, flags: {:?}", title, flags);
-
-                        Ok(Command::Add(title, flags))
-                    } else {
-                        Err(ParseError("Failed to parse parameters".into()))
-                    }
-                }
-                None | Some("list") => (if args.get(0).map(|s| s.as_ref()) == Some("list") {
-                    &args[1..]
-                } else {
-                    &args[..]
-                }).iter()
-                    .map(Flag::from_str)
-                    .collect::<Option<Vec<Flag>>>()
-                    .map(Command::List)
-                    .ok_or_else(|| ParseError("Found invalid flags".into())),
-                _ => panic!("Unknown command {:?}", args[0]),
-            }
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_list() {
-        let c = Command::from_slice(&["list"]);
-        assert_eq!(c, Ok(Command::List(Default::default())));
-
-        let c = Command::from_slice(&["list", "+foo"]);
-        assert_eq!(c, Ok(Command::List(vec![Flag::TagPositive("foo".into())])));
-
-        let c = Command::from_slice(&["list", "-foo"]);
-        assert_eq!(c, Ok(Command::List(vec![Flag::TagNegative("foo".into())])));
-
-        let c = Command::from_slice(&["list", "+foo", "-bar", "p:h"]);
-        assert_eq!(
-            c,
-            Ok(Command::List(vec![
-                Flag::TagPositive("foo".into()),
-                Flag::TagNegative("bar".into()),
-                Flag::Priority(Priority::High),
-            ]))
-        );
-
-        assert!(Command::from_slice(&["list", "unimplemented"]).is_err());
-    }
-
-    #[test]
-    #[ignore]
-    fn test_show() {
-        unimplemented!()
-        // let c = Command::from_slice(&vec!["show", "foo"]);
-        // assert_eq!(c, Some(Command::Show("foo".into())));
-
-        // let c = Command::from_slice(&vec!["show"]);
-        // assert_eq!(c, None);
-
-        // let c = Command::from_slice(&vec!["show", "asdfsafd"]);
-        // assert_eq!(c, Some(Command::Show("asdfsafd".into())));
-    }
-
-    #[test]
-    fn test_add() {
-        let c = Command::from_slice(&vec!["add", "foo"]);
-        assert_eq!(c, Ok(Command::Add("foo".into(), vec![])));
-
-        let c = Command::from_slice(&vec!["add", "foo", "bar"]);
-        assert_eq!(c, Ok(Command::Add("foo bar".into(), vec![])));
-    }
-
-    #[test]
-    fn test_tag_flag_semantics() {
-        let params = vec![
-            "add",
-            "+foo",
-            "my title containing +42",
-            "priority:h",
-            "+42 foo",
-        ];
-        if let Command::Add(title, flags) = Command::from_slice(&params).unwrap() {
-            assert_eq!(title, "my title containing +42");
-            assert_eq!(
-                flags,
-                vec![
-                    Flag::TagPositive("foo".into()),
-                    Flag::Priority(Priority::High),
-                    Flag::TagPositive("42 foo".into()),
-                ]
-            );
-        } else {
-            assert!(false, "Command parsing failed");
-        }
-    }
-
-    #[test]
-    fn test_default() {
-        let empty: [&'static str; 0] = [];
-        let c = Command::from_slice(&empty);
-        assert_eq!(c, Ok(Command::List(Default::default())));
-    }
 }
@@ -0,0 +1,925 @@
+//! The command line grammar, shared by all frontends:
+//!
+//! ```text
+//! rtask [<filter>...] [<command> [<argument>...]]
+//! ```
+//!
+//! The filter consists of task ids and flags like `+work` or `pri:h`.
+//! Commands working on tasks take the tasks with the given ids that
+//! match the flags, or all tasks matching the flags if there are no
+//! ids, e.g. `rtask +work done`. Ids and flags can also follow these
+//! commands, like in `rtask done 1 2`.
+//!
+//! Without a command the tasks with the given ids are shown, otherwise
+//! the matching tasks are listed. Reports can be used like commands,
+//! `rtask +work completed` lists the done tasks tagged `work`.
+
+use column::Column;
+use config::Config;
+use date;
+use formats::Format;
+use history::AsOf;
+use report::{Report, DEFAULT_REPORT};
+use task::*;
+use task_ref::{TaskRef, TaskRefs};
+
+use chrono;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use super::{Command, Flag, ListOptions, ParseError};
+
+/// The names of all commands, they take precedence over reports
+pub const COMMANDS: &'static [&'static str] = &[
+    "add",
+    "list",
+    "show",
+    "edit",
+    "modify",
+    "done",
+    "cancel",
+    "delete",
+    "history",
+    "start",
+    "stop",
+    "timesheet",
+    "import",
+    "export",
+    "log",
+    "tui",
+    "daemon",
+    "completions",
+];
+
+/// The tasks a command works on
+#[derive(Debug, Default)]
+struct Selection {
+    refs: TaskRefs,
+    flags: Vec<Flag>,
+}
+
+impl Selection {
+    fn is_empty(&self) -> bool {
+        self.refs.is_empty() && self.flags.is_empty()
+    }
+
+    /// Adds `arg` if it's a task id or flag, returns false otherwise
+    fn push(&mut self, arg: &str) -> bool {
+        // `--all` would be a negative tag otherwise
+        if arg.starts_with("--") {
+            return false;
+        }
+
+        if let Ok(task_ref) = TaskRef::from_str(arg) {
+            self.refs.push(task_ref);
+        } else if let Some(flag) = Flag::from_str(arg) {
+            self.flags.push(flag);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// The command `name` on the selected tasks, which is filtered if
+    /// flags were given
+    fn command<F>(self, name: &str, command: F) -> Result<Command, ParseError>
+    where
+        F: FnOnce(TaskRefs) -> Command,
+    {
+        if self.is_empty() {
+            return Err(ParseError(format!("{} needs task ids or a filter", name)));
+        }
+
+        let command = command(self.refs);
+        if self.flags.is_empty() {
+            Ok(command)
+        } else {
+            Ok(Command::Filtered(self.flags, Box::new(command)))
+        }
+    }
+
+    fn expect_empty(&self, name: &str) -> Result<(), ParseError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseError(format!("{} doesn't take task ids or a filter", name)))
+        }
+    }
+}
+
+impl Command {
+    /// Parses a command line without the program name, like
+    /// `["+work", "done"]`. Reports are looked up in `config`.
+    pub fn parse<S: AsRef<str>>(args: &[S], config: &Config) -> Result<Self, ParseError> {
+        let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
+
+        let mut selection = Selection::default();
+        let n_filter = args.iter().take_while(|arg| selection.push(arg)).count();
+        debug!("Got selection: {:?}", selection);
+
+        let (name, params) = match args.get(n_filter) {
+            Some(name) => (*name, &args[n_filter + 1..]),
+            None if !selection.refs.is_empty() => return selection.command("show", Command::Show),
+            None => return parse_list(default_report(config), selection, &[]),
+        };
+
+        match name {
+            "add" => {
+                selection.expect_empty(name)?;
+                parse_add(params)
+            }
+            "list" => {
+                let mut params = params.to_vec();
+                let report = match take_value(&mut params, "--report")? {
+                    Some(report) => config
+                        .report(report)
+                        .ok_or_else(|| ParseError(format!("Unknown report {:?}", report)))?,
+                    None => default_report(config),
+                };
+                parse_list(report, selection, &params)
+            }
+            "show" | "done" | "cancel" | "delete" | "history" | "start" | "stop" => {
+                let mut stop_others = false;
+                for param in params {
+                    if name == "start" && *param == "--stop-others" {
+                        stop_others = true;
+                    } else if !selection.push(param) {
+                        return Err(unexpected(param));
+                    }
+                }
+
+                selection.command(name, |refs| match name {
+                    "show" => Command::Show(refs),
+                    "done" => Command::MarkDone(refs),
+                    "cancel" => Command::MarkCanceled(refs),
+                    "delete" => Command::Delete(refs),
+                    "history" => Command::History(refs),
+                    "start" => Command::Start {
+                        task_refs: refs,
+                        stop_others: stop_others,
+                    },
+                    _ => Command::Stop(refs),
+                })
+            }
+            "edit" | "modify" => {
+                let n_refs = params
+                    .iter()
+                    .take_while(|p| TaskRef::from_str(p).is_ok())
+                    .count();
+                for param in &params[..n_refs] {
+                    selection.refs.push(TaskRef::from_str(param)?);
+                }
+
+                let (added_tags, removed_tags, priority, dates) = parse_changes(&params[n_refs..])?;
+                selection.command(name, |refs| Command::ChangeTaskProperties {
+                    task_refs: refs,
+                    added_tags: added_tags,
+                    removed_tags: removed_tags,
+                    priority: priority,
+                    dates: dates,
+                })
+            }
+            "export" => {
+                if !selection.refs.is_empty() {
+                    return Err(ParseError("export takes a filter, but no task ids".into()));
+                }
+                let (format, mut flags, output) = parse_export(params)?;
+                selection.flags.append(&mut flags);
+                Ok(Command::Export {
+                    format: format,
+                    flags: selection.flags,
+                    output: output,
+                })
+            }
+            "timesheet" | "import" | "log" | "tui" | "daemon" | "completions" => {
+                selection.expect_empty(name)?;
+                parse_without_selection(name, params)
+            }
+            // `rtask +work --limit 5` and `rtask sort:due`
+            name if name.starts_with("--") || ListOptions::default().parse_arg(name) != Ok(false) => {
+                parse_list(default_report(config), selection, &args[n_filter..])
+            }
+            name => match config.report(name) {
+                Some(report) => parse_list(report, selection, params),
+                None => Err(ParseError(format!("Unknown command {:?}", name))),
+            },
+        }
+    }
+
+    /// Parses a command line with the default configuration
+    pub fn from_slice<S: AsRef<str>>(args: &[S]) -> Result<Self, ParseError> {
+        Self::parse(args, &Config::default())
+    }
+}
+
+fn unexpected(arg: &str) -> ParseError {
+    ParseError(format!("Unexpected argument {:?}", arg))
+}
+
+fn default_report(config: &Config) -> Report {
+    config
+        .report(DEFAULT_REPORT)
+        .expect("The default report is built in")
+}
+
+fn parse_add(params: &[&str]) -> Result<Command, ParseError> {
+    let flags = params.iter().flat_map(Flag::from_str).collect();
+
+    let title = params
+        .iter()
+        .filter(|p| Flag::from_str(p).is_none())
+        .fold(String::new(), |acc, arg| acc + " " + arg)
+        .trim()
+        .to_string();
+
+    if title != "" {
+        debug!("title: {:?}, flags: {:?}", title, flags);
+        Ok(Command::Add(title, flags))
+    } else {
+        Err(ParseError("Tasks need a description".into()))
+    }
+}
+
+/// The changes of `edit`: added and removed tags, the priority and dates
+fn parse_changes(params: &[&str]) -> Result<(Tags, Tags, Option<Priority>, BTreeMap<TaskDate, Option<Time>>), ParseError> {
+    if params.is_empty() {
+        return Err(ParseError("Got no changes".into()));
+    }
+
+    let mut added = Tags::new();
+    let mut removed = Tags::new();
+    let mut priority = None;
+    let mut dates = BTreeMap::new();
+
+    for param in params {
+        match Flag::from_str(param) {
+            None => return Err(ParseError(format!("Invalid change {:?}", param))),
+            Some(Flag::Priority(p)) => priority = Some(p),
+            Some(Flag::TagPositive(t)) => {
+                added.insert(t);
+            }
+            Some(Flag::TagNegative(t)) => {
+                removed.insert(t);
+            }
+            Some(Flag::Date(kind, date)) => {
+                dates.insert(kind, date);
+            }
+            Some(Flag::Status(_)) => {
+                return Err(ParseError("Use done or cancel to change the status".into()))
+            }
+        }
+    }
+
+    Ok((added, removed, priority, dates))
+}
+
+/// The format, filter and output file of `export`
+fn parse_export(params: &[&str]) -> Result<(Format, Vec<Flag>, Option<String>), ParseError> {
+    let mut params = params.to_vec();
+    let format = take_value(&mut params, "--format")?;
+    let output = match take_value(&mut params, "-o")? {
+        Some(output) => Some(output),
+        None => take_value(&mut params, "--output")?,
+    };
+
+    let mut flags = vec![];
+    for param in params {
+        flags.push(Flag::from_str(param).ok_or_else(|| unexpected(param))?);
+    }
+
+    let format = match format {
+        Some(f) => Format::from_str(f).map_err(|e| ParseError(format!("{}", e)))?,
+        None => output.and_then(Format::from_path).unwrap_or(Format::Json),
+    };
+
+    Ok((format, flags, output.map(String::from)))
+}
+
+/// The commands which don't work on tasks
+fn parse_without_selection(name: &str, params: &[&str]) -> Result<Command, ParseError> {
+    match (name, params) {
+        ("tui", &[]) => Ok(Command::Tui),
+        ("daemon", &[]) => Ok(Command::Daemon { stop: false }),
+        ("daemon", &["--stop"]) => Ok(Command::Daemon { stop: true }),
+        ("completions", &[shell]) => Ok(Command::Completions(shell.into())),
+        ("completions", _) => Err(ParseError("Usage: completions <shell>".into())),
+        ("import", &[format, path]) => Ok(Command::Import {
+            format: Format::from_str(format).map_err(|e| ParseError(format!("{}", e)))?,
+            path: path.into(),
+        }),
+        ("import", &[path]) => Ok(Command::Import {
+            format: Format::from_path(path).unwrap_or(Format::Json),
+            path: path.into(),
+        }),
+        ("import", _) => Err(ParseError("Usage: import [format] <file>".into())),
+        ("timesheet", &[]) | ("timesheet", &["--week"]) => {
+            Ok(Command::Timesheet(date::start_of_week(chrono::Utc::now())))
+        }
+        ("timesheet", &["--since", since]) => date::parse_date(since)
+            .map(Command::Timesheet)
+            .ok_or_else(|| ParseError(format!("Invalid date {:?}", since))),
+        ("timesheet", _) => Err(ParseError("Usage: timesheet [--week|--since <date>]".into())),
+        ("log", &[]) => Ok(Command::Log(None)),
+        ("log", &["--since", since]) => date::parse_duration(since)
+            .map(|d| Command::Log(Some(chrono::Utc::now() - d)))
+            .ok_or_else(|| ParseError(format!("Invalid duration {:?}", since))),
+        ("log", _) => Err(ParseError("Usage: log [--since <duration>]".into())),
+        (_, params) => Err(unexpected(params[0])),
+    }
+}
+
+fn parse_list(report: Report, selection: Selection, params: &[&str]) -> Result<Command, ParseError> {
+    if !selection.refs.is_empty() {
+        return Err(ParseError("Lists don't take task ids, use show".into()));
+    }
+
+    let (mut flags, options) = parse_list_params(params)?;
+    let mut filter = selection.flags;
+    filter.append(&mut flags);
+
+    let (flags, options) = report.list_command(filter, options);
+    Ok(Command::List(flags, options))
+}
+
+fn parse_list_params(params: &[&str]) -> Result<(Vec<Flag>, ListOptions), ParseError> {
+    let mut params = params.to_vec();
+
+    let mut options = ListOptions::default();
+    if let Some(as_of) = take_value(&mut params, "--as-of")? {
+        options.as_of = Some(AsOf::from_str(as_of).map_err(|e| ParseError(format!("{}", e)))?);
+    }
+    if let Some(columns) = take_value(&mut params, "--columns")? {
+        options.columns = Column::parse_list(columns).map_err(|e| ParseError(format!("{}", e)))?;
+    }
+    options.tag_separator = take_value(&mut params, "--tag-separator")?.map(String::from);
+    options.csv = take_switch(&mut params, "--csv");
+    options.limit = take_count(&mut params, "--limit")?;
+    options.page = take_count(&mut params, "--page")?;
+    options.all = take_switch(&mut params, "--all");
+
+    let mut flags = vec![];
+    for param in params {
+        if !options.parse_arg(param)? {
+            flags.push(Flag::from_str(param).ok_or_else(|| ParseError(format!("Invalid flag {:?}", param)))?);
+        }
+    }
+    Ok((flags, options))
+}
+
+/// Removes `--name value` or `--name=value` from `params`
+fn take_value<'a>(params: &mut Vec<&'a str>, name: &str) -> Result<Option<&'a str>, ParseError> {
+    let prefix = format!("{}=", name);
+    let pos = match params.iter().position(|s| *s == name || s.starts_with(&prefix)) {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
+
+    let param = params.remove(pos);
+    if param.starts_with(&prefix) {
+        Ok(Some(&param[prefix.len()..]))
+    } else if pos < params.len() {
+        Ok(Some(params.remove(pos)))
+    } else {
+        Err(ParseError(format!("Missing argument for {}", name)))
+    }
+}
+
+/// Removes `name` from `params`, returns whether it was there
+fn take_switch(params: &mut Vec<&str>, name: &str) -> bool {
+    match params.iter().position(|s| *s == name) {
+        Some(pos) => {
+            params.remove(pos);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes `name` and the positive number following it from `params`
+fn take_count(params: &mut Vec<&str>, name: &str) -> Result<Option<usize>, ParseError> {
+    match take_value(params, name)? {
+        Some(count) => match count.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Some(n)),
+            _ => Err(ParseError(format!("{} needs a positive number", name))),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::Status;
+    use group::Grouping;
+    use sort::SortSpec;
+
+    fn tag(t: &str) -> Flag {
+        Flag::TagPositive(t.into())
+    }
+
+    fn refs(ids: &[u64]) -> TaskRefs {
+        ids.iter().map(|n| TaskRef::Numerical(*n)).collect()
+    }
+
+    #[test]
+    fn test_list() {
+        let c = Command::from_slice(&["list"]);
+        assert_eq!(c, Ok(Command::List(Default::default(), Default::default())));
+
+        let c = Command::from_slice(&["list", "+foo"]);
+        assert_eq!(
+            c,
+            Ok(Command::List(vec![Flag::TagPositive("foo".into())], Default::default()))
+        );
+
+        let c = Command::from_slice(&["list", "-foo"]);
+        assert_eq!(
+            c,
+            Ok(Command::List(vec![Flag::TagNegative("foo".into())], Default::default()))
+        );
+
+        let c = Command::from_slice(&["list", "+foo", "-bar", "p:h"]);
+        assert_eq!(
+            c,
+            Ok(Command::List(
+                vec![
+                    Flag::TagPositive("foo".into()),
+                    Flag::TagNegative("bar".into()),
+                    Flag::Priority(Priority::High),
+                ],
+                Default::default()
+            ))
+        );
+
+        assert!(Command::from_slice(&["list", "unimplemented"]).is_err());
+    }
+
+    #[test]
+    fn test_list_as_of() {
+        let c = Command::from_slice(&["list", "+foo", "--as-of", "42"]);
+        assert_eq!(
+            c,
+            Ok(Command::List(
+                vec![Flag::TagPositive("foo".into())],
+                ListOptions {
+                    as_of: Some(AsOf::Effect(42)),
+                    ..Default::default()
+                }
+            ))
+        );
+
+        assert!(Command::from_slice(&["list", "--as-of"]).is_err());
+        assert!(Command::from_slice(&["list", "--as-of", "someday"]).is_err());
+    }
+
+    #[test]
+    fn test_list_csv() {
+        let c = Command::from_slice(&["list", "--csv", "--columns", "id,desc,due", "+foo", "--tag-separator", ";"]);
+        assert_eq!(
+            c,
+            Ok(Command::List(
+                vec![Flag::TagPositive("foo".into())],
+                ListOptions {
+                    columns: vec![Column::Id, Column::Description, Column::Due],
+                    csv: true,
+                    tag_separator: Some(";".into()),
+                    ..Default::default()
+                }
+            ))
+        );
+
+        assert!(Command::from_slice(&["list", "--columns", "id,bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_list_sort() {
+        let c = Command::from_slice(&["list", "sort:priority-,due+", "+foo"]);
+        assert_eq!(
+            c,
+            Ok(Command::List(
+                vec![Flag::TagPositive("foo".into())],
+                ListOptions {
+                    sort: Some(SortSpec::from_str("pri-,due").unwrap()),
+                    ..Default::default()
+                }
+            ))
+        );
+
+        // Explicit sort orders override the report's
+        match Command::from_slice(&["completed", "sort:desc"]) {
+            Ok(Command::List(_, options)) => {
+                assert_eq!(Some(SortSpec::from_str("desc").unwrap()), options.sort)
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+
+        assert!(Command::from_slice(&["list", "sort:bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_list_pages() {
+        match Command::from_slice(&["list", "--limit", "5", "--page", "2", "+foo"]) {
+            Ok(Command::List(flags, options)) => {
+                assert_eq!(vec![Flag::TagPositive("foo".into())], flags);
+                assert_eq!(Some(5), options.limit);
+                assert_eq!(Some(2), options.page);
+                assert_eq!(5..10, options.visible_range(12, None));
+                // An explicit limit wins over the terminal's page size
+                assert_eq!(5..10, options.visible_range(12, Some(20)));
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+
+        match Command::from_slice(&["completed", "--all"]) {
+            Ok(Command::List(_, options)) => {
+                assert!(options.all);
+                assert_eq!(0..12, options.visible_range(12, Some(3)));
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+
+        let options = ListOptions {
+            page: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(0..12, options.visible_range(12, None));
+        assert_eq!(8..12, options.visible_range(12, Some(4)));
+        assert_eq!(12..12, options.visible_range(12, Some(10)));
+
+        assert!(Command::from_slice(&["list", "--limit", "0"]).is_err());
+        assert!(Command::from_slice(&["list", "--page"]).is_err());
+    }
+
+    #[test]
+    fn test_list_group() {
+        match Command::from_slice(&["list", "group:tag", "+foo"]) {
+            Ok(Command::List(flags, options)) => {
+                assert_eq!(vec![Flag::TagPositive("foo".into())], flags);
+                assert_eq!(Some(Grouping::Tag), options.group);
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+
+        assert!(Command::from_slice(&["list", "group:due"]).is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let c = Command::from_slice(&["completed", "+foo"]);
+        assert_eq!(
+            c,
+            Ok(Command::List(
+                vec![Flag::Status(Status::Done), Flag::TagPositive("foo".into())],
+                ListOptions {
+                    columns: Report::builtin("completed").unwrap().columns,
+                    sort: Report::builtin("completed").unwrap().sort,
+                    ..Default::default()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_add() {
+        let c = Command::from_slice(&vec!["add", "foo"]);
+        assert_eq!(c, Ok(Command::Add("foo".into(), vec![])));
+
+        let c = Command::from_slice(&vec!["add", "foo", "bar"]);
+        assert_eq!(c, Ok(Command::Add("foo bar".into(), vec![])));
+    }
+
+    #[test]
+    fn test_tag_flag_semantics() {
+        let params = vec![
+            "add",
+            "+foo",
+            "my title containing +42",
+            "priority:h",
+            "+42 foo",
+        ];
+        if let Command::Add(title, flags) = Command::from_slice(&params).unwrap() {
+            assert_eq!(title, "my title containing +42");
+            assert_eq!(
+                flags,
+                vec![
+                    Flag::TagPositive("foo".into()),
+                    Flag::Priority(Priority::High),
+                    Flag::TagPositive("42 foo".into()),
+                ]
+            );
+        } else {
+            assert!(false, "Command parsing failed");
+        }
+    }
+
+    #[test]
+    fn test_edit() {
+        let c = Command::from_slice(&["1", "edit", "+foo", "wait:none", "p:l"]);
+        let mut dates = BTreeMap::new();
+        dates.insert(TaskDate::Wait, None);
+        assert_eq!(
+            c,
+            Ok(Command::ChangeTaskProperties {
+                task_refs: vec![TaskRef::Numerical(1)],
+                added_tags: vec!["foo".to_string()].into_iter().collect(),
+                removed_tags: Tags::new(),
+                priority: Some(Priority::Low),
+                dates: dates,
+            })
+        );
+
+        assert!(Command::from_slice(&["1", "edit", "bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_history() {
+        let c = Command::from_slice(&["42", "history"]);
+        assert_eq!(c, Ok(Command::History(vec![TaskRef::Numerical(42)])));
+    }
+
+    #[test]
+    fn test_start_stop() {
+        assert_eq!(
+            Command::from_slice(&["1", "2", "start"]),
+            Ok(Command::Start {
+                task_refs: vec![TaskRef::Numerical(1), TaskRef::Numerical(2)],
+                stop_others: false,
+            })
+        );
+        assert_eq!(
+            Command::from_slice(&["1", "start", "--stop-others"]),
+            Ok(Command::Start {
+                task_refs: vec![TaskRef::Numerical(1)],
+                stop_others: true,
+            })
+        );
+        assert_eq!(
+            Command::from_slice(&["1", "stop"]),
+            Ok(Command::Stop(vec![TaskRef::Numerical(1)]))
+        );
+    }
+
+    #[test]
+    fn test_timesheet() {
+        let monday = date::start_of_week(chrono::Utc::now());
+        assert_eq!(
+            Command::from_slice(&["timesheet", "--week"]),
+            Ok(Command::Timesheet(monday))
+        );
+        assert!(Command::from_slice(&["timesheet", "--since", "2018-07-13"]).is_ok());
+        assert!(Command::from_slice(&["timesheet", "--since", "foo"]).is_err());
+    }
+
+    #[test]
+    fn test_import_export() {
+        assert_eq!(
+            Command::from_slice(&["import", "taskwarrior", "tasks.json"]),
+            Ok(Command::Import {
+                format: Format::TaskWarrior,
+                path: "tasks.json".into(),
+            })
+        );
+        assert_eq!(
+            Command::from_slice(&["import", "tasks.jsonl"]),
+            Ok(Command::Import {
+                format: Format::JsonLines,
+                path: "tasks.jsonl".into(),
+            })
+        );
+        assert!(Command::from_slice(&["import", "foo", "tasks.json"]).is_err());
+        assert!(Command::from_slice(&["import"]).is_err());
+
+        assert_eq!(
+            Command::from_slice(&["export", "--format", "taskwarrior", "-o", "out.json"]),
+            Ok(Command::Export {
+                format: Format::TaskWarrior,
+                flags: vec![],
+                output: Some("out.json".into()),
+            })
+        );
+        assert_eq!(
+            Command::from_slice(&["export", "+work", "p:h"]),
+            Ok(Command::Export {
+                format: Format::Json,
+                flags: vec![Flag::TagPositive("work".into()), Flag::Priority(Priority::High)],
+                output: None,
+            })
+        );
+        assert_eq!(
+            Command::from_slice(&["export", "-o", "tasks.ics"]),
+            Ok(Command::Export {
+                format: Format::ICal,
+                flags: vec![],
+                output: Some("tasks.ics".into()),
+            })
+        );
+        assert!(Command::from_slice(&["export", "--format", "foo"]).is_err());
+        assert!(Command::from_slice(&["export", "bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_log() {
+        assert_eq!(Command::from_slice(&["log"]), Ok(Command::Log(None)));
+
+        match Command::from_slice(&["log", "--since", "1w"]) {
+            Ok(Command::Log(Some(since))) => {
+                let week_ago = chrono::Utc::now() - chrono::Duration::weeks(1);
+                assert!((week_ago - since).num_seconds().abs() < 5);
+            }
+            c => assert!(false, "Unexpected result {:?}", c),
+        }
+
+        assert!(Command::from_slice(&["log", "--since", "foo"]).is_err());
+        assert!(Command::from_slice(&["log", "--since"]).is_err());
+    }
+
+    #[test]
+    fn test_default() {
+        let empty: [&'static str; 0] = [];
+        let c = Command::from_slice(&empty);
+        assert_eq!(c, Ok(Command::List(Default::default(), Default::default())));
+    }
+
+    #[test]
+    fn test_filter_then_command() {
+        assert_eq!(
+            Command::from_slice(&["+work", "done"]),
+            Ok(Command::Filtered(vec![tag("work")], Box::new(Command::MarkDone(vec![]))))
+        );
+        assert_eq!(
+            Command::from_slice(&["1", "+work", "p:h", "delete", "2"]),
+            Ok(Command::Filtered(
+                vec![tag("work"), Flag::Priority(Priority::High)],
+                Box::new(Command::Delete(refs(&[1, 2])))
+            ))
+        );
+        assert_eq!(
+            Command::from_slice(&["+work", "start", "--stop-others"]),
+            Ok(Command::Filtered(
+                vec![tag("work")],
+                Box::new(Command::Start {
+                    task_refs: vec![],
+                    stop_others: true,
+                })
+            ))
+        );
+        assert_eq!(
+            Command::from_slice(&["+work", "show"]),
+            Ok(Command::Filtered(vec![tag("work")], Box::new(Command::Show(vec![]))))
+        );
+        assert_eq!(
+            Command::from_slice(&["1", "+work"]),
+            Ok(Command::Filtered(vec![tag("work")], Box::new(Command::Show(refs(&[1])))))
+        );
+
+        assert!(Command::from_slice(&["done"]).is_err());
+        assert!(Command::from_slice(&["+work", "done", "bogus"]).is_err());
+        assert!(Command::from_slice(&["1", "start", "--stop"]).is_err());
+    }
+
+    #[test]
+    fn test_ids_after_command() {
+        assert_eq!(Command::from_slice(&["done", "1", "2"]), Ok(Command::MarkDone(refs(&[1, 2]))));
+        assert_eq!(Command::from_slice(&["1", "cancel", "2"]), Ok(Command::MarkCanceled(refs(&[1, 2]))));
+        assert_eq!(Command::from_slice(&["show", "1"]), Ok(Command::Show(refs(&[1]))));
+        assert_eq!(Command::from_slice(&["1", "2"]), Ok(Command::Show(refs(&[1, 2]))));
+        assert_eq!(
+            Command::from_slice(&["start", "--stop-others", "abcdef"]),
+            Ok(Command::Start {
+                task_refs: vec![TaskRef::ShortUUID("abcdef".into())],
+                stop_others: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_edit_forms() {
+        let change = |task_refs: TaskRefs| Command::ChangeTaskProperties {
+            task_refs: task_refs,
+            added_tags: vec!["foo".to_string()].into_iter().collect(),
+            removed_tags: vec!["bar".to_string()].into_iter().collect(),
+            priority: None,
+            dates: BTreeMap::new(),
+        };
+
+        assert_eq!(Command::from_slice(&["1", "2", "edit", "+foo", "-bar"]), Ok(change(refs(&[1, 2]))));
+        assert_eq!(Command::from_slice(&["edit", "1", "2", "+foo", "-bar"]), Ok(change(refs(&[1, 2]))));
+        assert_eq!(Command::from_slice(&["1", "modify", "+foo", "-bar"]), Ok(change(refs(&[1]))));
+        assert_eq!(
+            Command::from_slice(&["+urgent", "edit", "+foo", "-bar"]),
+            Ok(Command::Filtered(vec![tag("urgent")], Box::new(change(vec![]))))
+        );
+
+        assert_eq!(
+            Command::from_slice(&["1", "edit"]),
+            Err(ParseError("Got no changes".into()))
+        );
+        assert!(Command::from_slice(&["edit", "+foo"]).is_err());
+        assert!(Command::from_slice(&["1", "edit", "status:done"]).is_err());
+        assert!(Command::from_slice(&["1", "edit", "+foo", "2"]).is_err());
+    }
+
+    #[test]
+    fn test_filter_before_list() {
+        assert_eq!(
+            Command::from_slice(&["+work"]),
+            Ok(Command::List(vec![tag("work")], Default::default()))
+        );
+        assert_eq!(
+            Command::from_slice(&["+work", "list", "-home"]),
+            Ok(Command::List(
+                vec![tag("work"), Flag::TagNegative("home".into())],
+                Default::default()
+            ))
+        );
+
+        // List options can be given without `list`
+        match Command::from_slice(&["+work", "--limit=5", "sort:due", "-home"]) {
+            Ok(Command::List(flags, options)) => {
+                assert_eq!(vec![tag("work"), Flag::TagNegative("home".into())], flags);
+                assert_eq!(Some(5), options.limit);
+                assert_eq!(Some(SortSpec::from_str("due").unwrap()), options.sort);
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+        // `--all` isn't a negative tag
+        match Command::from_slice(&["--all"]) {
+            Ok(Command::List(flags, options)) => {
+                assert!(flags.is_empty());
+                assert!(options.all);
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+
+        assert!(Command::from_slice(&["1", "list"]).is_err());
+        assert!(Command::from_slice(&["1", "completed"]).is_err());
+    }
+
+    #[test]
+    fn test_config_reports() {
+        let config = Config::from_str(
+            r#"{"reports": {"work": {"filter": "+work", "limit": 5}, "add": {"filter": "+add"}}}"#,
+        ).unwrap();
+        let parse = |args: &[&str]| Command::parse(args, &config);
+
+        match parse(&["+urgent", "work", "p:h"]) {
+            Ok(Command::List(flags, options)) => {
+                assert_eq!(vec![tag("work"), tag("urgent"), Flag::Priority(Priority::High)], flags);
+                assert_eq!(Some(5), options.limit);
+            }
+            c => panic!("Unexpected {:?}", c),
+        }
+        assert_eq!(
+            parse(&["list", "--report", "work", "--limit", "2"]),
+            parse(&["work", "--limit", "2"])
+        );
+        assert_eq!(
+            parse(&["list", "--report=bogus"]),
+            Err(ParseError("Unknown report \"bogus\"".into()))
+        );
+
+        // Commands take precedence over reports
+        assert_eq!(parse(&["add", "foo"]), Ok(Command::Add("foo".into(), vec![])));
+        assert_eq!(
+            Command::from_slice(&["work"]),
+            Err(ParseError("Unknown command \"work\"".into()))
+        );
+    }
+
+    #[test]
+    fn test_commands_without_selection() {
+        assert_eq!(Command::from_slice(&["tui"]), Ok(Command::Tui));
+        assert_eq!(Command::from_slice(&["daemon"]), Ok(Command::Daemon { stop: false }));
+        assert_eq!(Command::from_slice(&["daemon", "--stop"]), Ok(Command::Daemon { stop: true }));
+        assert_eq!(
+            Command::from_slice(&["completions", "zsh"]),
+            Ok(Command::Completions("zsh".into()))
+        );
+        assert!(Command::from_slice(&["completions"]).is_err());
+        assert!(Command::from_slice(&["tui", "+work"]).is_err());
+
+        for args in &[
+            &["1", "add", "foo"][..],
+            &["+work", "add", "foo"],
+            &["+work", "timesheet"],
+            &["1", "log"],
+            &["1", "export"],
+            &["+work", "import", "tasks.json"],
+        ] {
+            assert!(Command::from_slice(args).is_err(), "{:?}", args);
+        }
+
+        assert_eq!(
+            Command::from_slice(&["+work", "export", "--output=tasks.ics", "p:h"]),
+            Ok(Command::Export {
+                format: Format::ICal,
+                flags: vec![tag("work"), Flag::Priority(Priority::High)],
+                output: Some("tasks.ics".into()),
+            })
+        );
+        assert_eq!(
+            Command::from_slice(&["add"]),
+            Err(ParseError("Tasks need a description".into()))
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! User configuration, read from `config.json` in the data directory.
+//!
+//! ```json
+//! {
+//!   "reports": {
+//!     "work": {
+//!       "description": "Everything for the day job",
+//!       "columns": "id,pri,due,desc",
+//!       "filter": "+work -someday",
+//!       "sort": "priority-,due+",
+//!       "limit": 20,
+//!       "group": "tag"
+//!     }
+//!   },
+//!   "theme": "light",
+//!   "pager": "less -R",
+//!   "aliases": {"t": "list +today", "bug": "add +bug priority:h"},
+//!   "colors": {"overdue": "bold red", "priority.low": "color244"}
+//! }
+//! ```
+
+use column::Column;
+use group::Grouping;
+use report::Report;
+use sort::SortSpec;
+use theme::{Theme, ThemeError};
+
+use serde_json;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const CONFIG_FILE: &'static str = "config.json";
+
+#[derive(Debug, Fail, From)]
+pub enum ConfigError {
+    #[fail(display = "IO Error: {}", _0)]
+    IO(io::Error),
+    #[fail(display = "Json Error: {}", _0)]
+    Json(serde_json::Error),
+    #[fail(display = "Invalid report {:?}: {}", _0, _1)]
+    InvalidReport(String, String),
+    #[fail(display = "Invalid theme: {}", _0)]
+    Theme(ThemeError),
+    #[fail(display = "Invalid alias {:?}: {}", _0, _1)]
+    InvalidAlias(String, String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ReportConfig {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    columns: String,
+    #[serde(default)]
+    filter: String,
+    sort: Option<String>,
+    limit: Option<usize>,
+    group: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    reports: BTreeMap<String, ReportConfig>,
+    theme: Option<String>,
+    /// Overrides single styles of the theme
+    #[serde(default)]
+    colors: BTreeMap<String, String>,
+    pager: Option<String>,
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub reports: BTreeMap<String, Report>,
+    pub theme: Theme,
+    /// Long lists are shown with this command, `$PAGER` if unset. An
+    /// empty command disables paging.
+    pub pager: Option<String>,
+    /// Words replaced by arguments on the command line, e.g. `t` by
+    /// `list +today`
+    pub aliases: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            reports: BTreeMap::new(),
+            theme: Theme::builtin("default").unwrap(),
+            pager: None,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl ReportConfig {
+    fn to_report(&self) -> Result<Report, String> {
+        Ok(Report {
+            description: self.description.clone(),
+            columns: Column::parse_list(&self.columns).map_err(|e| e.to_string())?,
+            filter: Report::parse_filter(&self.filter)?,
+            sort: match self.sort {
+                Some(ref sort) => Some(SortSpec::from_str(sort).map_err(|e| e.to_string())?),
+                None => None,
+            },
+            limit: self.limit,
+            group: match self.group {
+                Some(ref group) => Some(Grouping::from_str(group).map_err(|e| e.to_string())?),
+                None => None,
+            },
+        })
+    }
+}
+
+impl Config {
+    pub fn from_str(s: &str) -> Result<Config, ConfigError> {
+        let file: ConfigFile = serde_json::from_str(s)?;
+
+        let mut reports = BTreeMap::new();
+        for (name, report) in file.reports {
+            let report = report
+                .to_report()
+                .map_err(|e| ConfigError::InvalidReport(name.clone(), e))?;
+            reports.insert(name, report);
+        }
+
+        let mut aliases = BTreeMap::new();
+        for (name, expansion) in file.aliases {
+            let args: Vec<String> = expansion.split_whitespace().map(String::from).collect();
+            let error = if name.is_empty() || name.starts_with('-') || name.contains(char::is_whitespace) {
+                Some("Aliases have to be a single word")
+            } else if args.is_empty() {
+                Some("Aliases can't be empty")
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                return Err(ConfigError::InvalidAlias(name, error.into()));
+            }
+            aliases.insert(name, args);
+        }
+
+        let name = file.theme.as_ref().map_or("default", |t| &t[..]);
+        let theme = Theme::builtin(name)
+            .ok_or_else(|| ThemeError::UnknownTheme(name.into()))?
+            .with_styles(&file.colors)?;
+
+        Ok(Config {
+            reports: reports,
+            theme: theme,
+            pager: file.pager,
+            aliases: aliases,
+        })
+    }
+
+    /// Loads the configuration from `path`, a missing file results in
+    /// the default configuration
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Config::from_str(&contents)
+    }
+
+    /// Looks up a report, configured reports take precedence over the
+    /// built-in ones
+    pub fn report(&self, name: &str) -> Option<Report> {
+        self.reports
+            .get(name)
+            .cloned()
+            .or_else(|| Report::builtin(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::Flag;
+
+    #[test]
+    fn test_from_str() {
+        let config = Config::from_str(
+            r#"{"reports": {
+                "work": {"columns": "id,due,desc", "filter": "+work", "sort": "due-,pri-", "limit": 5, "group": "priority"},
+                "next": {"filter": "-someday"}
+            }}"#,
+        ).unwrap();
+
+        let work = config.report("work").unwrap();
+        assert_eq!(vec![Column::Id, Column::Due, Column::Description], work.columns);
+        assert_eq!(vec![Flag::TagPositive("work".into())], work.filter);
+        assert_eq!(Some(SortSpec::from_str("due-,priority-").unwrap()), work.sort);
+        assert_eq!(Some(5), work.limit);
+        assert_eq!(Some(Grouping::Priority), work.group);
+
+        // Built-in reports can be overridden
+        assert_eq!(
+            vec![Flag::TagNegative("someday".into())],
+            config.report("next").unwrap().filter
+        );
+        assert!(config.report("completed").is_some());
+        assert!(config.report("bogus").is_none());
+
+        assert!(Config::from_str(r#"{"reports": {"x": {"columns": "bogus"}}}"#).is_err());
+        assert!(Config::from_str(r#"{"reports": {"x": {"filter": "bogus"}}}"#).is_err());
+        assert!(Config::from_str(r#"{"reports": {"x": {"group": "bogus"}}}"#).is_err());
+        assert_eq!(Config::default(), Config::from_str("{}").unwrap());
+        assert_eq!(
+            Some("".to_string()),
+            Config::from_str(r#"{"pager": ""}"#).unwrap().pager
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        let config = Config::from_str(r#"{"aliases": {"bug": " add +bug  priority:h"}}"#).unwrap();
+        assert_eq!(
+            Some(&vec!["add".to_string(), "+bug".into(), "priority:h".into()]),
+            config.aliases.get("bug")
+        );
+
+        assert!(Config::from_str(r#"{"aliases": {"two words": "list"}}"#).is_err());
+        assert!(Config::from_str(r#"{"aliases": {"--help": "list"}}"#).is_err());
+        assert!(Config::from_str(r#"{"aliases": {"t": " "}}"#).is_err());
+    }
+
+    #[test]
+    fn test_theme() {
+        use theme::{Role, Style};
+
+        let config = Config::from_str(r#"{"theme": "basic", "colors": {"overdue": "bold red"}}"#).unwrap();
+        assert_eq!(Style::from_str("red bold").unwrap(), config.theme.style(Role::Overdue));
+        assert_eq!(
+            Theme::builtin("basic").unwrap().style(Role::HighPriority),
+            config.theme.style(Role::HighPriority)
+        );
+
+        assert!(Config::from_str(r#"{"theme": "bogus"}"#).is_err());
+        assert!(Config::from_str(r#"{"colors": {"bogus": "red"}}"#).is_err());
+        assert!(Config::from_str(r#"{"colors": {"header": "bogus"}}"#).is_err());
+    }
+}
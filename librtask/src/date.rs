@@ -0,0 +1,147 @@
+use chrono;
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use regex::Regex;
+use task::Time;
+
+use std::str::FromStr;
+
+/// Parses durations like `30s`, `15min`, `2h`, `3d`, `1w`, `2mo` or
+/// `1y`. Months and years are approximated as 30 and 365 days.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    lazy_static! {
+        static ref DURATION_RE: Regex = Regex::new("^(\\d+)\\s*([a-z]+)$").unwrap();
+    }
+
+    let s = s.trim().to_lowercase();
+    let captures = DURATION_RE.captures(&s)?;
+    let n = i64::from_str(&captures[1]).ok()?;
+
+    match &captures[2] {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(Duration::seconds(n)),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(Duration::minutes(n)),
+        "h" | "hour" | "hours" => Some(Duration::hours(n)),
+        "d" | "day" | "days" => Some(Duration::days(n)),
+        "w" | "week" | "weeks" => Some(Duration::weeks(n)),
+        "mo" | "month" | "months" => Some(Duration::days(30 * n)),
+        "y" | "year" | "years" => Some(Duration::days(365 * n)),
+        _ => None,
+    }
+}
+
+fn local_to_utc(dt: &NaiveDateTime) -> Option<Time> {
+    Local
+        .from_local_datetime(dt)
+        .earliest()
+        .map(|dt| dt.with_timezone(&chrono::Utc))
+}
+
+/// Parses a point in time relative to `now`. Understands RFC 3339
+/// timestamps, local dates (`2018-07-13`) and times (`2018-07-13
+/// 14:00`), `now`, `today`, `yesterday`, `tomorrow` as well as
+/// offsets like `-1w` or `+3d`.
+pub fn parse_date_relative(s: &str, now: Time) -> Option<Time> {
+    let s = s.trim();
+    let midnight = |t: Time| {
+        local_to_utc(&t.with_timezone(&Local).date().naive_local().and_hms(0, 0, 0))
+    };
+
+    match &s.to_lowercase()[..] {
+        "now" => return Some(now),
+        "today" => return midnight(now),
+        "yesterday" => return midnight(now - Duration::days(1)),
+        "tomorrow" => return midnight(now + Duration::days(1)),
+        _ => (),
+    }
+
+    if s.starts_with('+') {
+        return parse_duration(&s[1..]).map(|d| now + d);
+    }
+    if s.starts_with('-') {
+        return parse_duration(&s[1..]).map(|d| now - d);
+    }
+
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(t.with_timezone(&chrono::Utc));
+    }
+
+    for format in &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+            return local_to_utc(&dt);
+        }
+    }
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| local_to_utc(&d.and_hms(0, 0, 0)))
+}
+
+/// Midnight of the last monday before `now`, in local time
+pub fn start_of_week(now: Time) -> Time {
+    use chrono::Datelike;
+
+    let local = now.with_timezone(&Local);
+    let days = local.weekday().num_days_from_monday() as i64;
+    let monday = local.date().naive_local() - Duration::days(days);
+    local_to_utc(&monday.and_hms(0, 0, 0)).unwrap_or(now - Duration::days(days))
+}
+
+/// Formats `t` in local time for display
+pub fn format_local(t: &Time) -> String {
+    t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+pub fn parse_date(s: &str) -> Option<Time> {
+    parse_date_relative(s, chrono::Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Some(Duration::seconds(30)));
+        assert_eq!(parse_duration("15min"), Some(Duration::minutes(15)));
+        assert_eq!(parse_duration("2h"), Some(Duration::hours(2)));
+        assert_eq!(parse_duration("3d"), Some(Duration::days(3)));
+        assert_eq!(parse_duration("1w"), Some(Duration::weeks(1)));
+        assert_eq!(parse_duration("1 week"), Some(Duration::weeks(1)));
+        assert_eq!(parse_duration("2mo"), Some(Duration::days(60)));
+        assert_eq!(parse_duration("1y"), Some(Duration::days(365)));
+
+        assert_eq!(parse_duration("w"), None);
+        assert_eq!(parse_duration("1x"), None);
+        assert_eq!(parse_duration("-1d"), None);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let now = chrono::Utc::now();
+        let at = |s| parse_date_relative(s, now);
+
+        assert_eq!(at("now"), Some(now));
+        assert_eq!(at("-1w"), Some(now - Duration::weeks(1)));
+        assert_eq!(at("+3d"), Some(now + Duration::days(3)));
+        assert_eq!(
+            at("2018-07-13T12:00:00Z"),
+            Some(chrono::Utc.ymd(2018, 7, 13).and_hms(12, 0, 0))
+        );
+
+        let date = at("2018-07-13").unwrap().with_timezone(&Local);
+        assert_eq!(date.naive_local(), NaiveDate::from_ymd(2018, 7, 13).and_hms(0, 0, 0));
+
+        let time = at("2018-07-13 14:30").unwrap().with_timezone(&Local);
+        assert_eq!(time.naive_local(), NaiveDate::from_ymd(2018, 7, 13).and_hms(14, 30, 0));
+
+        let today = at("today").unwrap();
+        assert!(today <= now);
+        assert_eq!(at("yesterday"), Some(today - Duration::days(1)));
+
+        let monday = start_of_week(now);
+        assert!(monday <= now && now - monday < Duration::weeks(1));
+        assert_eq!(start_of_week(monday), monday);
+
+        assert_eq!(at("someday"), None);
+        assert_eq!(at("2018-13-01"), None);
+    }
+}
@@ -1,20 +1,41 @@
+//! Locks the rtask directory while the store is used
+
 use libc;
 
 use std::ffi::CString;
 use std::fmt::Debug;
 use std::os::unix::ffi::OsStringExt;
+use std::env;
 use std::path::Path;
-use std::path::PathBuf;
 use std::{fs, io};
 
+/// Locked by everyone using the store, the daemon holds it all the time.
+/// It's never deleted, a process waiting for the lock on a deleted file
+/// would get it while another one locks a new file.
+pub const PID_FILE: &'static str = "tasks.pid";
+
 pub struct FileLock {
     fd: libc::c_int,
-    path: PathBuf,
-    pub delete_on_drop: bool,
 }
 
 impl FileLock {
+    /// Waits until the lock is acquired
     pub fn new<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path> + Debug,
+    {
+        Self::lock(path, libc::LOCK_EX).map(|lock| lock.expect("Blocking flock returned early"))
+    }
+
+    /// Returns `None` instead of waiting if someone else holds the lock
+    pub fn try_new<P>(path: P) -> io::Result<Option<Self>>
+    where
+        P: AsRef<Path> + Debug,
+    {
+        Self::lock(path, libc::LOCK_EX | libc::LOCK_NB)
+    }
+
+    fn lock<P>(path: P, operation: libc::c_int) -> io::Result<Option<Self>>
     where
         P: AsRef<Path> + Debug,
     {
@@ -26,15 +47,21 @@ impl FileLock {
             return Err(io::Error::last_os_error());
         }
 
-        let lock_result = unsafe { libc::flock(fd, libc::LOCK_EX) };
+        let lock_result = unsafe { libc::flock(fd, operation) };
         if lock_result == -1 {
-            Err(io::Error::last_os_error())
+            let error = io::Error::last_os_error();
+            unsafe {
+                libc::close(fd);
+            }
+            if error.kind() == io::ErrorKind::WouldBlock {
+                Ok(None)
+            } else {
+                Err(error)
+            }
         } else {
-            Ok(FileLock {
+            Ok(Some(FileLock {
                 fd: fd,
-                path: path.as_ref().to_path_buf(),
-                delete_on_drop: false,
-            })
+            }))
         }
     }
 }
@@ -44,9 +71,41 @@ impl Drop for FileLock {
         unsafe {
             libc::close(self.fd);
         }
+    }
+}
 
-        if self.delete_on_drop {
-            fs::remove_file(&self.path).expect("Failed to remove pid file");
-        }
+/// Changes into the rtask directory, `~/.rtask` unless `RTASK_DIRECTORY`
+/// is set, and creates it if needed
+pub fn chdir() {
+    let dir = env::var("RTASK_DIRECTORY")
+        .map(Into::into)
+        .unwrap_or_else(|_| {
+            let mut dir = env::home_dir().expect("Couldn't get home dir");
+            dir.push(".rtask/");
+            dir
+        });
+
+    if let Err(error) = fs::create_dir_all(&dir) {
+        panic!("Couldn't create {}: {}", dir.display(), error);
+    }
+
+    let dir = dir.canonicalize().expect("Failed to get absolute path");
+    info!("Working directory: {}", dir.display());
+    env::set_current_dir(&dir).expect("Couldn't chdir");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_new() {
+        let path = env::temp_dir().join(format!("rtask-lock-{}", ::std::process::id()));
+        let lock = FileLock::new(&path).unwrap();
+        assert!(FileLock::try_new(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(FileLock::try_new(&path).unwrap().is_some());
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,434 @@
+//! iCalendar (RFC 5545) `VTODO` components.
+//!
+//! Written files contain a single `VCALENDAR` and can be subscribed to
+//! by calendar clients. Components other than `VTODO` are ignored on
+//! import. A `UID` which isn't a UUID is turned into one, components
+//! without a `UID` get one derived from their summary. Active tasks
+//! are written as `IN-PROCESS`, but the time spent on tasks isn't kept,
+//! imports leave it alone.
+
+use super::{derived_uuid, DescriptionUuids, Error, Format};
+use task::*;
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use std::io;
+
+const DATE_FORMAT: &'static str = "%Y%m%dT%H%M%SZ";
+
+// Lines are folded after this many octets, excluding the line break
+const MAX_LINE_LENGTH: usize = 75;
+
+fn format_date(t: &Time) -> String {
+    t.format(DATE_FORMAT).to_string()
+}
+
+/// Understands UTC times, floating (local) times and dates. Time zone
+/// parameters aren't supported, such times are taken as local time.
+fn parse_date(s: &str) -> Result<Time, Error> {
+    let local = |dt: NaiveDateTime| {
+        Local
+            .from_local_datetime(&dt)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+    };
+
+    NaiveDateTime::parse_from_str(s, DATE_FORMAT)
+        .ok()
+        .map(|dt| Utc.from_utc_datetime(&dt))
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S")
+                .ok()
+                .and_then(&local)
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y%m%d")
+                .ok()
+                .and_then(|d| local(d.and_hms(0, 0, 0)))
+        })
+        .ok_or_else(|| Error::Invalid(format!("Invalid date {:?}", s)))
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => (),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Splits a list value at unescaped commas and unescapes the parts
+fn unescape_list(s: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                parts.push(unescape(&s[start..i]));
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(unescape(&s[start..]));
+    parts
+}
+
+/// Writes a content line, folded so that no line exceeds 75 octets
+fn write_line(out: &mut io::Write, name: &str, value: &str) -> io::Result<()> {
+    let line = format!("{}:{}", name, value);
+    let mut start = 0;
+    let mut limit = MAX_LINE_LENGTH;
+
+    while line.len() - start > limit {
+        let mut end = start + limit;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.write_all(line[start..end].as_bytes())?;
+        out.write_all(b"\r\n ")?;
+        start = end;
+        // Continuation lines start with a space
+        limit = MAX_LINE_LENGTH - 1;
+    }
+
+    out.write_all(line[start..].as_bytes())?;
+    out.write_all(b"\r\n")
+}
+
+/// Joins folded lines and splits them into name, parameters and value
+fn content_lines(input: &str) -> Vec<(String, String)> {
+    let mut lines: Vec<String> = vec![];
+    for line in input.split('\n') {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+            }
+        } else if !line.is_empty() {
+            lines.push(line.into());
+        }
+    }
+
+    lines
+        .into_iter()
+        .filter_map(|line| {
+            // The value starts at the first colon outside of quoted
+            // parameter values
+            let mut quoted = false;
+            let colon = line.char_indices().find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c == ':' && !quoted
+            })?;
+
+            let name = line[..colon.0].split(';').next().unwrap().to_uppercase();
+            Some((name, line[colon.0 + 1..].into()))
+        })
+        .collect()
+}
+
+fn to_task(properties: &[(String, String)], uuids: &mut DescriptionUuids) -> Result<Task, Error> {
+    let mut task = Task::new("");
+    let mut status = None;
+    let mut completed = None;
+    let mut canceled = None;
+    let mut modified = None;
+    let mut has_uid = false;
+
+    for &(ref name, ref value) in properties {
+        match &name[..] {
+            "UID" => {
+                has_uid = true;
+                task.uuid = Uuid::parse_str(value).unwrap_or_else(|_| derived_uuid(Format::ICal, value));
+            }
+            "SUMMARY" => task.description = unescape(value),
+            "DESCRIPTION" => {
+                task.extras.insert(ExtraData::Notes, unescape(value));
+            }
+            "STATUS" => status = Some(value.to_uppercase()),
+            "PRIORITY" => {
+                task.priority = match value.trim().parse::<u8>() {
+                    Ok(1..=4) => Priority::High,
+                    Ok(6..=9) => Priority::Low,
+                    _ => Priority::Default,
+                }
+            }
+            "CATEGORIES" => task.tags.extend(
+                unescape_list(value)
+                    .into_iter()
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty()),
+            ),
+            "CREATED" => task.created = parse_date(value)?,
+            "LAST-MODIFIED" => modified = Some(parse_date(value)?),
+            "COMPLETED" => completed = Some(parse_date(value)?),
+            "X-RTASK-CANCELED" => canceled = Some(parse_date(value)?),
+            "X-RTASK-WAIT" => task.wait = Some(parse_date(value)?),
+            "DTSTART" => task.scheduled = Some(parse_date(value)?),
+            "DUE" => task.due = Some(parse_date(value)?),
+            _ => (),
+        }
+    }
+
+    if task.description.is_empty() {
+        return Err(Error::Invalid(format!("VTODO {} has no summary", task.uuid)));
+    }
+    if !has_uid {
+        task.uuid = uuids.next(&task.description);
+    }
+
+    task.modified = modified.unwrap_or(task.created);
+    task.status = match status.as_ref().map(|s| &s[..]) {
+        Some("COMPLETED") => TaskState::Done(completed.unwrap_or(task.modified)),
+        Some("CANCELLED") => TaskState::Canceled(canceled.unwrap_or(task.modified)),
+        _ if completed.is_some() => TaskState::Done(completed.unwrap()),
+        _ => TaskState::Open,
+    };
+
+    Ok(task)
+}
+
+fn write_task(task: &Task, out: &mut io::Write) -> io::Result<()> {
+    let status = match task.status {
+        TaskState::Open if task.is_active() => "IN-PROCESS",
+        TaskState::Open => "NEEDS-ACTION",
+        TaskState::Done(_) => "COMPLETED",
+        TaskState::Canceled(_) => "CANCELLED",
+    };
+    let priority = match task.priority {
+        Priority::High => "1",
+        Priority::Default => "5",
+        Priority::Low => "9",
+    };
+
+    write_line(out, "BEGIN", "VTODO")?;
+    write_line(out, "UID", &task.uuid.hyphenated().to_string())?;
+    write_line(out, "DTSTAMP", &format_date(&task.modified))?;
+    write_line(out, "CREATED", &format_date(&task.created))?;
+    write_line(out, "LAST-MODIFIED", &format_date(&task.modified))?;
+    write_line(out, "SUMMARY", &escape(&task.description))?;
+    if let Some(notes) = task.extras.get(&ExtraData::Notes) {
+        write_line(out, "DESCRIPTION", &escape(notes))?;
+    }
+    write_line(out, "STATUS", status)?;
+    write_line(out, "PRIORITY", priority)?;
+
+    if !task.tags.is_empty() {
+        let mut tags: Vec<String> = task.tags.iter().map(|t| escape(t)).collect();
+        tags.sort();
+        write_line(out, "CATEGORIES", &tags.join(","))?;
+    }
+
+    match task.status {
+        TaskState::Done(t) => write_line(out, "COMPLETED", &format_date(&t))?,
+        TaskState::Canceled(t) => write_line(out, "X-RTASK-CANCELED", &format_date(&t))?,
+        TaskState::Open => (),
+    }
+    if let Some(ref scheduled) = task.scheduled {
+        write_line(out, "DTSTART", &format_date(scheduled))?;
+    }
+    if let Some(ref due) = task.due {
+        write_line(out, "DUE", &format_date(due))?;
+    }
+    if let Some(ref wait) = task.wait {
+        write_line(out, "X-RTASK-WAIT", &format_date(wait))?;
+    }
+
+    write_line(out, "END", "VTODO")
+}
+
+pub fn read(input: &str) -> Result<Vec<Task>, Error> {
+    let mut tasks = vec![];
+    let mut uuids = DescriptionUuids::new(Format::ICal);
+    let mut components: Vec<String> = vec![];
+    let mut properties = vec![];
+
+    for (name, value) in content_lines(input) {
+        match &name[..] {
+            "BEGIN" => components.push(value.to_uppercase()),
+            "END" => {
+                if components.last().map(|c| &c[..]) == Some("VTODO") {
+                    tasks.push(to_task(&properties, &mut uuids)?);
+                    properties.clear();
+                }
+                components.pop();
+            }
+            // Skips the properties of nested components like alarms
+            _ if components.last().map(|c| &c[..]) == Some("VTODO") => {
+                properties.push((name, value))
+            }
+            _ => (),
+        }
+    }
+
+    if !components.is_empty() {
+        return Err(Error::Invalid(format!("Unterminated {}", components.pop().unwrap())));
+    }
+    Ok(tasks)
+}
+
+pub fn write(tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    write_line(out, "BEGIN", "VCALENDAR")?;
+    write_line(out, "VERSION", "2.0")?;
+    write_line(out, "PRODID", "-//rtask//rtask//EN")?;
+    write_line(out, "CALSCALE", "GREGORIAN")?;
+    for task in tasks {
+        write_task(task, out)?;
+    }
+    write_line(out, "END", "VCALENDAR")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+    use formats::import_effects;
+    use model::{Effect, Model};
+
+    #[test]
+    fn test_read() {
+        let input = "BEGIN:VCALENDAR\r\n\
+                     VERSION:2.0\r\n\
+                     BEGIN:VEVENT\r\n\
+                     SUMMARY:Not a task\r\n\
+                     END:VEVENT\r\n\
+                     BEGIN:VTODO\r\n\
+                     UID:1b4e28ba-2fa1-11d2-883f-0016d3cca427\r\n\
+                     CREATED:20180710T080000Z\r\n\
+                     SUMMARY:Buy milk\\, eggs and a very long list of other things that \r\n \
+                     needs folding\r\n\
+                     PRIORITY:2\r\n\
+                     CATEGORIES:shopping,home\r\n\
+                     STATUS:COMPLETED\r\n\
+                     COMPLETED:20180713T101500Z\r\n\
+                     DUE;VALUE=DATE:20180720\r\n\
+                     BEGIN:VALARM\r\n\
+                     SUMMARY:Alarm\r\n\
+                     END:VALARM\r\n\
+                     END:VTODO\r\n\
+                     END:VCALENDAR\r\n";
+
+        let tasks = read(input).unwrap();
+        assert_eq!(1, tasks.len());
+
+        let t = &tasks[0];
+        assert_eq!(
+            "Buy milk, eggs and a very long list of other things that needs folding",
+            t.description
+        );
+        assert_eq!(Utc.ymd(2018, 7, 10).and_hms(8, 0, 0), t.created);
+        assert_eq!(t.created, t.modified);
+        assert_eq!(Priority::High, t.priority);
+        assert!(t.tags.contains("shopping") && t.tags.contains("home"));
+        assert_eq!(TaskState::Done(Utc.ymd(2018, 7, 13).and_hms(10, 15, 0)), t.status);
+        assert_eq!(Some(parse_date("20180720").unwrap()), t.due);
+
+        assert!(read("BEGIN:VTODO\r\nSUMMARY:foo\r\n").is_err());
+        assert!(read("BEGIN:VTODO\r\nCREATED:yesterday\r\nEND:VTODO\r\n").is_err());
+    }
+
+    #[test]
+    fn test_derived_uids() {
+        let input = "BEGIN:VTODO\r\nUID:20180710T080000Z-42@example.com\r\nSUMMARY:foo\r\nEND:VTODO\r\n\
+                     BEGIN:VTODO\r\nSUMMARY:bar\r\nEND:VTODO\r\n\
+                     BEGIN:VTODO\r\nSUMMARY:bar\r\nEND:VTODO\r\n";
+        let uuids = |input: &str| read(input).unwrap().iter().map(|t| t.uuid).collect::<Vec<_>>();
+
+        let first = uuids(input);
+        assert_eq!(first, uuids(input));
+        assert!(first[1] != first[2]);
+        assert_eq!(first[0], uuids(&input.replace("SUMMARY:foo", "SUMMARY:baz"))[0]);
+    }
+
+    #[test]
+    fn test_write_line() {
+        let mut out = vec![];
+        write_line(&mut out, "SUMMARY", &"ä".repeat(100)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = out.split("\r\n").collect();
+        assert_eq!(vec![74, 75, 61, 0], lines.iter().map(|l| l.len()).collect::<Vec<_>>());
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(vec![("SUMMARY".to_string(), "ä".repeat(100))], content_lines(&out));
+    }
+
+    #[test]
+    fn test_escape() {
+        let s = "a;b,c\\d\ne";
+        assert_eq!("a\\;b\\,c\\\\d\\ne", escape(s));
+        assert_eq!(s, unescape(&escape(s)));
+        assert_eq!(vec!["a,b", "c"], unescape_list("a\\,b,c"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut t1 = Task::new("foo, bar; baz\nqux");
+        t1.priority = Priority::Low;
+        t1.tags.insert("a,b".into());
+        t1.tags.insert("c".into());
+        t1.due = Some(Utc.ymd(2018, 7, 20).and_hms(12, 0, 0));
+        t1.extras.insert(ExtraData::Notes, "some notes".into());
+
+        let mut t2 = Task::new("canceled");
+        t2.status = TaskState::Canceled(Utc.ymd(2018, 7, 21).and_hms(12, 0, 0));
+
+        let mut out = vec![];
+        write(&[&t1, &t2], &mut out).unwrap();
+        let tasks = read(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(2, tasks.len());
+
+        let strip = |t: &Task| {
+            let mut t = t.clone();
+            t.created = t.created.with_nanosecond(0).unwrap();
+            t.modified = t.modified.with_nanosecond(0).unwrap();
+            t
+        };
+        assert_eq!(strip(&t1), tasks[0]);
+        assert_eq!(strip(&t2), tasks[1]);
+    }
+
+    #[test]
+    fn test_import_active() {
+        let mut model = Model::new();
+        let task = Task::new("foo");
+        model.apply_effect(&Effect::AddTask(task.clone()));
+        model.apply_effect(&Effect::StartTask(task.uuid, Utc::now()));
+
+        let mut out = vec![];
+        write(&[&model.tasks[&task.uuid]], &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("STATUS:IN-PROCESS"));
+        let tasks = read(&output).unwrap();
+        assert!(import_effects(&model, tasks, Format::ICal).unwrap().is_empty());
+    }
+}
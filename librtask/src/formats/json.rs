@@ -0,0 +1,59 @@
+//! rtask's own JSON representation of `Task`, either as a single
+//! array or as one task per line.
+
+use super::Error;
+use task::Task;
+
+use serde_json;
+
+use std::io;
+
+pub fn read(input: &str) -> Result<Vec<Task>, Error> {
+    if input.trim_start().starts_with('[') {
+        Ok(serde_json::from_str(input)?)
+    } else {
+        let tasks = input
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok(tasks)
+    }
+}
+
+pub fn write(tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    serde_json::to_writer_pretty(&mut *out, tasks)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+pub fn write_lines(tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    for task in tasks {
+        serde_json::to_writer(&mut *out, task)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut t1 = Task::new("foo");
+        t1.tags.insert("bar".into());
+        let t2 = Task::new("with \"quotes\"\nand newlines");
+        let tasks = vec![&t1, &t2];
+
+        let mut out = vec![];
+        write(&tasks, &mut out).unwrap();
+        assert_eq!(vec![t1.clone(), t2.clone()], read(&String::from_utf8(out).unwrap()).unwrap());
+
+        let mut out = vec![];
+        write_lines(&tasks, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(2, out.lines().count());
+        assert_eq!(vec![t1, t2], read(&out).unwrap());
+    }
+}
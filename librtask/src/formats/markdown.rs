@@ -0,0 +1,59 @@
+//! Markdown task lists (`- [ ]`/`- [x]`), e.g. for pull requests or
+//! wikis. This is an export-only format.
+
+use super::Error;
+use task::*;
+
+use chrono::Local;
+
+use std::io;
+
+fn format_line(task: &Task) -> String {
+    let description = task.description.lines().collect::<Vec<_>>().join(" ");
+    let mut line = match task.status {
+        TaskState::Open => format!("- [ ] {}", description),
+        TaskState::Done(_) => format!("- [x] {}", description),
+        TaskState::Canceled(_) => format!("- [x] ~~{}~~", description),
+    };
+
+    match task.priority {
+        Priority::High => line.push_str(" **(high)**"),
+        Priority::Low => line.push_str(" (low)"),
+        Priority::Default => (),
+    }
+    if let Some(ref due) = task.due {
+        line.push_str(&format!(" (due {})", due.with_timezone(&Local).format("%Y-%m-%d")));
+    }
+
+    let mut tags: Vec<&Tag> = task.tags.iter().collect();
+    tags.sort();
+    for tag in tags {
+        line.push_str(&format!(" `{}`", tag));
+    }
+
+    line
+}
+
+pub fn write(tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    for task in tasks {
+        writeln!(out, "{}", format_line(task))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono;
+
+    #[test]
+    fn test_format_line() {
+        let mut t = Task::new("foo\nbar");
+        assert_eq!("- [ ] foo bar", format_line(&t));
+
+        t.priority = Priority::High;
+        t.tags.insert("work".into());
+        t.status = TaskState::Canceled(chrono::Utc::now());
+        assert_eq!("- [x] ~~foo bar~~ **(high)** `work`", format_line(&t));
+    }
+}
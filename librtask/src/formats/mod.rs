@@ -0,0 +1,328 @@
+//! Conversion of tasks from and to the formats of other tools
+
+use model::{Effect, Model};
+use task::{Task, TaskState, Time, TASK_DATES};
+
+use chrono::Duration;
+
+use serde_json;
+use uuid::{Uuid, NAMESPACE_URL};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+pub mod ical;
+pub mod json;
+pub mod markdown;
+pub mod org;
+pub mod taskwarrior;
+pub mod todotxt;
+
+#[derive(Debug, Fail, From)]
+pub enum Error {
+    #[fail(display = "IO Error: {}", _0)]
+    IO(io::Error),
+    #[fail(display = "Json Error: {}", _0)]
+    Json(serde_json::Error),
+    #[fail(display = "Invalid input: {}", _0)]
+    Invalid(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    JsonLines,
+    TaskWarrior,
+    TodoTxt,
+    ICal,
+    Org,
+    Markdown,
+}
+
+pub const FORMATS: &'static [&'static str] = &["json", "jsonl", "taskwarrior", "todotxt", "ical", "org", "markdown"];
+
+impl Format {
+    /// The resolution of timestamps in this format, if it can't
+    /// represent them exactly
+    pub fn precision(&self) -> Option<Duration> {
+        match *self {
+            Format::Json | Format::JsonLines => None,
+            Format::TaskWarrior => Some(Duration::seconds(1)),
+            Format::TodoTxt => Some(Duration::days(1)),
+            Format::ICal => Some(Duration::seconds(1)),
+            Format::Org => Some(Duration::minutes(1)),
+            Format::Markdown => None,
+        }
+    }
+
+    /// Whether the time spent on tasks is written and read back,
+    /// formats which can't keep it only mark active tasks at most
+    pub fn has_time_tracking(&self) -> bool {
+        match *self {
+            Format::Json | Format::JsonLines | Format::TaskWarrior | Format::Org => true,
+            Format::TodoTxt | Format::ICal | Format::Markdown => false,
+        }
+    }
+
+    /// Guesses the format of a file by its extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let extension = path.as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension.as_ref().map(|e| &e[..]) {
+            Some("json") => Some(Format::Json),
+            Some("jsonl") => Some(Format::JsonLines),
+            Some("txt") => Some(Format::TodoTxt),
+            Some("ics") => Some(Format::ICal),
+            Some("org") => Some(Format::Org),
+            Some("md") | Some("markdown") => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "json" => Ok(Format::Json),
+            "jsonl" | "json-lines" => Ok(Format::JsonLines),
+            "taskwarrior" | "tw" => Ok(Format::TaskWarrior),
+            "todotxt" | "todo.txt" => Ok(Format::TodoTxt),
+            "ical" | "ics" | "icalendar" => Ok(Format::ICal),
+            "org" => Ok(Format::Org),
+            "markdown" | "md" => Ok(Format::Markdown),
+            _ => Err(Error::Invalid(format!("Unknown format {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Format::Json => f.write_str("json"),
+            Format::JsonLines => f.write_str("jsonl"),
+            Format::TaskWarrior => f.write_str("taskwarrior"),
+            Format::TodoTxt => f.write_str("todotxt"),
+            Format::ICal => f.write_str("ical"),
+            Format::Org => f.write_str("org"),
+            Format::Markdown => f.write_str("markdown"),
+        }
+    }
+}
+
+/// Reads the tasks in `input`, which was last changed at `modified`.
+/// That's the closing time of tasks which are done without saying when.
+pub fn read(format: Format, input: &str, modified: Time) -> Result<Vec<Task>, Error> {
+    match format {
+        Format::Json | Format::JsonLines => json::read(input),
+        Format::TaskWarrior => taskwarrior::read(input),
+        Format::TodoTxt => todotxt::read(input, modified),
+        Format::ICal => ical::read(input),
+        Format::Org => org::read(input, modified),
+        Format::Markdown => Err(Error::Invalid("Markdown can only be exported".into())),
+    }
+}
+
+pub fn write(format: Format, tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    match format {
+        Format::Json => json::write(tasks, out),
+        Format::JsonLines => json::write_lines(tasks, out),
+        Format::TaskWarrior => taskwarrior::write(tasks, out),
+        Format::TodoTxt => todotxt::write(tasks, out),
+        Format::ICal => ical::write(tasks, out),
+        Format::Org => org::write(tasks, out),
+        Format::Markdown => markdown::write(tasks, out),
+    }
+}
+
+/// The uuid of a task read without one, `name` identifies it within
+/// `format`. Importing the same file again updates the task instead of
+/// adding it once more.
+fn derived_uuid(format: Format, name: &str) -> Uuid {
+    Uuid::new_v5(&NAMESPACE_URL, &format!("rtask:{}:{}", format, name))
+}
+
+/// Derives uuids from descriptions, tasks with the same description are
+/// told apart by their order
+struct DescriptionUuids {
+    format: Format,
+    seen: HashMap<String, usize>,
+}
+
+impl DescriptionUuids {
+    fn new(format: Format) -> Self {
+        DescriptionUuids {
+            format: format,
+            seen: HashMap::new(),
+        }
+    }
+
+    fn next(&mut self, description: &str) -> Uuid {
+        let n = self.seen.entry(description.to_string()).or_insert(0);
+        *n += 1;
+        derived_uuid(self.format, &format!("{}#{}", description, n))
+    }
+}
+
+// Takes over what `format` can't represent from `existing`: the time
+// tracking and timestamps which only differ from the ones in `task` due
+// to the limited precision of the format
+fn reconcile(existing: &Task, task: &mut Task, format: Format) {
+    if !format.has_time_tracking() {
+        task.intervals = existing.intervals.clone();
+    }
+    let precision = match format.precision() {
+        Some(precision) => precision,
+        None => return,
+    };
+    let close = |a: Time, b: Time| (a - b).num_milliseconds().abs() < precision.num_milliseconds();
+
+    match (existing.status, task.status) {
+        (TaskState::Done(a), TaskState::Done(b)) | (TaskState::Canceled(a), TaskState::Canceled(b))
+            if close(a, b) =>
+        {
+            task.status = existing.status
+        }
+        _ => (),
+    }
+
+    for kind in TASK_DATES {
+        match (existing.date(*kind), task.date(*kind)) {
+            (Some(a), Some(b)) if close(a, b) => task.set_date(*kind, Some(a)),
+            _ => (),
+        }
+    }
+
+    if let (Some(a), Some(b)) = (existing.active_since(), task.active_since()) {
+        if close(a, b) {
+            task.intervals = existing.intervals.clone();
+        }
+    }
+}
+
+/// Returns the effects needed to make `model` contain `tasks`, read
+/// from a file in `format`. Unknown tasks get added, known tasks get
+/// updated with the minimal set of effects. Importing the same file
+/// twice is therefore a no-op. Descriptions can't be changed and a task
+/// listed twice has to be the same both times.
+pub fn import_effects(model: &Model, tasks: Vec<Task>, format: Format) -> Result<Vec<Effect>, Error> {
+    let mut unique: Vec<Task> = vec![];
+    for task in tasks {
+        match unique.iter().find(|t| t.uuid == task.uuid) {
+            Some(other) if *other == task => continue,
+            Some(_) => return Err(Error::Invalid(format!("Task {} is listed twice with different contents", task.uuid))),
+            None => unique.push(task),
+        }
+    }
+
+    let mut effects = vec![];
+    for mut task in unique {
+        if let Some(existing) = model.get_task(&task.uuid) {
+            if existing.description != task.description {
+                return Err(Error::Invalid(format!(
+                    "Can't change the description of task {} from {:?} to {:?}",
+                    task.uuid, existing.description, task.description
+                )));
+            }
+            reconcile(existing, &mut task, format);
+            effects.extend(Effect::diff(existing, &task));
+            continue;
+        }
+
+        let uuid = task.uuid;
+        let status = task.status;
+        let active_since = task.active_since();
+        task.status = TaskState::Open;
+        task.intervals.retain(|i| i.end.is_some());
+
+        effects.push(Effect::AddTask(task));
+        if let Some(start) = active_since {
+            effects.push(Effect::StartTask(uuid, start));
+        }
+        if status != TaskState::Open {
+            effects.push(Effect::ChangeTaskState(uuid, status));
+        }
+    }
+
+    Ok(effects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono;
+    use task::Priority;
+
+    #[test]
+    fn test_import_effects() {
+        let mut m = Model::new();
+        let known = Task::new("known");
+        m.apply_effect(&Effect::AddTask(known.clone()));
+
+        let mut done = Task::new("done");
+        let end = chrono::Utc::now();
+        done.status = TaskState::Done(end);
+
+        let mut known_changed = known.clone();
+        known_changed.priority = Priority::High;
+
+        let effects = import_effects(&m, vec![known.clone(), done.clone(), done.clone()], Format::Json).unwrap();
+        assert_eq!(2, effects.len());
+
+        let mut added = done.clone();
+        added.status = TaskState::Open;
+        assert_eq!(Effect::AddTask(added), effects[0]);
+        assert_eq!(Effect::ChangeTaskState(done.uuid, done.status), effects[1]);
+
+        for e in effects.iter() {
+            m.apply_effect(e);
+        }
+        assert_eq!(Some(&done), m.get_task(&done.uuid));
+        assert!(import_effects(&m, vec![done.clone()], Format::Json).unwrap().is_empty());
+
+        // The same task can't be listed with different contents
+        let mut renamed = done.clone();
+        renamed.description = "renamed".into();
+        assert!(import_effects(&m, vec![done, renamed.clone()], Format::Json).is_err());
+        assert!(import_effects(&m, vec![renamed], Format::Json).is_err());
+
+        // Known tasks get updated
+        assert_eq!(
+            vec![Effect::ChangeTaskPriority(known.uuid, Priority::High)],
+            import_effects(&m, vec![known_changed], Format::Json).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_import_precision() {
+        let mut m = Model::new();
+        let mut task = Task::new("foo");
+        task.status = TaskState::Done(chrono::Utc::now());
+        m.apply_effect(&Effect::AddTask(task.clone()));
+
+        let mut coarse = task.clone();
+        coarse.status = TaskState::Done(chrono::Utc::now() - Duration::hours(3));
+
+        assert!(import_effects(&m, vec![coarse.clone()], Format::TodoTxt).unwrap().is_empty());
+        assert_eq!(
+            vec![Effect::ChangeTaskState(task.uuid, coarse.status)],
+            import_effects(&m, vec![coarse], Format::TaskWarrior).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Some(Format::Json), Format::from_path("tasks.JSON"));
+        assert_eq!(Some(Format::JsonLines), Format::from_path("/tmp/tasks.jsonl"));
+        assert_eq!(Some(Format::ICal), Format::from_path("calendar.ics"));
+        assert_eq!(Some(Format::Org), Format::from_path("~/todo.org"));
+        assert_eq!(None, Format::from_path("tasks"));
+    }
+}
@@ -0,0 +1,450 @@
+//! Org-mode outlines: one `TODO`, `DONE` or `CANCELED` headline per
+//! task, with the rtask specific data kept in property drawers and
+//! time tracking in `CLOCK` entries. Headlines without a `:UUID:`
+//! property get one derived from their title.
+
+use super::{DescriptionUuids, Error, Format};
+use task::*;
+
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use regex::Regex;
+
+use std::io;
+
+const KEYWORDS: &'static str = "#+TODO: TODO | DONE CANCELED";
+
+fn format_date(t: &Time, active: bool) -> String {
+    let date = t.with_timezone(&Local).format("%Y-%m-%d %a %H:%M");
+    if active {
+        format!("<{}>", date)
+    } else {
+        format!("[{}]", date)
+    }
+}
+
+/// Parses active and inactive timestamps, with or without a time.
+/// Repeaters and delays are ignored.
+fn parse_date(s: &str) -> Result<Time, Error> {
+    lazy_static! {
+        static ref TIMESTAMP_RE: Regex = Regex::new(
+            "^[<\\[](\\d{4}-\\d{2}-\\d{2})(?: [^ \\d>\\]]+)?(?: (\\d{1,2}:\\d{2}))?[^>\\]]*[>\\]]$"
+        ).unwrap();
+    }
+
+    let invalid = || Error::Invalid(format!("Invalid timestamp {:?}", s));
+    let captures = TIMESTAMP_RE.captures(s.trim()).ok_or_else(invalid)?;
+
+    let date = NaiveDate::parse_from_str(&captures[1], "%Y-%m-%d").map_err(|_| invalid())?;
+    let time = match captures.get(2) {
+        Some(time) => NaiveTime::parse_from_str(time.as_str(), "%H:%M").map_err(|_| invalid())?,
+        None => NaiveTime::from_hms(0, 0, 0),
+    };
+
+    Local
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(invalid)
+}
+
+fn parse_priority(s: &str) -> Priority {
+    match s {
+        "A" => Priority::High,
+        "C" => Priority::Low,
+        _ => Priority::Default,
+    }
+}
+
+fn write_task(task: &Task, out: &mut io::Write) -> io::Result<()> {
+    let keyword = match task.status {
+        TaskState::Open => "TODO",
+        TaskState::Done(_) => "DONE",
+        TaskState::Canceled(_) => "CANCELED",
+    };
+    let priority = match task.priority {
+        Priority::High => " [#A]",
+        Priority::Default => "",
+        Priority::Low => " [#C]",
+    };
+
+    let mut tags: Vec<&Tag> = task.tags.iter().collect();
+    tags.sort();
+    let tags = if tags.is_empty() {
+        String::new()
+    } else {
+        format!(" :{}:", tags.iter().map(|t| &t[..]).collect::<Vec<_>>().join(":"))
+    };
+
+    let description = task.description.lines().collect::<Vec<_>>().join(" ");
+    writeln!(out, "* {}{} {}{}", keyword, priority, description, tags)?;
+
+    let mut planning = vec![];
+    match task.status {
+        TaskState::Done(t) | TaskState::Canceled(t) => {
+            planning.push(format!("CLOSED: {}", format_date(&t, false)))
+        }
+        TaskState::Open => (),
+    }
+    if let Some(ref scheduled) = task.scheduled {
+        planning.push(format!("SCHEDULED: {}", format_date(scheduled, true)));
+    }
+    if let Some(ref due) = task.due {
+        planning.push(format!("DEADLINE: {}", format_date(due, true)));
+    }
+    if !planning.is_empty() {
+        writeln!(out, "  {}", planning.join(" "))?;
+    }
+
+    writeln!(out, "  :PROPERTIES:")?;
+    writeln!(out, "  :UUID:     {}", task.uuid.hyphenated())?;
+    writeln!(out, "  :CREATED:  {}", format_date(&task.created, false))?;
+    if let Some(ref wait) = task.wait {
+        writeln!(out, "  :WAIT:     {}", format_date(wait, false))?;
+    }
+    writeln!(out, "  :END:")?;
+
+    if !task.intervals.is_empty() {
+        writeln!(out, "  :LOGBOOK:")?;
+        for interval in task.intervals.iter().rev() {
+            match interval.end {
+                Some(end) => {
+                    let minutes = (end - interval.start).num_minutes();
+                    writeln!(
+                        out,
+                        "  CLOCK: {}--{} => {:2}:{:02}",
+                        format_date(&interval.start, false),
+                        format_date(&end, false),
+                        minutes / 60,
+                        minutes % 60
+                    )?
+                }
+                None => writeln!(out, "  CLOCK: {}", format_date(&interval.start, false))?,
+            }
+        }
+        writeln!(out, "  :END:")?;
+    }
+
+    if let Some(notes) = task.extras.get(&ExtraData::Notes) {
+        for line in notes.lines() {
+            if line.is_empty() {
+                writeln!(out)?;
+            } else {
+                writeln!(out, "  {}", line)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(PartialEq)]
+enum Drawer {
+    Properties,
+    Logbook,
+    Other,
+}
+
+// Builds up a task from the lines following its headline
+struct Entry {
+    task: Task,
+    has_uuid: bool,
+    closed: Option<Time>,
+    drawer: Option<Drawer>,
+    notes: Vec<String>,
+}
+
+impl Entry {
+    fn parse_line(&mut self, line: &str) -> Result<(), Error> {
+        lazy_static! {
+            static ref PLANNING_RE: Regex =
+                Regex::new("(CLOSED|SCHEDULED|DEADLINE): ([<\\[][^>\\]]*[>\\]])").unwrap();
+            static ref PROPERTY_RE: Regex = Regex::new("^:([^:\\s]+):\\s*(.*)$").unwrap();
+            static ref CLOCK_RE: Regex =
+                Regex::new("^CLOCK: (\\[[^\\]]*\\])(?:--(\\[[^\\]]*\\]))?").unwrap();
+        }
+
+        let trimmed = line.trim();
+        match self.drawer {
+            Some(_) if trimmed == ":END:" => self.drawer = None,
+            Some(Drawer::Properties) => {
+                if let Some(property) = PROPERTY_RE.captures(trimmed) {
+                    let value = property[2].trim();
+                    match &property[1].to_uppercase()[..] {
+                        "UUID" => {
+                            self.task.uuid = Uuid::parse_str(value)
+                                .map_err(|_| Error::Invalid(format!("Invalid UUID {:?}", value)))?;
+                            self.has_uuid = true;
+                        }
+                        "CREATED" => {
+                            self.task.created = parse_date(value)?;
+                            self.task.modified = self.task.created;
+                        }
+                        "WAIT" => self.task.wait = Some(parse_date(value)?),
+                        _ => (),
+                    }
+                }
+            }
+            Some(Drawer::Logbook) => {
+                if let Some(clock) = CLOCK_RE.captures(trimmed) {
+                    let end = match clock.get(2) {
+                        Some(end) => Some(parse_date(end.as_str())?),
+                        None => None,
+                    };
+                    self.task.intervals.insert(
+                        0,
+                        Interval {
+                            start: parse_date(&clock[1])?,
+                            end: end,
+                        },
+                    );
+                }
+            }
+            Some(Drawer::Other) => (),
+            None if trimmed == ":PROPERTIES:" => self.drawer = Some(Drawer::Properties),
+            None if trimmed == ":LOGBOOK:" => self.drawer = Some(Drawer::Logbook),
+            None if trimmed.len() > 2 && trimmed.starts_with(':') && trimmed.ends_with(':') => {
+                self.drawer = Some(Drawer::Other)
+            }
+            None if PLANNING_RE.is_match(trimmed) && self.notes.is_empty() => {
+                for planning in PLANNING_RE.captures_iter(trimmed) {
+                    let time = parse_date(&planning[2])?;
+                    match &planning[1] {
+                        "CLOSED" => self.closed = Some(time),
+                        "SCHEDULED" => self.task.scheduled = Some(time),
+                        _ => self.task.due = Some(time),
+                    }
+                }
+            }
+            None => self.notes.push(line.trim_end().into()),
+        }
+        Ok(())
+    }
+
+    /// Tasks closed without a `CLOSED` stamp are closed at the end of
+    /// their last clock, or at `modified`
+    fn finish(mut self, uuids: &mut DescriptionUuids, modified: Time) -> Task {
+        if !self.has_uuid {
+            self.task.uuid = uuids.next(&self.task.description);
+        }
+
+        let closed = self.closed
+            .or_else(|| self.task.intervals.iter().filter_map(|i| i.end).max())
+            .unwrap_or(modified);
+        self.task.status = match self.task.status {
+            TaskState::Done(_) => TaskState::Done(closed),
+            TaskState::Canceled(_) => TaskState::Canceled(closed),
+            TaskState::Open => TaskState::Open,
+        };
+
+        // Removes the indentation shared by all lines
+        let indent = self.notes
+            .iter()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.len() - l.trim_start().len())
+            .min()
+            .unwrap_or(0);
+
+        let notes = self.notes
+            .iter()
+            .map(|l| if l.is_empty() { "" } else { &l[indent..] })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let notes = notes.trim_matches('\n');
+        if !notes.is_empty() {
+            self.task.extras.insert(ExtraData::Notes, notes.into());
+        }
+        self.task
+    }
+}
+
+/// Reads all headlines with a TODO keyword, at any level. The outline
+/// was last changed at `modified`.
+pub fn read(input: &str, modified: Time) -> Result<Vec<Task>, Error> {
+    lazy_static! {
+        static ref HEADLINE_RE: Regex = Regex::new(
+            "^\\*+\\s+(?:(TODO|DONE|CANCELED|CANCELLED)\\s+)?(?:\\[#([A-Z])\\]\\s*)?(.*?)(?:\\s+(:[^\\s]+:))?\\s*$"
+        ).unwrap();
+    }
+
+    let mut tasks = vec![];
+    let mut entry: Option<Entry> = None;
+    let mut uuids = DescriptionUuids::new(Format::Org);
+
+    for line in input.lines() {
+        if !line.starts_with('*') {
+            if let Some(ref mut entry) = entry {
+                entry.parse_line(line)?;
+            }
+            continue;
+        }
+
+        tasks.extend(entry.take().map(|e| e.finish(&mut uuids, modified)));
+
+        let headline = match HEADLINE_RE.captures(line) {
+            Some(headline) => headline,
+            None => continue,
+        };
+        if headline.get(1).is_none() {
+            continue;
+        }
+
+        let mut task = Task::new(headline[3].trim());
+        if let Some(priority) = headline.get(2) {
+            task.priority = parse_priority(priority.as_str());
+        }
+        if let Some(tags) = headline.get(4) {
+            task.tags = tags
+                .as_str()
+                .split(':')
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .collect();
+        }
+
+        // The closing time is set once the entry is finished
+        task.status = match &headline[1] {
+            "DONE" => TaskState::Done(modified),
+            "CANCELED" | "CANCELLED" => TaskState::Canceled(modified),
+            _ => TaskState::Open,
+        };
+
+        entry = Some(Entry {
+            task: task,
+            has_uuid: false,
+            closed: None,
+            drawer: None,
+            notes: vec![],
+        });
+    }
+    tasks.extend(entry.map(|e| e.finish(&mut uuids, modified)));
+
+    Ok(tasks)
+}
+
+pub fn write(tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    writeln!(out, "{}", KEYWORDS)?;
+    for task in tasks {
+        write_task(task, out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    const OUTLINE: &'static str = "#+TODO: TODO | DONE CANCELED
+* Work
+** DONE [#A] Write report :work:report:
+   CLOSED: [2018-07-13 Fri 10:15] DEADLINE: <2018-07-14 Sat>
+   :PROPERTIES:
+   :UUID:     8e2d8c67-2b1f-4a4f-9c1a-5fb0b4c6c6a1
+   :CREATED:  [2018-07-10 Tue 08:00]
+   :END:
+   :LOGBOOK:
+   CLOCK: [2018-07-12 Thu 09:00]--[2018-07-12 Thu 10:30] =>  1:30
+   CLOCK: [2018-07-11 Wed 14:00]--[2018-07-11 Wed 15:00] =>  1:00
+   :END:
+   First draft is done.
+
+** Meeting notes
+   Not a task
+* TODO Buy milk
+";
+
+    #[test]
+    fn test_read() {
+        let tasks = read(OUTLINE, Utc::now()).unwrap();
+        assert_eq!(2, tasks.len());
+
+        let report = &tasks[0];
+        assert_eq!("Write report", report.description);
+        assert_eq!(
+            Uuid::parse_str("8e2d8c67-2b1f-4a4f-9c1a-5fb0b4c6c6a1").unwrap(),
+            report.uuid
+        );
+        assert_eq!(parse_date("[2018-07-10 Tue 08:00]").unwrap(), report.created);
+        assert_eq!(
+            TaskState::Done(parse_date("[2018-07-13 Fri 10:15]").unwrap()),
+            report.status
+        );
+        assert_eq!(Some(parse_date("<2018-07-14>").unwrap()), report.due);
+        assert_eq!(Priority::High, report.priority);
+        assert!(report.tags.contains("work") && report.tags.contains("report"));
+        assert_eq!(2, report.intervals.len());
+        assert!(report.intervals[0].start < report.intervals[1].start);
+        assert_eq!(
+            Some(&"First draft is done.".to_string()),
+            report.extras.get(&ExtraData::Notes)
+        );
+
+        assert_eq!("Buy milk", tasks[1].description);
+        assert_eq!(TaskState::Open, tasks[1].status);
+
+        assert!(read("* TODO foo\n  :PROPERTIES:\n  :UUID: bar\n  :END:\n", Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_read_again() {
+        let outline = "* DONE foo\n* TODO foo\n* CANCELED bar\n  :LOGBOOK:\n  \
+                       CLOCK: [2018-07-12 Thu 09:00]--[2018-07-12 Thu 10:30] =>  1:30\n  :END:\n";
+        let modified = parse_date("[2018-07-13 Fri 10:15]").unwrap();
+        let tasks = read(outline, modified).unwrap();
+
+        assert_eq!(TaskState::Done(modified), tasks[0].status);
+        assert_eq!(
+            TaskState::Canceled(parse_date("[2018-07-12 Thu 10:30]").unwrap()),
+            tasks[2].status
+        );
+        assert!(tasks[0].uuid != tasks[1].uuid);
+
+        // The same outline yields the same tasks
+        let again = read(outline, modified).unwrap();
+        let uuids = |tasks: &[Task]| tasks.iter().map(|t| t.uuid).collect::<Vec<_>>();
+        assert_eq!(uuids(&tasks), uuids(&again));
+        assert_eq!(tasks[2].status, again[2].status);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let t = parse_date("<2018-07-14 Sat 10:00 +1w>").unwrap().with_timezone(&Local);
+        assert_eq!(NaiveDate::from_ymd(2018, 7, 14).and_hms(10, 0, 0), t.naive_local());
+        assert!(parse_date("2018-07-14").is_err());
+        assert!(parse_date("[2018-13-14]").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let now = Utc::now().with_second(0).unwrap().with_nanosecond(0).unwrap();
+        let mut task = Task::new("foo");
+        task.created = now;
+        task.modified = now;
+        task.priority = Priority::Low;
+        task.scheduled = Some(now);
+        task.wait = Some(now);
+        task.tags.insert("bar".into());
+        task.extras.insert(ExtraData::Notes, "some\n\n  notes".into());
+        task.intervals = vec![
+            Interval {
+                start: now,
+                end: Some(now),
+            },
+            Interval {
+                start: now,
+                end: None,
+            },
+        ];
+
+        let mut canceled = Task::new("baz");
+        canceled.created = now;
+        canceled.modified = now;
+        canceled.status = TaskState::Canceled(now);
+
+        let mut out = vec![];
+        write(&[&task, &canceled], &mut out).unwrap();
+        let tasks = read(&String::from_utf8(out).unwrap(), Utc::now()).unwrap();
+        assert_eq!(vec![task, canceled], tasks);
+    }
+}
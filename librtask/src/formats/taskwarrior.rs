@@ -0,0 +1,256 @@
+//! TaskWarrior's JSON format as produced by `task export` and
+//! understood by `task import`.
+
+use super::Error;
+use task::*;
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use serde_json;
+
+use std::io;
+
+const DATE_FORMAT: &'static str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Annotation {
+    entry: String,
+    description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwTask {
+    uuid: String,
+    description: String,
+    status: String,
+    entry: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wait: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scheduled: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+}
+
+fn parse_date(s: &str) -> Result<Time, Error> {
+    NaiveDateTime::parse_from_str(s, DATE_FORMAT)
+        .map(|t| Utc.from_utc_datetime(&t))
+        .map_err(|_| Error::Invalid(format!("Invalid date {:?}", s)))
+}
+
+fn format_date(t: &Time) -> String {
+    t.format(DATE_FORMAT).to_string()
+}
+
+fn parse_opt_date(s: &Option<String>) -> Result<Option<Time>, Error> {
+    match *s {
+        Some(ref s) => parse_date(s).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn to_task(tw: TwTask) -> Result<Task, Error> {
+    let uuid = Uuid::parse_str(&tw.uuid)
+        .map_err(|_| Error::Invalid(format!("Invalid UUID {:?}", tw.uuid)))?;
+    let created = parse_date(&tw.entry)?;
+    let modified = parse_opt_date(&tw.modified)?.unwrap_or(created);
+    let end = parse_opt_date(&tw.end)?.unwrap_or(modified);
+
+    let status = match &tw.status[..] {
+        "pending" | "waiting" => TaskState::Open,
+        "completed" => TaskState::Done(end),
+        "deleted" => TaskState::Canceled(end),
+        s => return Err(Error::Invalid(format!("Unsupported status {:?}", s))),
+    };
+
+    let priority = match tw.priority.as_ref().map(|s| &s[..]) {
+        Some("H") => Priority::High,
+        Some("L") => Priority::Low,
+        _ => Priority::Default,
+    };
+
+    let mut extras = ExtraMap::new();
+    if !tw.annotations.is_empty() {
+        let notes = tw.annotations
+            .iter()
+            .map(|a| &a.description[..])
+            .collect::<Vec<_>>()
+            .join("\n");
+        extras.insert(ExtraData::Notes, notes);
+    }
+
+    let intervals = match parse_opt_date(&tw.start)? {
+        Some(start) => vec![Interval {
+            start: start,
+            end: None,
+        }],
+        None => vec![],
+    };
+
+    Ok(Task {
+        description: tw.description,
+        status: status,
+        priority: priority,
+        created: created,
+        modified: modified,
+        uuid: uuid,
+        tags: tw.tags.into_iter().collect(),
+        extras: extras,
+        intervals: intervals,
+        wait: parse_opt_date(&tw.wait)?,
+        scheduled: parse_opt_date(&tw.scheduled)?,
+        due: parse_opt_date(&tw.due)?,
+    })
+}
+
+fn from_task(task: &Task) -> TwTask {
+    let (status, end) = match task.status {
+        TaskState::Open if task.is_waiting() => ("waiting", None),
+        TaskState::Open => ("pending", None),
+        TaskState::Done(t) => ("completed", Some(format_date(&t))),
+        TaskState::Canceled(t) => ("deleted", Some(format_date(&t))),
+    };
+
+    let priority = match task.priority {
+        Priority::High => Some("H".into()),
+        Priority::Low => Some("L".into()),
+        Priority::Default => None,
+    };
+
+    let annotations = task.extras
+        .get(&ExtraData::Notes)
+        .map_or(vec![], |notes| {
+            notes
+                .lines()
+                .map(|line| Annotation {
+                    entry: format_date(&task.modified),
+                    description: line.into(),
+                })
+                .collect()
+        });
+
+    let mut tags: Vec<String> = task.tags.iter().cloned().collect();
+    tags.sort();
+
+    TwTask {
+        uuid: task.uuid.hyphenated().to_string(),
+        description: task.description.clone(),
+        status: status.into(),
+        entry: format_date(&task.created),
+        modified: Some(format_date(&task.modified)),
+        end: end,
+        start: task.active_since().as_ref().map(format_date),
+        wait: task.wait.as_ref().map(format_date),
+        scheduled: task.scheduled.as_ref().map(format_date),
+        due: task.due.as_ref().map(format_date),
+        tags: tags,
+        priority: priority,
+        annotations: annotations,
+    }
+}
+
+/// Reads tasks either from a JSON array or from one JSON object per
+/// line, TaskWarrior accepts both.
+pub fn read(input: &str) -> Result<Vec<Task>, Error> {
+    let tw_tasks: Vec<TwTask> = if input.trim_start().starts_with('[') {
+        serde_json::from_str(input)?
+    } else {
+        input
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut tasks = vec![];
+    for tw in tw_tasks {
+        // Templates of recurring tasks have no equivalent
+        if tw.status == "recurring" {
+            warn!("Skipping recurring task {}", tw.uuid);
+            continue;
+        }
+        tasks.push(to_task(tw)?);
+    }
+    Ok(tasks)
+}
+
+pub fn write(tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    let tw_tasks: Vec<TwTask> = tasks.iter().map(|t| from_task(t)).collect();
+    serde_json::to_writer_pretty(&mut *out, &tw_tasks)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &'static str = r#"[
+{"id":0,"description":"Write report","end":"20180713T101500Z","entry":"20180710T080000Z","modified":"20180713T101500Z","status":"completed","uuid":"8e2d8c67-2b1f-4a4f-9c1a-5fb0b4c6c6a1","tags":["work","report"],"priority":"H","annotations":[{"entry":"20180711T090000Z","description":"first draft"},{"entry":"20180712T090000Z","description":"needs figures"}],"urgency":0},
+{"id":1,"description":"Buy milk","entry":"20180712T080000Z","modified":"20180712T080000Z","status":"pending","uuid":"1b4e28ba-2fa1-11d2-883f-0016d3cca427","priority":"L","due":"20180720T000000Z","urgency":1.2}
+]"#;
+
+    #[test]
+    fn test_read() {
+        let tasks = read(EXPORT).unwrap();
+        assert_eq!(2, tasks.len());
+
+        let report = &tasks[0];
+        assert_eq!("Write report", report.description);
+        assert_eq!(
+            Uuid::parse_str("8e2d8c67-2b1f-4a4f-9c1a-5fb0b4c6c6a1").unwrap(),
+            report.uuid
+        );
+        assert_eq!(Utc.ymd(2018, 7, 10).and_hms(8, 0, 0), report.created);
+        assert_eq!(
+            TaskState::Done(Utc.ymd(2018, 7, 13).and_hms(10, 15, 0)),
+            report.status
+        );
+        assert_eq!(Priority::High, report.priority);
+        assert!(report.tags.contains("work") && report.tags.contains("report"));
+        assert_eq!(
+            Some(&"first draft\nneeds figures".to_string()),
+            report.extras.get(&ExtraData::Notes)
+        );
+
+        assert_eq!(TaskState::Open, tasks[1].status);
+        assert_eq!(Priority::Low, tasks[1].priority);
+        assert_eq!(Some(Utc.ymd(2018, 7, 20).and_hms(0, 0, 0)), tasks[1].due);
+    }
+
+    #[test]
+    fn test_read_json_lines() {
+        let line = r#"{"description":"foo","entry":"20180712T080000Z","status":"pending","uuid":"1b4e28ba-2fa1-11d2-883f-0016d3cca427"}"#;
+        let tasks = read(&format!("{}\n\n{}\n", line, line)).unwrap();
+        assert_eq!(2, tasks.len());
+        assert_eq!(tasks[0].created, tasks[0].modified);
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        assert!(read(r#"[{"description":"foo","entry":"yesterday","status":"pending","uuid":"1b4e28ba-2fa1-11d2-883f-0016d3cca427"}]"#).is_err());
+        assert!(read(r#"[{"description":"foo","entry":"20180712T080000Z","status":"pending","uuid":"foo"}]"#).is_err());
+        assert!(read("{").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tasks = read(EXPORT).unwrap();
+        let mut out = vec![];
+        write(&tasks.iter().collect::<Vec<_>>(), &mut out).unwrap();
+
+        let again = read(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(tasks, again);
+    }
+}
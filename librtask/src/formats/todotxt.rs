@@ -0,0 +1,312 @@
+//! The todo.txt format (https://github.com/todotxt/todo.txt).
+//!
+//! `+project`s and `@context`s both map to tags, the latter keeping
+//! their `@`. Dates, the rtask UUID and everything else without a
+//! place in the format are stored as `key:value` extensions. Lines
+//! without a `uuid:` get one derived from their description.
+//!
+//! rtask only knows three priorities: `(A)` is high, `(B)` the default
+//! and `(C)` to `(Z)` are all low.
+
+use super::{DescriptionUuids, Error, Format};
+use task::*;
+
+use chrono;
+use chrono::{Local, NaiveDate, TimeZone};
+use regex::Regex;
+
+use std::io;
+
+const DATE_FORMAT: &'static str = "%Y-%m-%d";
+
+fn parse_date(s: &str) -> Option<Time> {
+    NaiveDate::parse_from_str(s, DATE_FORMAT)
+        .ok()
+        .and_then(|d| Local.from_local_date(&d).earliest())
+        .map(|d| d.and_hms(0, 0, 0).with_timezone(&chrono::Utc))
+}
+
+fn format_date(t: &Time) -> String {
+    t.with_timezone(&Local).format(DATE_FORMAT).to_string()
+}
+
+// Everything below `(B)` is low
+fn parse_priority(c: char) -> Priority {
+    match c {
+        'A' => Priority::High,
+        'B' => Priority::Default,
+        _ => Priority::Low,
+    }
+}
+
+fn format_priority(p: Priority) -> Option<char> {
+    match p {
+        Priority::High => Some('A'),
+        Priority::Default => None,
+        Priority::Low => Some('C'),
+    }
+}
+
+/// `x` lines without a completion date were done at `modified`
+fn parse_line(line: &str, uuids: &mut DescriptionUuids, modified: Time) -> Result<Task, Error> {
+    lazy_static! {
+        static ref PRIORITY_RE: Regex = Regex::new("^\\(([A-Z])\\)$").unwrap();
+    }
+
+    let invalid = |what: &str, value: &str| Error::Invalid(format!("Invalid {} {:?} in line {:?}", what, value, line));
+
+    let mut tokens = line.split_whitespace().peekable();
+    let mut task = Task::new("");
+
+    let done = tokens.peek() == Some(&"x");
+    let mut completed = None;
+    if done {
+        tokens.next();
+        completed = tokens.peek().and_then(|s| parse_date(s));
+        if completed.is_some() {
+            tokens.next();
+        }
+    }
+
+    if let Some(p) = tokens.peek().and_then(|s| PRIORITY_RE.captures(s)) {
+        task.priority = parse_priority(p[1].chars().next().unwrap());
+    }
+    if task.priority != Priority::Default || tokens.peek() == Some(&"(B)") {
+        tokens.next();
+    }
+
+    if let Some(created) = tokens.peek().and_then(|s| parse_date(s)) {
+        task.created = created;
+        task.modified = created;
+        tokens.next();
+    }
+
+    let mut canceled = false;
+    let mut has_uuid = false;
+    let mut description = vec![];
+    for token in tokens {
+        if token.len() > 1 && token.starts_with('+') {
+            task.tags.insert(token[1..].into());
+            continue;
+        }
+        if token.len() > 1 && token.starts_with('@') {
+            task.tags.insert(token.into());
+            continue;
+        }
+
+        // Unknown extensions stay part of the description
+        let mut kv = token.splitn(2, ':');
+        match (kv.next(), kv.next()) {
+            (Some("uuid"), Some(v)) => {
+                task.uuid = Uuid::parse_str(v).map_err(|_| invalid("uuid", v))?;
+                has_uuid = true;
+            }
+            (Some("due"), Some(v)) => task.due = Some(parse_date(v).ok_or_else(|| invalid("date", v))?),
+            (Some("t"), Some(v)) => task.wait = Some(parse_date(v).ok_or_else(|| invalid("date", v))?),
+            (Some("scheduled"), Some(v)) => {
+                task.scheduled = Some(parse_date(v).ok_or_else(|| invalid("date", v))?)
+            }
+            (Some("pri"), Some(v)) if v.len() == 1 => {
+                task.priority = parse_priority(v.chars().next().unwrap())
+            }
+            (Some("status"), Some("canceled")) => canceled = true,
+            _ => description.push(token),
+        }
+    }
+
+    if description.is_empty() {
+        return Err(Error::Invalid(format!("Missing description in line {:?}", line)));
+    }
+    task.description = description.join(" ");
+    if !has_uuid {
+        task.uuid = uuids.next(&task.description);
+    }
+
+    if done {
+        let time = completed.unwrap_or(modified);
+        task.status = if canceled {
+            TaskState::Canceled(time)
+        } else {
+            TaskState::Done(time)
+        };
+    }
+
+    Ok(task)
+}
+
+fn format_line(task: &Task) -> String {
+    let mut parts: Vec<String> = vec![];
+
+    match task.status {
+        TaskState::Open => {
+            if let Some(p) = format_priority(task.priority) {
+                parts.push(format!("({})", p));
+            }
+        }
+        TaskState::Done(t) | TaskState::Canceled(t) => {
+            parts.push("x".into());
+            parts.push(format_date(&t));
+        }
+    }
+
+    parts.push(format_date(&task.created));
+    parts.push(task.description.split_whitespace().collect::<Vec<_>>().join(" "));
+
+    // Projects first, then contexts
+    let mut tags: Vec<&Tag> = task.tags.iter().collect();
+    tags.sort_by_key(|t| (t.starts_with('@'), t.to_string()));
+    for tag in tags {
+        if tag.starts_with('@') {
+            parts.push(tag.clone());
+        } else {
+            parts.push(format!("+{}", tag));
+        }
+    }
+
+    if let Some(ref due) = task.due {
+        parts.push(format!("due:{}", format_date(due)));
+    }
+    if let Some(ref wait) = task.wait {
+        parts.push(format!("t:{}", format_date(wait)));
+    }
+    if let Some(ref scheduled) = task.scheduled {
+        parts.push(format!("scheduled:{}", format_date(scheduled)));
+    }
+    if !task.is_open() {
+        if let Some(p) = format_priority(task.priority) {
+            parts.push(format!("pri:{}", p));
+        }
+    }
+    if let TaskState::Canceled(_) = task.status {
+        parts.push("status:canceled".into());
+    }
+    parts.push(format!("uuid:{}", task.uuid.hyphenated()));
+
+    parts.join(" ")
+}
+
+/// Reads all non-empty lines of a file last changed at `modified`
+pub fn read(input: &str, modified: Time) -> Result<Vec<Task>, Error> {
+    let mut uuids = DescriptionUuids::new(Format::TodoTxt);
+    input
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| parse_line(l, &mut uuids, modified))
+        .collect()
+}
+
+pub fn write(tasks: &[&Task], out: &mut io::Write) -> Result<(), Error> {
+    for task in tasks {
+        writeln!(out, "{}", format_line(task))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formats::import_effects;
+    use model::{Effect, Model};
+
+    fn parse(line: &str) -> Result<Task, Error> {
+        parse_line(line, &mut DescriptionUuids::new(Format::TodoTxt), chrono::Utc::now())
+    }
+
+    #[test]
+    fn test_parse() {
+        let t = parse("(A) 2018-07-10 Call mom +family @phone due:2018-07-14").unwrap();
+        assert_eq!("Call mom", t.description);
+        assert_eq!(Priority::High, t.priority);
+        assert_eq!(TaskState::Open, t.status);
+        assert_eq!(parse_date("2018-07-10"), Some(t.created));
+        assert_eq!(parse_date("2018-07-14"), t.due);
+        assert!(t.tags.contains("family") && t.tags.contains("@phone"));
+
+        let t = parse("x 2018-07-13 2018-07-10 Pay rent pri:C").unwrap();
+        assert_eq!("Pay rent", t.description);
+        assert_eq!(TaskState::Done(parse_date("2018-07-13").unwrap()), t.status);
+        assert_eq!(Priority::Low, t.priority);
+
+        let t = parse("Read https://example.com t:2018-08-01").unwrap();
+        assert_eq!("Read https://example.com", t.description);
+        assert_eq!(parse_date("2018-08-01"), t.wait);
+        assert_eq!(Priority::Default, t.priority);
+
+        assert!(parse("(A) +tag").is_err());
+        assert!(parse("foo due:tomorrow").is_err());
+        assert!(parse("foo uuid:bar").is_err());
+        assert_eq!(Priority::Low, parse("(D) foo").unwrap().priority);
+    }
+
+    #[test]
+    fn test_read_again() {
+        let input = "x Pay rent\nPay rent\nCall mom uuid:1b4e28ba-2fa1-11d2-883f-0016d3cca427\n";
+        let modified = parse_date("2018-07-13").unwrap();
+        let tasks = read(input, modified).unwrap();
+
+        assert_eq!(TaskState::Done(modified), tasks[0].status);
+        assert!(tasks[0].uuid != tasks[1].uuid);
+        assert_eq!(Uuid::parse_str("1b4e28ba-2fa1-11d2-883f-0016d3cca427").unwrap(), tasks[2].uuid);
+
+        let uuids = |tasks: &[Task]| tasks.iter().map(|t| t.uuid).collect::<Vec<_>>();
+        assert_eq!(uuids(&tasks), uuids(&read(input, modified).unwrap()));
+    }
+
+    #[test]
+    fn test_format_line() {
+        let mut t = Task::new("Call mom");
+        t.created = parse_date("2018-07-10").unwrap();
+        t.priority = Priority::High;
+        t.tags.insert("family".into());
+        t.tags.insert("@phone".into());
+        assert_eq!(
+            format!("(A) 2018-07-10 Call mom +family @phone uuid:{}", t.uuid.hyphenated()),
+            format_line(&t)
+        );
+
+        t.status = TaskState::Canceled(parse_date("2018-07-11").unwrap());
+        assert_eq!(
+            format!(
+                "x 2018-07-11 2018-07-10 Call mom +family @phone pri:A status:canceled uuid:{}",
+                t.uuid.hyphenated()
+            ),
+            format_line(&t)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut t1 = Task::new("foo\nbar");
+        t1.created = parse_date("2018-07-10").unwrap();
+        t1.modified = t1.created;
+        t1.priority = Priority::Low;
+        t1.due = parse_date("2018-07-20");
+        t1.scheduled = parse_date("2018-07-15");
+        t1.tags.insert("work".into());
+
+        let mut t2 = Task::new("done");
+        t2.created = parse_date("2018-07-10").unwrap();
+        t2.modified = t2.created;
+        t2.status = TaskState::Done(parse_date("2018-07-12").unwrap());
+
+        let mut out = vec![];
+        write(&[&t1, &t2], &mut out).unwrap();
+        let tasks = read(&String::from_utf8(out).unwrap(), chrono::Utc::now()).unwrap();
+
+        t1.description = "foo bar".into();
+        assert_eq!(vec![t1, t2], tasks);
+    }
+
+    #[test]
+    fn test_import_active() {
+        let mut model = Model::new();
+        let task = Task::new("foo");
+        model.apply_effect(&Effect::AddTask(task.clone()));
+        model.apply_effect(&Effect::StartTask(task.uuid, chrono::Utc::now()));
+
+        let mut out = vec![];
+        write(&[&model.tasks[&task.uuid]], &mut out).unwrap();
+        let tasks = read(&String::from_utf8(out).unwrap(), chrono::Utc::now()).unwrap();
+        assert!(import_effects(&model, tasks, Format::TodoTxt).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+//! Splitting task lists into sections, like `group:tag`
+
+use task::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    /// A task shows up once for every tag it has
+    Tag,
+    Priority,
+    Status,
+}
+
+#[derive(Debug, PartialEq, Eq, Fail)]
+#[fail(display = "Unknown grouping {:?}, expected tag, priority or status", _0)]
+pub struct GroupingError(String);
+
+/// A section of a task list, the tasks keep their order
+pub struct Group<'a> {
+    pub name: String,
+    pub tasks: Vec<&'a Task>,
+}
+
+impl Grouping {
+    /// Splits sorted `tasks` into groups. Tags are ordered by name with
+    /// untagged tasks last, priorities from high to low and states from
+    /// open to canceled. Empty groups are left out.
+    pub fn group<'a>(&self, tasks: &[&'a Task]) -> Vec<Group<'a>> {
+        let names: Vec<String> = match *self {
+            Grouping::Tag => {
+                let mut tags: Vec<String> = tasks
+                    .iter()
+                    .flat_map(|t| t.tags.iter().cloned())
+                    .collect();
+                // Tags differing in case are separate groups, sorted
+                // next to each other
+                tags.sort_by(|a, b| (a.to_lowercase(), a).cmp(&(b.to_lowercase(), b)));
+                tags.dedup();
+                tags.push(NO_TAG.into());
+                tags
+            }
+            Grouping::Priority => vec!["high".into(), "default".into(), "low".into()],
+            Grouping::Status => vec!["open".into(), "done".into(), "canceled".into()],
+        };
+
+        names
+            .into_iter()
+            .map(|name| Group {
+                tasks: tasks
+                    .iter()
+                    .filter(|t| self.keys(t).contains(&name))
+                    .cloned()
+                    .collect(),
+                name: name,
+            })
+            .filter(|g| !g.tasks.is_empty())
+            .collect()
+    }
+
+    fn keys(&self, task: &Task) -> Vec<String> {
+        match *self {
+            Grouping::Tag if task.tags.is_empty() => vec![NO_TAG.into()],
+            Grouping::Tag => task.tags.iter().cloned().collect(),
+            Grouping::Priority => vec![
+                match task.priority {
+                    Priority::High => "high",
+                    Priority::Default => "default",
+                    Priority::Low => "low",
+                }.into(),
+            ],
+            Grouping::Status => vec![task.status.to_string()],
+        }
+    }
+}
+
+const NO_TAG: &'static str = "(no tag)";
+
+impl FromStr for Grouping {
+    type Err = GroupingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "tag" | "tags" => Ok(Grouping::Tag),
+            "pri" | "priority" => Ok(Grouping::Priority),
+            "status" => Ok(Grouping::Status),
+            _ => Err(GroupingError(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Grouping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Grouping::Tag => f.write_str("tag"),
+            Grouping::Priority => f.write_str("priority"),
+            Grouping::Status => f.write_str("status"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono;
+
+    fn summary(groups: &[Group]) -> Vec<(String, Vec<String>)> {
+        groups
+            .iter()
+            .map(|g| {
+                (
+                    g.name.clone(),
+                    g.tasks.iter().map(|t| t.description.clone()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_group() {
+        let mut a = Task::new("a");
+        let mut b = Task::new("b");
+        let mut c = Task::new("c");
+        a.tags.insert("work".into());
+        a.tags.insert("home".into());
+        b.tags.insert("work".into());
+        b.priority = Priority::High;
+        c.status = TaskState::Done(chrono::Utc::now());
+        let tasks = vec![&a, &b, &c];
+
+        assert_eq!(
+            vec![
+                ("home".to_string(), vec!["a".to_string()]),
+                ("work".to_string(), vec!["a".to_string(), "b".to_string()]),
+                ("(no tag)".to_string(), vec!["c".to_string()]),
+            ],
+            summary(&Grouping::Tag.group(&tasks))
+        );
+        assert_eq!(
+            vec![
+                ("high".to_string(), vec!["b".to_string()]),
+                ("default".to_string(), vec!["a".to_string(), "c".to_string()]),
+            ],
+            summary(&Grouping::Priority.group(&tasks))
+        );
+        assert_eq!(
+            vec!["open", "done"],
+            Grouping::Status
+                .group(&tasks)
+                .iter()
+                .map(|g| &g.name[..])
+                .collect::<Vec<_>>()
+        );
+
+        let mut d = Task::new("d");
+        d.tags.insert("Work".into());
+        d.tags.insert("Home".into());
+        let tasks = vec![&a, &d, &b];
+        assert_eq!(
+            vec!["Home", "home", "Work", "work"],
+            Grouping::Tag
+                .group(&tasks)
+                .iter()
+                .map(|g| &g.name[..])
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(Ok(Grouping::Priority), Grouping::from_str("pri"));
+        assert!(Grouping::from_str("due").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use date;
+use model::{Model, StoredEffect};
+use task::{Task, Time, Uuid};
+
+use std::str::FromStr;
+
+/// A point in the history of the task store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOf {
+    Time(Time),
+    /// The (1-based) position of an effect in the effect log
+    Effect(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Fail)]
+#[fail(display = "Invalid date or effect id {:?}", _0)]
+pub struct AsOfError(String);
+
+impl FromStr for AsOf {
+    type Err = AsOfError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        usize::from_str(s)
+            .ok()
+            .map(AsOf::Effect)
+            .or_else(|| date::parse_date(s).map(AsOf::Time))
+            .ok_or_else(|| AsOfError(s.to_string()))
+    }
+}
+
+/// A single entry in the history of the task store: An effect
+/// together with the state of the affected task before and after it
+/// got applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<'a> {
+    pub stored: &'a StoredEffect,
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+}
+
+impl<'a> Change<'a> {
+    pub fn lines(&self) -> Vec<String> {
+        self.stored
+            .effect
+            .diff_lines(self.before.as_ref(), self.after.as_ref())
+    }
+}
+
+/// Replays `effects` and returns every change for which `pred`
+/// returns true.
+pub fn changes<'a, F>(effects: &'a [StoredEffect], pred: F) -> Vec<Change<'a>>
+where
+    F: Fn(&StoredEffect) -> bool,
+{
+    let mut model = Model::new();
+    let mut changes = vec![];
+
+    for stored in effects {
+        let uuid = *stored.effect.task_id();
+        let before = model.get_task(&uuid).cloned();
+        model.apply_effect(&stored.effect);
+
+        if pred(stored) {
+            changes.push(Change {
+                stored: stored,
+                before: before,
+                after: model.get_task(&uuid).cloned(),
+            });
+        }
+    }
+
+    changes
+}
+
+impl Model {
+    /// Reconstructs the state of the model at the given point in
+    /// history. Numerical ids aren't part of the effect log and are
+    /// therefore left empty.
+    pub fn as_of(&self, as_of: &AsOf) -> Model {
+        let n = match *as_of {
+            AsOf::Effect(n) => n,
+            AsOf::Time(t) => self.applied_effects
+                .iter()
+                .take_while(|e| e.meta.time <= t)
+                .count(),
+        };
+
+        let n = n.min(self.applied_effects.len());
+        Model::from_effects(&self.applied_effects[..n])
+    }
+
+    /// All changes ever made to the task with `uuid`, oldest first.
+    pub fn task_history<'a>(&'a self, uuid: &Uuid) -> Vec<Change<'a>> {
+        changes(&self.applied_effects, |e| e.effect.task_id() == uuid)
+    }
+
+    /// All changes made to any task after `since`, oldest first.
+    pub fn changes_since<'a>(&'a self, since: Option<Time>) -> Vec<Change<'a>> {
+        changes(&self.applied_effects, |e| {
+            since.map_or(true, |since| e.meta.time >= since)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono;
+    use {Effect, Priority, TaskState};
+
+    #[test]
+    fn test_task_history() {
+        let mut m = Model::new();
+        let t = Task::new("foo");
+        let t2 = Task::new("bar");
+        m.apply_effect(&Effect::AddTask(t.clone()));
+        m.apply_effect(&Effect::AddTask(t2.clone()));
+        m.apply_effect(&Effect::ChangeTaskPriority(t.uuid, Priority::High));
+        m.apply_effect(&Effect::DeleteTask(t.uuid));
+
+        let history = m.task_history(&t.uuid);
+        assert_eq!(3, history.len());
+
+        assert_eq!(None, history[0].before);
+        assert_eq!(Some(t.clone()), history[0].after);
+
+        assert_eq!(Some(Priority::Default), history[1].before.as_ref().map(|t| t.priority));
+        assert_eq!(Some(Priority::High), history[1].after.as_ref().map(|t| t.priority));
+        assert_eq!(
+            vec!["changed priority of 'foo'".to_string(), "priority: D -> H".into()],
+            history[1].lines()
+        );
+
+        assert!(history[2].after.is_none());
+        assert_eq!(vec!["deleted task 'foo'".to_string()], history[2].lines());
+    }
+
+    #[test]
+    fn test_as_of() {
+        let mut m = Model::new();
+        let t = Task::new("foo");
+        m.apply_effect(&Effect::AddTask(t.clone()));
+        m.apply_effect(&Effect::ChangeTaskPriority(t.uuid, Priority::High));
+        m.recalculate_numerical_ids("default", &[t.uuid]);
+
+        assert!(m.as_of(&AsOf::Effect(0)).tasks.is_empty());
+        assert_eq!(Priority::Default, m.as_of(&AsOf::Effect(1)).tasks[&t.uuid].priority);
+        assert_eq!(Priority::High, m.as_of(&AsOf::Effect(100)).tasks[&t.uuid].priority);
+
+        let first = m.applied_effects[0].meta.time;
+        assert!(m.as_of(&AsOf::Time(first - chrono::Duration::seconds(1))).tasks.is_empty());
+        assert_eq!(1, m.as_of(&AsOf::Time(first)).tasks.len());
+
+        // The original model stays untouched
+        assert_eq!(2, m.applied_effects.len());
+        assert_eq!(Some(1), m.short_task_id("default", &t.uuid));
+    }
+
+    #[test]
+    fn test_parse_as_of() {
+        assert_eq!(Ok(AsOf::Effect(42)), AsOf::from_str("42"));
+        assert!(match AsOf::from_str("2018-07-13") {
+            Ok(AsOf::Time(_)) => true,
+            _ => false,
+        });
+        assert!(AsOf::from_str("last friday").is_err());
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let mut m = Model::new();
+        let t = Task::new("foo");
+        m.apply_effect(&Effect::AddTask(t.clone()));
+        m.apply_effect(&Effect::ChangeTaskState(
+            t.uuid,
+            TaskState::Done(chrono::Utc::now()),
+        ));
+
+        assert_eq!(2, m.changes_since(None).len());
+
+        let later = m.applied_effects[1].meta.time + chrono::Duration::seconds(1);
+        assert!(m.changes_since(Some(later)).is_empty());
+
+        let lines = m.changes_since(None)[1].lines();
+        assert_eq!("status: open -> done", lines[1]);
+    }
+}
@@ -0,0 +1,393 @@
+//! Executables in the `hooks` directory run whenever a command changes
+//! tasks. Their name says when they run, `on-add`, `on-modify`,
+//! `on-done` and `on-exit`, optionally followed by anything, e.g.
+//! `on-done-release`. Hooks for the same event run in order of their
+//! names.
+//!
+//! `on-add`, `on-modify` and `on-done` get the task as JSON before and
+//! after the change on stdin, one line each (`null` before a task is
+//! added). If a hook exits with a non-zero status the whole command is
+//! rejected. Otherwise a line of task JSON on its stdout replaces the
+//! changed task, all other lines are shown to the user. Hooks can
+//! change the tags, priority, dates, status and time tracking of an
+//! existing task, but not its description or extras. Deleting a task
+//! runs no hooks.
+//!
+//! `on-exit` runs once the changes are applied and gets the changed
+//! tasks, one per line. It can't change anything anymore. Commands
+//! which don't change anything run no hooks.
+
+use serde_json;
+
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::fs;
+
+use command::{validate_tag, Changes};
+use task::*;
+use {Effect, Model};
+
+pub const HOOKS_DIR: &'static str = "hooks";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Add,
+    Modify,
+    Done,
+    Exit,
+}
+
+impl Event {
+    fn from_file_name(name: &str) -> Option<Self> {
+        [
+            ("on-add", Event::Add),
+            ("on-modify", Event::Modify),
+            ("on-done", Event::Done),
+            ("on-exit", Event::Exit),
+        ].iter()
+            .find(|&&(prefix, _)| name.starts_with(prefix))
+            .map(|&(_, event)| event)
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum HookError {
+    #[fail(display = "Hook {} rejected the change: {}", _0, _1)]
+    Rejected(String, String),
+    #[fail(display = "Hook {} returned an invalid task: {}", _0, _1)]
+    InvalidTask(String, String),
+    #[fail(display = "Failed to run hook {}: {}", _0, _1)]
+    Run(String, io::Error),
+}
+
+#[derive(Debug, Default)]
+pub struct Hooks {
+    hooks: Vec<(Event, PathBuf)>,
+}
+
+/// What a hook printed
+struct HookOutput {
+    task: Option<Task>,
+    messages: Vec<String>,
+}
+
+impl Hooks {
+    /// The executables in `dir`, there are no hooks if it doesn't exist
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Hooks::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut hooks = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let executable = entry.metadata()?.permissions().mode() & 0o111 != 0;
+            let event = entry.file_name().to_str().and_then(Event::from_file_name);
+            match event {
+                Some(event) if executable => hooks.push((event, entry.path())),
+                _ => debug!("Ignoring {:?} in hooks", entry.path()),
+            }
+        }
+        hooks.sort_by(|a, b| a.1.cmp(&b.1));
+
+        Ok(Hooks { hooks: hooks })
+    }
+
+    /// Applies `effects` as one command, after the hooks had their say.
+    /// Returns the effects that were applied and what the hooks printed.
+    pub fn apply(&self, model: &mut Model, effects: Vec<Effect>) -> Result<Changes, HookError> {
+        let mut changes = Changes::default();
+        if effects.is_empty() {
+            return Ok(changes);
+        }
+        let mut effects = effects;
+
+        // Every task is changed as a whole, in order of their first effect
+        let mut uuids: Vec<Uuid> = vec![];
+        for effect in effects.iter() {
+            if !uuids.contains(effect.task_id()) {
+                uuids.push(*effect.task_id());
+            }
+        }
+
+        let mut changed = vec![];
+        for uuid in uuids {
+            let before = model.get_task(&uuid).cloned();
+            let task_effects: Vec<Effect> = effects
+                .iter()
+                .filter(|e| *e.task_id() == uuid)
+                .cloned()
+                .collect();
+            let after = match changed_task(before.as_ref(), &task_effects) {
+                Some(after) => after,
+                None => continue,
+            };
+
+            let event = match before {
+                None => Event::Add,
+                Some(ref b) if b.is_open() && after.status != b.status => match after.status {
+                    TaskState::Done(_) => Event::Done,
+                    _ => Event::Modify,
+                },
+                Some(_) => Event::Modify,
+            };
+
+            let mut rewritten = after.clone();
+            for path in self.paths(event) {
+                let output = run(path, &[before.clone(), Some(rewritten.clone())])?;
+                changes.notes.extend(output.messages);
+
+                if let Some(task) = output.task {
+                    check_rewritten(path, before.as_ref(), &after, &task)?;
+                    rewritten = task;
+                }
+            }
+
+            if rewritten != after {
+                let replacement = match before {
+                    Some(ref before) => Effect::diff(before, &rewritten),
+                    None => vec![Effect::AddTask(rewritten.clone())],
+                };
+                effects = replace_effects(effects, &uuid, replacement);
+            }
+            changed.push(Some(rewritten));
+        }
+
+        model.begin_command();
+        for effect in effects.iter() {
+            info!("Applying Effect: {:?}", effect);
+            model.apply_effect(effect);
+        }
+        changes.effects = effects;
+
+        for path in self.paths(Event::Exit) {
+            match run(path, &changed) {
+                Ok(output) => changes.notes.extend(output.messages),
+                Err(error) => changes.notes.push(error.to_string()),
+            }
+        }
+
+        Ok(changes)
+    }
+
+    fn paths(&self, event: Event) -> Vec<&Path> {
+        self.hooks
+            .iter()
+            .filter(|h| h.0 == event)
+            .map(|h| h.1.as_path())
+            .collect()
+    }
+}
+
+/// The task after `effects`, `None` if it is deleted
+fn changed_task(before: Option<&Task>, effects: &[Effect]) -> Option<Task> {
+    let mut scratch = Model::new();
+    if let Some(task) = before {
+        scratch.apply_effect(&Effect::AddTask(task.clone()));
+    }
+    for effect in effects {
+        scratch.apply_effect(effect);
+    }
+
+    effects
+        .first()
+        .and_then(|e| scratch.get_task(e.task_id()))
+        .cloned()
+}
+
+/// Replaces the effects on the task `uuid` where the first of them was
+fn replace_effects(effects: Vec<Effect>, uuid: &Uuid, replacement: Vec<Effect>) -> Vec<Effect> {
+    let mut replacement = Some(replacement);
+    let mut result = vec![];
+    for effect in effects {
+        if effect.task_id() != uuid {
+            result.push(effect);
+        } else if let Some(replacement) = replacement.take() {
+            result.extend(replacement);
+        }
+    }
+    result
+}
+
+fn check_rewritten(path: &Path, before: Option<&Task>, after: &Task, task: &Task) -> Result<(), HookError> {
+    let invalid = |message: String| HookError::InvalidTask(hook_name(path), message);
+
+    if task.uuid != after.uuid {
+        return Err(invalid("The uuid can't be changed".into()));
+    }
+    // Changes to existing tasks are stored as effects, which don't
+    // cover everything
+    if let Some(before) = before {
+        let effects = Effect::diff(before, task);
+        let expressible = if effects.is_empty() {
+            task == before
+        } else {
+            changed_task(Some(before), &effects).as_ref() == Some(task)
+        };
+        if task != after && !expressible {
+            return Err(invalid(
+                "Only the tags, priority, dates, status and time tracking can be changed".into(),
+            ));
+        }
+    }
+    for tag in task.tags.difference(&after.tags) {
+        validate_tag(tag).map_err(|e| invalid(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn hook_name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+/// Runs a hook with one line of JSON per task on stdin
+fn run(path: &Path, tasks: &[Option<Task>]) -> Result<HookOutput, HookError> {
+    let name = hook_name(path);
+    let error = |e: io::Error| HookError::Run(hook_name(path), e);
+    info!("Running hook {}", name);
+
+    let mut input = vec![];
+    for task in tasks {
+        serde_json::to_writer(&mut input, task).expect("Failed to serialize task");
+        input.push(b'\n');
+    }
+
+    let mut child = process::Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(&error)?;
+    // Hooks don't have to read their input
+    match child.stdin.take().expect("Stdin is piped").write_all(&input) {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        result => result.map_err(&error)?,
+    }
+    let output = child.wait_with_output().map_err(&error)?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut result = HookOutput {
+        task: None,
+        messages: vec![],
+    };
+    for line in stdout.lines().map(str::trim) {
+        if line.starts_with('{') {
+            let task = serde_json::from_str(line).map_err(|e| HookError::InvalidTask(name.clone(), e.to_string()))?;
+            result.task = Some(task);
+        } else if !line.is_empty() {
+            result.messages.push(line.to_string());
+        }
+    }
+
+    if output.status.success() {
+        Ok(result)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut reasons = result.messages;
+        reasons.extend(stderr.lines().filter(|l| !l.trim().is_empty()).map(String::from));
+        if reasons.is_empty() {
+            reasons.push(output.status.to_string());
+        }
+        Err(HookError::Rejected(name, reasons.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn hooks_dir(name: &str, hooks: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rtask-hooks-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for &(file, script) in hooks {
+            let path = dir.join(file);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_reject() {
+        let dir = hooks_dir(
+            "reject",
+            &[
+                (
+                    "on-add-bug",
+                    "read before; read after\n\
+                     echo \"$after\" | grep -q '\"priority\":\"Default\"' || exit 0\n\
+                     echo \"$after\" | grep -q '\"bug\"' && echo 'Bugs need a priority' && exit 1",
+                ),
+                ("on-exit", "cat > /dev/null; echo bye"),
+            ],
+        );
+        let hooks = Hooks::load(&dir).unwrap();
+        let mut m = Model::new();
+
+        let mut task = Task::new("crash");
+        task.tags.insert("bug".into());
+        match hooks.apply(&mut m, vec![Effect::AddTask(task.clone())]) {
+            Err(HookError::Rejected(ref name, ref message)) => {
+                assert_eq!("on-add-bug", name);
+                assert_eq!("Bugs need a priority", message);
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(m.get_task(&task.uuid).is_none());
+
+        task.priority = Priority::High;
+        let changes = hooks.apply(&mut m, vec![Effect::AddTask(task.clone())]).unwrap();
+        assert_eq!(vec!["bye"], changes.notes);
+        assert_eq!(Some(&task), m.get_task(&task.uuid));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let dir = hooks_dir(
+            "rewrite",
+            &[("on-modify", "read before; read after\necho \"$after\" | sed 's/\"Low\"/\"High\"/'")],
+        );
+        let hooks = Hooks::load(&dir).unwrap();
+        let mut m = Model::new();
+
+        let task = Task::new("foo");
+        hooks.apply(&mut m, vec![Effect::AddTask(task.clone())]).unwrap();
+        let changes = hooks
+            .apply(&mut m, vec![Effect::ChangeTaskPriority(task.uuid, Priority::Low)])
+            .unwrap();
+        assert_eq!(vec![Effect::ChangeTaskPriority(task.uuid, Priority::High)], changes.effects);
+        assert_eq!(Priority::High, m.get_task(&task.uuid).unwrap().priority);
+
+        assert!(Hooks::load(dir.join("missing")).unwrap().hooks.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_description() {
+        let dir = hooks_dir(
+            "description",
+            &[("on-modify", "read before; read after\necho \"$after\" | sed 's/\"foo\"/\"bar\"/'")],
+        );
+        let hooks = Hooks::load(&dir).unwrap();
+        let mut m = Model::new();
+
+        let task = Task::new("foo");
+        hooks.apply(&mut m, vec![Effect::AddTask(task.clone())]).unwrap();
+        match hooks.apply(&mut m, vec![Effect::ChangeTaskPriority(task.uuid, Priority::Low)]) {
+            Err(HookError::InvalidTask(ref name, _)) => assert_eq!("on-modify", name),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(Some(&task), m.get_task(&task.uuid));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
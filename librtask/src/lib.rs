@@ -4,6 +4,7 @@ extern crate rusqlite;
 extern crate serde;
 extern crate chrono;
 extern crate serde_json;
+extern crate unicode_width;
 extern crate uuid;
 
 #[macro_use] extern crate derive_more;
@@ -12,21 +13,36 @@ extern crate uuid;
 #[macro_use] extern crate log;
 #[macro_use] extern crate serde_derive;
 
+pub mod column;
 pub mod command;
+pub mod config;
+pub mod date;
 pub mod file_lock;
+pub mod formats;
+pub mod group;
+pub mod history;
+pub mod hooks;
 pub mod model;
+pub mod report;
+pub mod sort;
 pub mod task;
 pub mod task_ref;
+pub mod theme;
+pub mod timesheet;
 
-pub use file_lock::FileLock;
+pub use file_lock::{chdir, FileLock, PID_FILE};
 pub use model::*;
 pub use task::*;
 
 pub trait StorageEngine: Sized + Drop {
     type LoadErr;
+    type SaveErr;
 
     fn new() -> Result<Self, Self::LoadErr>;
     fn model<'a>(&'a mut self) -> &'a mut Model;
+    /// Writes all changes to the model made since the last save, this
+    /// also happens when the storage is dropped
+    fn save(&mut self) -> Result<(), Self::SaveErr>;
 }
 
 mod sqlite_storage;
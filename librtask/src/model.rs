@@ -2,7 +2,15 @@ use std::collections::{BTreeMap, HashMap};
 use task::*;
 use task_ref::TaskRef;
 
-use std::io;
+use chrono;
+use chrono::TimeZone;
+use date;
+use libc;
+use uuid;
+
+use std::ffi::CStr;
+use std::ops::Deref;
+use std::{fmt, io};
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Effect {
@@ -15,11 +23,14 @@ pub enum Effect {
     ChangeTaskState(Uuid, TaskState),
     ChangeTaskPriority(Uuid, Priority),
     DeleteTask(Uuid),
+    StartTask(Uuid, Time),
+    StopTask(Uuid, Time),
+    ChangeTaskDate(Uuid, TaskDate, Option<Time>),
     // Undo,
 }
 
 impl Effect {
-    fn task_id<'a>(&'a self) -> &'a Uuid {
+    pub fn task_id<'a>(&'a self) -> &'a Uuid {
         use Effect::*;
         match *self {
             AddTask(Task { ref uuid, .. }) => uuid,
@@ -27,6 +38,9 @@ impl Effect {
             ChangeTaskState(ref u, _) => u,
             ChangeTaskPriority(ref u, _) => u,
             DeleteTask(ref u) => u,
+            StartTask(ref u, _) => u,
+            StopTask(ref u, _) => u,
+            ChangeTaskDate(ref u, _, _) => u,
         }
     }
 
@@ -68,22 +82,287 @@ impl Effect {
                         task.description, priority
                     )?;
                 }
+                StartTask(_, _) => writeln!(out, "Started task '{}'", task.description)?,
+                StopTask(_, _) => writeln!(out, "Stopped task '{}'", task.description)?,
+                ChangeTaskDate(_, kind, Some(ref date)) => writeln!(
+                    out,
+                    "Changed {} date of task '{}' to {}",
+                    kind,
+                    task.description,
+                    date::format_local(date)
+                )?,
+                ChangeTaskDate(_, kind, None) => writeln!(
+                    out,
+                    "Removed {} date of task '{}'",
+                    kind, task.description
+                )?,
             };
         }
 
         Ok(())
     }
+
+    /// Describes the changes this effect made to a task as a list of
+    /// human-readable lines. `before` and `after` are the states of
+    /// the task prior and subsequent to applying the effect.
+    pub fn diff_lines(&self, before: Option<&Task>, after: Option<&Task>) -> Vec<String> {
+        use Effect::*;
+
+        fn join_tags<'a, I: Iterator<Item = &'a Tag>>(tags: I) -> String {
+            let mut tags: Vec<&str> = tags.map(|t| &t[..]).collect();
+            tags.sort();
+            tags.join(", ")
+        }
+
+        let description = after
+            .or(before)
+            .map(|t| t.description.clone())
+            .unwrap_or_else(|| self.task_id().to_string());
+
+        fn format_date(date: Option<Time>) -> String {
+            date.map_or("none".into(), |d| date::format_local(&d))
+        }
+
+        let mut lines = vec![];
+        match *self {
+            AddTask(ref task) => {
+                lines.push(format!("added task '{}'", task.description));
+                lines.push(format!("priority: {}", task.priority));
+                if !task.tags.is_empty() {
+                    lines.push(format!("tags: {}", join_tags(task.tags.iter())));
+                }
+                for kind in TASK_DATES {
+                    if let Some(date) = task.date(*kind) {
+                        lines.push(format!("{}: {}", kind, format_date(Some(date))));
+                    }
+                }
+            }
+            DeleteTask(_) => lines.push(format!("deleted task '{}'", description)),
+            ChangeTaskTags {
+                ref added,
+                ref removed,
+                ..
+            } => {
+                lines.push(format!("changed tags of '{}'", description));
+                match (before, after) {
+                    (Some(before), Some(after)) => lines.push(format!(
+                        "tags: {} -> {}",
+                        join_tags(before.tags.iter()),
+                        join_tags(after.tags.iter())
+                    )),
+                    _ => {
+                        if !added.is_empty() {
+                            lines.push(format!("added tags: {}", join_tags(added.iter())));
+                        }
+                        if !removed.is_empty() {
+                            lines.push(format!("removed tags: {}", join_tags(removed.iter())));
+                        }
+                    }
+                }
+            }
+            ChangeTaskState(_, ref state) => {
+                lines.push(format!("changed status of '{}'", description));
+                match before {
+                    Some(before) => lines.push(format!("status: {} -> {}", before.status, state)),
+                    None => lines.push(format!("status: {}", state)),
+                }
+            }
+            ChangeTaskPriority(_, ref priority) => {
+                lines.push(format!("changed priority of '{}'", description));
+                match before {
+                    Some(before) => {
+                        lines.push(format!("priority: {} -> {}", before.priority, priority))
+                    }
+                    None => lines.push(format!("priority: {}", priority)),
+                }
+            }
+            StartTask(_, _) => lines.push(format!("started '{}'", description)),
+            StopTask(_, time) => {
+                lines.push(format!("stopped '{}'", description));
+                if let Some(start) = before.and_then(|t| t.active_since()) {
+                    lines.push(format!("worked for {}", Age(time - start)));
+                }
+            }
+            ChangeTaskDate(_, kind, date) => {
+                lines.push(format!("changed {} date of '{}'", kind, description));
+                match before {
+                    Some(before) => lines.push(format!(
+                        "{}: {} -> {}",
+                        kind,
+                        format_date(before.date(kind)),
+                        format_date(date)
+                    )),
+                    None => lines.push(format!("{}: {}", kind, format_date(date))),
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Returns the effects needed to turn `old` into `new`. Changes to
+    /// properties without a corresponding effect (like the
+    /// description) are ignored.
+    pub fn diff(old: &Task, new: &Task) -> Vec<Effect> {
+        use Effect::*;
+
+        assert_eq!(old.uuid, new.uuid);
+        let uuid = old.uuid;
+        let mut effects = vec![];
+
+        let added: Tags = new.tags.difference(&old.tags).cloned().collect();
+        let removed: Tags = old.tags.difference(&new.tags).cloned().collect();
+        if !added.is_empty() || !removed.is_empty() {
+            effects.push(ChangeTaskTags {
+                uuid: uuid,
+                added: added,
+                removed: removed,
+            });
+        }
+
+        if old.priority != new.priority {
+            effects.push(ChangeTaskPriority(uuid, new.priority));
+        }
+
+        for kind in TASK_DATES {
+            if old.date(*kind) != new.date(*kind) {
+                effects.push(ChangeTaskDate(uuid, *kind, new.date(*kind)));
+            }
+        }
+
+        match (old.active_since(), new.active_since()) {
+            (None, Some(start)) => effects.push(StartTask(uuid, start)),
+            (Some(_), None) => {
+                let end = new.intervals
+                    .last()
+                    .and_then(|i| i.end)
+                    .unwrap_or_else(chrono::Utc::now);
+                effects.push(StopTask(uuid, end));
+            }
+            _ => (),
+        }
+
+        if old.status != new.status {
+            effects.push(ChangeTaskState(uuid, new.status));
+        }
+
+        effects
+    }
+
+    pub fn print_diff(
+        &self,
+        before: Option<&Task>,
+        after: Option<&Task>,
+        out: &mut io::Write,
+    ) -> io::Result<()> {
+        for line in self.diff_lines(before, after) {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+pub type DeviceId = String;
+pub type CommandId = Uuid;
+
+/// Metadata recorded alongside every effect applied to a `Model`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EffectMeta {
+    pub uuid: Uuid,
+    pub time: Time,
+    pub origin: DeviceId,
+    /// Groups all effects resulting from a single invocation
+    pub command: CommandId,
+}
+
+/// An `Effect` wrapped in its `EffectMeta`. This is what gets
+/// persisted in the `effects` table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredEffect {
+    pub meta: EffectMeta,
+    pub effect: Effect,
+}
+
+impl StoredEffect {
+    /// Wraps an effect stored before effects carried any metadata.
+    /// There's no way to recover the real timestamp, so we use the
+    /// best guess we have: the time encoded in the effect itself or
+    /// the time of the previous effect. The effect id is derived from
+    /// the row id, so it stays the same every time the row is loaded.
+    pub fn from_legacy(row_id: i64, effect: Effect, previous: Option<&StoredEffect>) -> Self {
+        let time = match effect {
+            Effect::AddTask(ref t) => Some(t.created),
+            Effect::ChangeTaskState(_, TaskState::Done(t)) => Some(t),
+            Effect::ChangeTaskState(_, TaskState::Canceled(t)) => Some(t),
+            _ => None,
+        }.or_else(|| previous.map(|p| p.meta.time))
+            .unwrap_or_else(|| chrono::Utc.timestamp(0, 0));
+
+        StoredEffect {
+            meta: EffectMeta {
+                uuid: Uuid::new_v5(&uuid::NAMESPACE_URL, &format!("rtask:legacy-effect:{}", row_id)),
+                time: time,
+                origin: "unknown".into(),
+                command: Uuid::nil(),
+            },
+            effect: effect,
+        }
+    }
+}
+
+/// Returns the hostname of this machine, used as the default origin
+/// of effects.
+pub fn hostname() -> DeviceId {
+    let mut buf = [0 as libc::c_char; 256];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) };
+    if ret == -1 {
+        warn!("Failed to get hostname: {:?}", io::Error::last_os_error());
+        return "unknown".into();
+    }
+    // Make sure the buffer is terminated even if the name got truncated
+    buf[buf.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
 }
 
 pub type ScopeName = String;
 pub type NumericalIds = HashMap<ScopeName, BTreeMap<u64, Uuid>>;
 
+/// Limits commands to the tasks with a tag, all tasks are in the
+/// `default` scope. Every scope has its own numerical ids.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Scope(pub Option<String>);
+
+impl Deref for Scope {
+    type Target = str;
+    fn deref(&self) -> &str {
+        // .as_ref().map(AsRef::as_ref), wtf?!
+        self.0.as_ref().map(AsRef::as_ref).unwrap_or("default")
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl Scope {
+    pub fn as_tag(&self) -> Option<Tag> {
+        self.0.clone()
+    }
+}
+
 pub struct Model {
     // TODO: hide `tasks` and add `archived_tasks`
     pub tasks: HashMap<Uuid, Task>,
-    pub applied_effects: Vec<Effect>,
+    pub applied_effects: Vec<StoredEffect>,
     pub numerical_ids: NumericalIds,
+    /// Device id recorded in the metadata of newly applied effects
+    pub origin: DeviceId,
 
+    command_id: CommandId,
     is_dirty: bool,
 }
 
@@ -93,23 +372,46 @@ impl Model {
             tasks: HashMap::new(),
             applied_effects: Vec::new(),
             numerical_ids: NumericalIds::new(),
+            origin: hostname(),
 
+            command_id: Uuid::new_v4(),
             is_dirty: false,
         }
     }
 
-    pub fn from_effects(effects: &[Effect]) -> Self {
+    pub fn from_effects(effects: &[StoredEffect]) -> Self {
         let mut model = Self::new();
         for effect in effects {
-            model.apply_effect(&effect)
+            model.apply_stored_effect(effect.clone())
         }
         model.is_dirty = false;
         model
     }
 
+    /// Starts a new command. All effects applied afterwards share the
+    /// returned `CommandId` in their metadata.
+    pub fn begin_command(&mut self) -> CommandId {
+        self.command_id = Uuid::new_v4();
+        self.command_id
+    }
+
     pub fn apply_effect(&mut self, effect: &Effect) -> () {
+        let meta = EffectMeta {
+            uuid: Uuid::new_v4(),
+            time: chrono::Utc::now(),
+            origin: self.origin.clone(),
+            command: self.command_id,
+        };
+
+        self.apply_stored_effect(StoredEffect {
+            meta: meta,
+            effect: effect.clone(),
+        });
+    }
+
+    fn apply_stored_effect(&mut self, stored: StoredEffect) -> () {
         use Effect::*;
-        match effect.clone() {
+        match stored.effect.clone() {
             AddTask(task) => {
                 self.add_task(task);
             }
@@ -129,9 +431,18 @@ impl Model {
             DeleteTask(uuid) => {
                 self.delete_task(&uuid);
             }
+            StartTask(uuid, time) => {
+                self.start_task(&uuid, time);
+            }
+            StopTask(uuid, time) => {
+                self.stop_task(&uuid, time);
+            }
+            ChangeTaskDate(uuid, kind, date) => {
+                self.change_task_date(&uuid, kind, date);
+            }
         }
 
-        self.applied_effects.push(effect.clone());
+        self.applied_effects.push(stored);
         self.is_dirty = true;
     }
 
@@ -153,6 +464,32 @@ impl Model {
         self.tasks.get_mut(u).expect("failed to get task").priority = priority;
     }
 
+    fn change_task_date(&mut self, u: &Uuid, kind: TaskDate, date: Option<Time>) {
+        self.tasks
+            .get_mut(u)
+            .expect("failed to get task")
+            .set_date(kind, date);
+    }
+
+    fn start_task(&mut self, u: &Uuid, time: Time) {
+        let task = self.tasks.get_mut(u).expect("failed to get task");
+        if !task.is_active() {
+            task.intervals.push(Interval {
+                start: time,
+                end: None,
+            });
+        }
+    }
+
+    fn stop_task(&mut self, u: &Uuid, time: Time) {
+        let task = self.tasks.get_mut(u).expect("failed to get task");
+        if let Some(interval) = task.intervals.last_mut() {
+            if interval.end.is_none() {
+                interval.end = Some(time);
+            }
+        }
+    }
+
     fn change_task_tags(&mut self, u: &Uuid, added: Tags, removed: Tags) {
         let ref mut tags = self.tasks.get_mut(u).expect("failed to get task").tags;
 
@@ -233,7 +570,8 @@ impl<'a> Iterator for TaskIter<'a> {
 impl Model {
     pub fn all_tasks<'a>(&'a self) -> TaskIter<'a> {
         let mut v: Vec<&Task> = self.tasks.values().collect();
-        v.sort_by(|a, b| b.cmp(a));
+        // The UUID makes the order of otherwise equal tasks predictable
+        v.sort_by(|a, b| b.cmp(a).then_with(|| a.uuid.cmp(&b.uuid)));
         TaskIter { tasks: v, pos: 0 }
     }
 
@@ -272,6 +610,11 @@ impl Model {
     pub fn is_dirty(&self) -> bool {
         self.is_dirty
     }
+
+    /// Called by storage engines once all changes are written
+    pub fn mark_saved(&mut self) {
+        self.is_dirty = false;
+    }
 }
 
 #[cfg(test)]
@@ -315,6 +658,115 @@ mod tests {
         assert_eq!(m.tasks[&uuid].priority, Priority::High);
     }
 
+    #[test]
+    fn test_change_task_date() {
+        let mut m = Model::new();
+        let t = Task::new("foo");
+        let uuid = t.uuid.clone();
+        m.add_task(t.clone());
+        assert_eq!(m.tasks[&uuid].wait, None);
+
+        let date = chrono::Utc::now();
+        m.change_task_date(&uuid, TaskDate::Wait, Some(date));
+        assert_eq!(m.tasks[&uuid].wait, Some(date));
+        assert_eq!(m.tasks[&uuid].scheduled, None);
+        m.change_task_date(&uuid, TaskDate::Wait, None);
+        assert_eq!(m.tasks[&uuid].wait, None);
+    }
+
+    #[test]
+    fn test_start_stop_task() {
+        let mut m = Model::new();
+        let t = Task::new("foo");
+        let uuid = t.uuid.clone();
+        m.add_task(t.clone());
+
+        let start = chrono::Utc::now();
+        m.start_task(&uuid, start);
+        assert_eq!(m.tasks[&uuid].active_since(), Some(start));
+        // Starting an active task doesn't open a second interval
+        m.start_task(&uuid, start + chrono::Duration::minutes(1));
+        assert_eq!(m.tasks[&uuid].intervals.len(), 1);
+
+        let end = start + chrono::Duration::minutes(5);
+        m.stop_task(&uuid, end);
+        assert!(!m.tasks[&uuid].is_active());
+        assert_eq!(
+            m.tasks[&uuid].intervals,
+            vec![Interval {
+                start: start,
+                end: Some(end),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_effect_diff() {
+        let mut m = Model::new();
+        let old = Task::new_with_tags("foo", vec!["a".to_string(), "b".into()].into_iter().collect());
+        m.apply_effect(&Effect::AddTask(old.clone()));
+
+        assert!(Effect::diff(&old, &old).is_empty());
+
+        let mut new = old.clone();
+        new.tags.remove("a");
+        new.tags.insert("c".into());
+        new.priority = Priority::High;
+        new.wait = Some(chrono::Utc::now());
+        new.status = TaskState::Done(chrono::Utc::now());
+        new.description = "ignored".into();
+
+        let effects = Effect::diff(&old, &new);
+        assert_eq!(4, effects.len());
+        for e in effects {
+            m.apply_effect(&e);
+        }
+
+        new.description = old.description.clone();
+        assert_eq!(Some(&new), m.get_task(&new.uuid));
+    }
+
+    #[test]
+    fn test_effect_meta() {
+        let mut m = Model::new();
+        let t = Task::new("foo");
+        m.apply_effect(&Effect::AddTask(t.clone()));
+        m.apply_effect(&Effect::ChangeTaskPriority(t.uuid, Priority::High));
+        let command = m.begin_command();
+        m.apply_effect(&Effect::DeleteTask(t.uuid));
+
+        let metas: Vec<_> = m.applied_effects.iter().map(|e| &e.meta).collect();
+        assert_eq!(metas[0].command, metas[1].command);
+        assert_eq!(metas[2].command, command);
+        assert!(metas[1].command != command);
+        assert!(metas[0].uuid != metas[1].uuid);
+        assert!(metas[0].time <= metas[2].time);
+        assert_eq!(metas[0].origin, m.origin);
+
+        // Replaying keeps the metadata intact
+        let m2 = Model::from_effects(&m.applied_effects);
+        assert_eq!(m2.applied_effects, m.applied_effects);
+    }
+
+    #[test]
+    fn test_legacy_effect_time() {
+        let t = Task::new("foo");
+        let add = StoredEffect::from_legacy(1, Effect::AddTask(t.clone()), None);
+        assert_eq!(add.meta.time, t.created);
+        let prio = StoredEffect::from_legacy(2, Effect::ChangeTaskPriority(t.uuid, Priority::Low), Some(&add));
+        assert_eq!(prio.meta.time, t.created);
+    }
+
+    #[test]
+    fn test_legacy_effect_id() {
+        let t = Task::new("foo");
+        let a = StoredEffect::from_legacy(1, Effect::AddTask(t.clone()), None);
+        let b = StoredEffect::from_legacy(1, Effect::AddTask(t.clone()), None);
+        let c = StoredEffect::from_legacy(2, Effect::AddTask(t.clone()), None);
+        assert_eq!(a.meta.uuid, b.meta.uuid);
+        assert!(a.meta.uuid != c.meta.uuid);
+    }
+
     #[test]
     fn test_numerical_ref() {
         assert_eq!(TaskRef::from_str("42"), Ok(TaskRef::Numerical(42)));
@@ -0,0 +1,153 @@
+//! Named task lists, invoked as `rtask <name>`
+
+use column::Column;
+use command::{Flag, ListOptions, Status};
+use group::Grouping;
+use sort::SortSpec;
+
+use std::str::FromStr;
+
+/// The report used by a plain `rtask` or `rtask list`
+pub const DEFAULT_REPORT: &'static str = "next";
+
+pub const BUILTIN_REPORTS: &'static [&'static str] = &["next", "all", "completed", "waiting"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub description: String,
+    /// Empty for the default columns
+    pub columns: Vec<Column>,
+    /// Flags every listed task must match
+    pub filter: Vec<Flag>,
+    /// Sorted by urgency if `None`
+    pub sort: Option<SortSpec>,
+    pub limit: Option<usize>,
+    pub group: Option<Grouping>,
+}
+
+impl Report {
+    pub fn builtin(name: &str) -> Option<Report> {
+        match name {
+            "next" => Some(Report {
+                description: "Open tasks by urgency".into(),
+                columns: vec![],
+                filter: vec![],
+                sort: None,
+                limit: None,
+                group: None,
+            }),
+            "all" => Some(Report {
+                description: "All tasks, including done and canceled ones".into(),
+                columns: vec![
+                    Column::Id,
+                    Column::Status,
+                    Column::Priority,
+                    Column::Age,
+                    Column::Description,
+                    Column::Tags,
+                ],
+                filter: vec![Flag::Status(Status::Any)],
+                sort: Some(SortSpec::from_str("created-").unwrap()),
+                limit: None,
+                group: None,
+            }),
+            "completed" => Some(Report {
+                description: "Done tasks, most recently done first".into(),
+                columns: vec![
+                    Column::ShortUuid,
+                    Column::End,
+                    Column::Priority,
+                    Column::Description,
+                    Column::Tags,
+                ],
+                filter: vec![Flag::Status(Status::Done)],
+                sort: Some(SortSpec::from_str("end-").unwrap()),
+                limit: None,
+                group: None,
+            }),
+            "waiting" => Some(Report {
+                description: "Tasks hidden until their wait date".into(),
+                columns: vec![
+                    Column::Id,
+                    Column::Wait,
+                    Column::Priority,
+                    Column::Description,
+                    Column::Tags,
+                ],
+                filter: vec![Flag::TagPositive("WAITING".into())],
+                sort: Some(SortSpec::from_str("wait+").unwrap()),
+                limit: None,
+                group: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Combines the report's filter with additional `flags`. Options
+    /// given explicitly take precedence over the report's settings.
+    pub fn list_command(&self, mut flags: Vec<Flag>, options: ListOptions) -> (Vec<Flag>, ListOptions) {
+        let mut filter = self.filter.clone();
+        filter.append(&mut flags);
+
+        let options = ListOptions {
+            columns: if options.columns.is_empty() {
+                self.columns.clone()
+            } else {
+                options.columns
+            },
+            sort: options.sort.or_else(|| self.sort.clone()),
+            limit: options.limit.or(self.limit),
+            group: options.group.or(self.group),
+            ..options
+        };
+        (filter, options)
+    }
+
+    /// Parses the whitespace separated flags of a report's filter
+    pub fn parse_filter(s: &str) -> Result<Vec<Flag>, String> {
+        s.split_whitespace()
+            .map(|f| Flag::from_str(f).ok_or_else(|| format!("Invalid flag {:?}", f)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin() {
+        for name in BUILTIN_REPORTS {
+            assert!(Report::builtin(name).is_some());
+        }
+        assert!(Report::builtin("bogus").is_none());
+
+        let (flags, options) = Report::builtin("completed")
+            .unwrap()
+            .list_command(vec![Flag::TagPositive("work".into())], Default::default());
+        assert_eq!(
+            vec![Flag::Status(Status::Done), Flag::TagPositive("work".into())],
+            flags
+        );
+        assert_eq!(Some(Column::End), options.sort.map(|s| s.0[0].column));
+
+        let explicit = ListOptions {
+            columns: vec![Column::Id],
+            limit: Some(3),
+            ..Default::default()
+        };
+        let (_, options) = Report::builtin("all").unwrap().list_command(vec![], explicit);
+        assert_eq!(vec![Column::Id], options.columns);
+        assert_eq!(Some(Column::Created), options.sort.map(|s| s.0[0].column));
+        assert_eq!(Some(3), options.limit);
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(
+            Ok(vec![Flag::TagPositive("work".into()), Flag::Status(Status::Any)]),
+            Report::parse_filter(" +work  status:any")
+        );
+        assert!(Report::parse_filter("+work bogus").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Ordering of task lists by column values
+
+use column::{Column, ColumnError};
+use model::Model;
+use task::Task;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Sorts by a single column, `due+` or `due` sorts ascending and
+/// `due-` descending. Tasks without a value always come last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: Column,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// Compares two tasks of `model`, numerical ids are looked up in
+    /// `scope`
+    pub fn compare(&self, a: &Task, b: &Task, model: &Model, scope: &str) -> Ordering {
+        match (
+            self.column.sort_value(a, model, scope),
+            self.column.sort_value(b, model, scope),
+        ) {
+            (Some(a), Some(b)) => {
+                let ordering = a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+                if self.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = ColumnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (column, descending) = if s.ends_with('-') {
+            (&s[..s.len() - 1], true)
+        } else if s.ends_with('+') {
+            (&s[..s.len() - 1], false)
+        } else {
+            (s, false)
+        };
+
+        Ok(SortKey {
+            column: Column::from_str(column)?,
+            descending: descending,
+        })
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.column, if self.descending { "-" } else { "+" })
+    }
+}
+
+/// A list of sort keys like `priority-,due+,description+`, later keys
+/// break ties of earlier ones
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SortSpec(pub Vec<SortKey>);
+
+impl SortSpec {
+    pub fn compare(&self, a: &Task, b: &Task, model: &Model, scope: &str) -> Ordering {
+        self.0
+            .iter()
+            .map(|key| key.compare(a, b, model, scope))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// Sorts `tasks` in place. The sort is stable, tasks comparing
+    /// equal keep their order.
+    pub fn sort(&self, tasks: &mut [&Task], model: &Model, scope: &str) {
+        tasks.sort_by(|a, b| self.compare(a, b, model, scope));
+    }
+}
+
+impl FromStr for SortSpec {
+    type Err = ColumnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|k| !k.trim().is_empty())
+            .map(SortKey::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .map(SortSpec)
+    }
+}
+
+impl fmt::Display for SortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys: Vec<String> = self.0.iter().map(|k| k.to_string()).collect();
+        f.write_str(&keys.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono;
+    use task::Priority;
+
+    fn descriptions(tasks: &[&Task]) -> Vec<String> {
+        tasks.iter().map(|t| t.description.clone()).collect()
+    }
+
+    #[test]
+    fn test_sort_key() {
+        let mut a = Task::new("a");
+        let b = Task::new("b");
+        let mut c = Task::new("c");
+        a.due = Some(chrono::Utc::now());
+        c.due = Some(chrono::Utc::now() - chrono::Duration::days(1));
+        let model = Model::new();
+
+        let mut tasks = vec![&a, &b, &c];
+        SortSpec::from_str("due").unwrap().sort(&mut tasks, &model, "default");
+        assert_eq!(vec!["c", "a", "b"], descriptions(&tasks));
+
+        // Tasks without a due date stay last
+        SortSpec::from_str("due-").unwrap().sort(&mut tasks, &model, "default");
+        assert_eq!(vec!["a", "c", "b"], descriptions(&tasks));
+
+        assert!(SortKey::from_str("bogus+").is_err());
+    }
+
+    #[test]
+    fn test_sort_spec() {
+        let mut a = Task::new("a");
+        let mut b = Task::new("B");
+        let c = Task::new("c");
+        let d = Task::new("c");
+        a.priority = Priority::High;
+        b.priority = Priority::High;
+        let model = Model::new();
+
+        let spec = SortSpec::from_str("priority-,description+").unwrap();
+        assert_eq!(2, spec.0.len());
+        assert_eq!("pri-,desc+", spec.to_string());
+
+        // Ties keep their original order
+        let mut tasks = vec![&d, &c, &b, &a];
+        spec.sort(&mut tasks, &model, "default");
+        assert_eq!(vec!["a", "B", "c", "c"], descriptions(&tasks));
+        assert_eq!(d.uuid, tasks[2].uuid);
+
+        assert!(SortSpec::from_str("pri-,bogus").is_err());
+    }
+
+    #[test]
+    fn test_sort_by_id() {
+        let a = Task::new("a");
+        let b = Task::new("b");
+        let c = Task::new("c");
+        let mut model = Model::new();
+        for t in &[&a, &b, &c] {
+            model.apply_effect(&::Effect::AddTask((*t).clone()));
+        }
+        model.recalculate_numerical_ids("default", &[b.uuid, a.uuid]);
+
+        // Tasks without a numerical id come last
+        let mut tasks = vec![&c, &a, &b];
+        SortSpec::from_str("id").unwrap().sort(&mut tasks, &model, "default");
+        assert_eq!(vec!["b", "a", "c"], descriptions(&tasks));
+    }
+}
@@ -7,7 +7,7 @@ use rusqlite;
 use rusqlite::Connection;
 
 use StorageEngine;
-use {Effect, Model, Uuid};
+use {Effect, Model, StoredEffect, Uuid};
 
 pub struct SqliteStorage {
     model: Model,
@@ -52,16 +52,24 @@ impl SqliteStorage {
         Ok(())
     }
 
-    fn query_effects(db: &Connection) -> Result<Vec<Effect>, Error> {
+    fn query_effects(db: &Connection) -> Result<Vec<StoredEffect>, Error> {
         let mut stmt = try!(db.prepare("select * from effects order by id"));
 
-        let rows = stmt.query_map(&[], |row| row.get(1))?;
-        let effects: Vec<Effect> = rows.map(|json_str| {
-            let json_str: String = json_str?;
-            let json = serde_json::from_str(&json_str)?;
-
-            Ok(json)
-        }).collect::<Result<Vec<_>, Error>>()?;
+        let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1)))?;
+        let mut effects: Vec<StoredEffect> = vec![];
+        for row in rows {
+            let (id, json_str): (i64, String) = row?;
+            // Effects stored before the introduction of `EffectMeta`
+            // are bare `Effect`s
+            let effect = match serde_json::from_str(&json_str) {
+                Ok(stored) => stored,
+                Err(_) => {
+                    let effect: Effect = serde_json::from_str(&json_str)?;
+                    StoredEffect::from_legacy(id, effect, effects.last())
+                }
+            };
+            effects.push(effect);
+        }
 
         debug!("effects: #{:?}", effects);
 
@@ -115,15 +123,19 @@ impl SqliteStorage {
     }
 
     pub fn new_in_memory() -> Self {
+        let mut db = Connection::open_in_memory().unwrap();
+        Self::initialize_db(&mut db).unwrap();
+
         SqliteStorage {
             model: Model::new(),
-            db: Connection::open_in_memory().unwrap(),
+            db: db,
         }
     }
 }
 
 impl StorageEngine for SqliteStorage {
     type LoadErr = Error;
+    type SaveErr = Error;
 
     fn new() -> Result<Self, Self::LoadErr> {
         Self::load_from("store.sqlite")
@@ -132,45 +144,47 @@ impl StorageEngine for SqliteStorage {
     fn model<'a>(&'a mut self) -> &'a mut Model {
         &mut self.model
     }
-}
 
-impl Drop for SqliteStorage {
-    fn drop(&mut self) {
+    fn save(&mut self) -> Result<(), Self::SaveErr> {
         if !self.model.is_dirty() {
             info!("Not serializing as model isn't dirty");
-            return;
+            return Ok(());
         }
 
-        // Ugh
-        let tx = self.db.transaction().expect("Failed to create transacton");
+        let tx = self.db.transaction()?;
 
-        let row_count: i64 = tx.query_row("select count(id) from effects", &[], |row| row.get(0))
-            .unwrap();
+        let row_count: i64 = tx.query_row("select count(id) from effects", &[], |row| row.get(0))?;
         debug!("Got {} rows", row_count);
 
         for effect in self.model.applied_effects.iter().skip(row_count as usize) {
-            let json = serde_json::to_string(&effect).unwrap();
+            let json = serde_json::to_string(&effect)?;
             debug!("Inserting JSON: {:?}", json);
-            tx.execute("insert into effects (json) values ($1)", &[&json])
-                .unwrap();
+            tx.execute("insert into effects (json) values ($1)", &[&json])?;
         }
 
         debug!("Storing numerical_ids");
-        tx.execute("delete from numerical_ids", &[])
-            .expect("Failed to clear numerical_ids");
+        tx.execute("delete from numerical_ids", &[])?;
 
         for (scope, ids) in self.model.numerical_ids.iter() {
             for (n, uuid) in ids {
                 let n = *n as i64;
-                let uuid = serde_json::to_string(&uuid).unwrap();
+                let uuid = serde_json::to_string(&uuid)?;
                 tx.execute(
                     "insert into numerical_ids (scope, id, uuid) values ($1, $2, $3)",
                     &[scope, &n, &uuid],
-                ).expect("Failed to insert numerical id");
+                )?;
             }
         }
 
-        tx.commit().expect("Failed to commit transaction");
+        tx.commit()?;
+        self.model.mark_saved();
+        Ok(())
+    }
+}
+
+impl Drop for SqliteStorage {
+    fn drop(&mut self) {
+        self.save().expect("Failed to save model");
     }
 }
 
@@ -204,7 +218,46 @@ mod tests {
         let store = SqliteStorage::load_from(&tempfile).unwrap();
         assert_eq!(1, store.model.tasks.len());
         assert_eq!(Some(&task), store.model.tasks.get(&task.uuid));
+        assert_eq!(1, store.model.applied_effects.len());
+        assert!(task.created <= store.model.applied_effects[0].meta.time);
 
         fs::remove_file(tempfile).unwrap();
     }
+
+    #[test]
+    fn test_save() {
+        use std::{env, fs};
+
+        let mut tempfile = env::temp_dir();
+        tempfile.push("tasks_save.bin");
+        let _ = fs::remove_file(&tempfile);
+
+        // Saving repeatedly only appends the new effects
+        let mut store = SqliteStorage::load_from(&tempfile).unwrap();
+        store.model.apply_effect(&Effect::AddTask(Task::new("first")));
+        store.save().unwrap();
+        assert!(!store.model.is_dirty());
+        store.model.apply_effect(&Effect::AddTask(Task::new("second")));
+        store.save().unwrap();
+
+        let effects = SqliteStorage::query_effects(&store.db).unwrap();
+        assert_eq!(store.model.applied_effects, effects);
+
+        fs::remove_file(tempfile).unwrap();
+    }
+
+    #[test]
+    fn test_load_legacy_effects() {
+        let task = Task::new("legacy");
+        let mut db = Connection::open_in_memory().unwrap();
+        SqliteStorage::initialize_db(&mut db).unwrap();
+        let json = serde_json::to_string(&Effect::AddTask(task.clone())).unwrap();
+        db.execute("insert into effects (json) values ($1)", &[&json])
+            .unwrap();
+
+        let effects = SqliteStorage::query_effects(&db).unwrap();
+        assert_eq!(1, effects.len());
+        assert_eq!(Effect::AddTask(task.clone()), effects[0].effect);
+        assert_eq!(task.created, effects[0].meta.time);
+    }
 }
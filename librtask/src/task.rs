@@ -1,5 +1,7 @@
 use chrono;
+use std::cmp;
 use std::collections::{HashMap, HashSet};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 use uuid;
 
 pub type Title = String;
@@ -9,7 +11,7 @@ pub type Tag = String;
 pub type Tags = HashSet<Tag>;
 pub type ExtraMap = HashMap<ExtraData, String>;
 
-pub struct Age(chrono::Duration);
+pub struct Age(pub chrono::Duration);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskState {
@@ -61,6 +63,41 @@ pub enum ExtraData {
     Notes = 1,
 }
 
+/// A span of time spent working on a task. `end` is `None` while the
+/// task is active.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interval {
+    pub start: Time,
+    pub end: Option<Time>,
+}
+
+impl Interval {
+    /// The part of this interval lying within `from` and `to`. Open
+    /// intervals are treated as ending at `to`.
+    pub fn duration_between(&self, from: Time, to: Time) -> chrono::Duration {
+        let start = cmp::max(self.start, from);
+        let end = cmp::min(self.end.unwrap_or(to), to);
+        if end > start {
+            end - start
+        } else {
+            chrono::Duration::zero()
+        }
+    }
+}
+
+/// The optional dates a task can carry
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TaskDate {
+    /// Hide the task until this date has passed
+    Wait,
+    /// Don't consider the task urgent before this date
+    Scheduled,
+    /// The task has to be done by this date
+    Due,
+}
+
+pub const TASK_DATES: &'static [TaskDate] = &[TaskDate::Wait, TaskDate::Scheduled, TaskDate::Due];
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Task {
     pub description: Title,
@@ -71,8 +108,19 @@ pub struct Task {
     pub uuid: Uuid,
     pub tags: Tags,
     pub extras: ExtraMap,
+    #[serde(default)]
+    pub intervals: Vec<Interval>,
+    #[serde(default)]
+    pub wait: Option<Time>,
+    #[serde(default)]
+    pub scheduled: Option<Time>,
+    #[serde(default)]
+    pub due: Option<Time>,
 }
 
+/// Tags which aren't stored but derived from a task's properties
+pub const VIRTUAL_TAGS: &'static [&'static str] = &["ACTIVE", "WAITING", "SCHEDULED"];
+
 impl Task {
     pub fn new(description: &str) -> Self {
         let now = chrono::Utc::now();
@@ -85,6 +133,10 @@ impl Task {
             uuid: Uuid::new_v4(),
             tags: Tags::new(),
             extras: ExtraMap::new(),
+            intervals: vec![],
+            wait: None,
+            scheduled: None,
+            due: None,
         }
     }
 
@@ -103,16 +155,54 @@ impl Task {
         urgency += days / 100.0; // Add 0.01 for every day since creation
         urgency += f32::from(self.priority); // Add priority
         urgency += self.tags.len() as f32 / 1000.0;
+        if self.is_scheduled() {
+            urgency -= 10.0; // Sort below everything that's due now
+        }
 
         urgency
     }
 
+    pub fn date(&self, kind: TaskDate) -> Option<Time> {
+        match kind {
+            TaskDate::Wait => self.wait,
+            TaskDate::Scheduled => self.scheduled,
+            TaskDate::Due => self.due,
+        }
+    }
+
+    pub fn set_date(&mut self, kind: TaskDate, date: Option<Time>) {
+        match kind {
+            TaskDate::Wait => self.wait = date,
+            TaskDate::Scheduled => self.scheduled = date,
+            TaskDate::Due => self.due = date,
+        }
+    }
+
+    /// True if the task has a wait date in the future
+    pub fn is_waiting(&self) -> bool {
+        self.wait.map_or(false, |t| t > chrono::Utc::now())
+    }
+
+    /// True if the task is scheduled for a date in the future
+    pub fn is_scheduled(&self) -> bool {
+        self.scheduled.map_or(false, |t| t > chrono::Utc::now())
+    }
+
+    /// True if the task is still open after its due date
+    pub fn is_overdue(&self) -> bool {
+        self.is_open() && self.due.map_or(false, |t| t < chrono::Utc::now())
+    }
+
     pub fn age(&self) -> Age {
         Age(chrono::Utc::now() - self.created)
     }
 
     pub fn short_id(&self) -> String {
-        let mut s = self.uuid.simple().to_string();
+        Self::short_uuid(&self.uuid)
+    }
+
+    pub fn short_uuid(uuid: &Uuid) -> String {
+        let mut s = uuid.simple().to_string();
         s.truncate(6);
         s
     }
@@ -124,9 +214,57 @@ impl Task {
             TaskState::Canceled(_) => false,
         }
     }
+
+    /// When the task was marked as done or canceled
+    pub fn closed(&self) -> Option<Time> {
+        match self.status {
+            TaskState::Open => None,
+            TaskState::Done(t) | TaskState::Canceled(t) => Some(t),
+        }
+    }
+
+    /// Returns the start of the currently running interval, if any
+    pub fn active_since(&self) -> Option<Time> {
+        self.intervals
+            .last()
+            .and_then(|i| if i.end.is_none() { Some(i.start) } else { None })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active_since().is_some()
+    }
+
+    pub fn active_for(&self) -> Option<Age> {
+        self.active_since().map(|t| Age(chrono::Utc::now() - t))
+    }
+
+    /// Total time spent on this task between `from` and `to`
+    pub fn time_spent_between(&self, from: Time, to: Time) -> chrono::Duration {
+        self.intervals
+            .iter()
+            .fold(chrono::Duration::zero(), |acc, i| acc + i.duration_between(from, to))
+    }
+
+    pub fn virtual_tags(&self) -> Tags {
+        let mut tags = Tags::new();
+        if self.is_active() {
+            tags.insert("ACTIVE".into());
+        }
+        if self.is_waiting() {
+            tags.insert("WAITING".into());
+        }
+        if self.is_scheduled() {
+            tags.insert("SCHEDULED".into());
+        }
+        tags
+    }
+
+    /// Checks for `tag` in both the regular and virtual tags
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag) || (VIRTUAL_TAGS.contains(&tag) && self.virtual_tags().contains(tag))
+    }
 }
 
-use std::cmp;
 impl cmp::PartialOrd for Task {
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
         Some(self.cmp(&other))
@@ -176,6 +314,11 @@ pub trait StringExt {
         })
     }
 
+    /// The number of terminal columns the string occupies, wide
+    /// characters take two columns and combining ones none
+    fn display_width(&self) -> usize;
+    /// The longest prefix that fits into `max_width` columns
+    fn truncate_width<'a>(&'a self, max_width: usize) -> &'a str;
     fn ellipsize<'a>(&'a self, max_width: usize) -> Cow<'a, str>;
 }
 
@@ -195,26 +338,34 @@ impl StringExt for str {
             })
     }
 
+    fn display_width(&self) -> usize {
+        UnicodeWidthStr::width(self)
+    }
+
+    fn truncate_width<'a>(&'a self, max_width: usize) -> &'a str {
+        let mut width = 0;
+        for (i, c) in self.char_indices() {
+            width += c.width().unwrap_or(0);
+            if width > max_width {
+                return &self[..i];
+            }
+        }
+        self
+    }
+
     fn ellipsize<'a>(&'a self, max_width: usize) -> Cow<'a, str> {
         assert!(max_width > 0);
 
         let ellipsis = "...";
 
-        if self.len() == 0 {
-            self.into()
-        } else if self.len() <= max_width {
+        if self.display_width() <= max_width {
             self.into()
-        } else {
-            let nchars = if max_width > ellipsis.len() {
-                max_width - ellipsis.len()
-            } else {
-                max_width
-            };
-            let mut s: String = self.chars().take(nchars).collect();
-            if nchars < max_width {
-                s.push_str(ellipsis);
-            }
+        } else if max_width > ellipsis.len() {
+            let mut s = self.truncate_width(max_width - ellipsis.len()).to_string();
+            s.push_str(ellipsis);
             s.into()
+        } else {
+            self.truncate_width(max_width).into()
         }
     }
 }
@@ -241,6 +392,28 @@ impl fmt::Display for Age {
     }
 }
 
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let s = match *self {
+            TaskState::Open => "open",
+            TaskState::Done(_) => "done",
+            TaskState::Canceled(_) => "canceled",
+        };
+        f.write_str(s)
+    }
+}
+
+impl fmt::Display for TaskDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let s = match *self {
+            TaskDate::Wait => "wait",
+            TaskDate::Scheduled => "scheduled",
+            TaskDate::Due => "due",
+        };
+        f.write_str(s)
+    }
+}
+
 impl fmt::Display for Priority {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let s = match *self {
@@ -271,6 +444,76 @@ mod tests {
         assert_eq!(t.tags, tags);
     }
 
+    #[test]
+    fn test_intervals() {
+        use chrono::Duration;
+
+        let now = chrono::Utc::now();
+        let mut t = Task::new("foo");
+        assert!(!t.is_active());
+        assert!(!t.has_tag("ACTIVE"));
+
+        t.intervals.push(Interval {
+            start: now - Duration::hours(3),
+            end: Some(now - Duration::hours(2)),
+        });
+        t.intervals.push(Interval {
+            start: now - Duration::minutes(30),
+            end: None,
+        });
+        assert!(t.is_active());
+        assert!(t.has_tag("ACTIVE"));
+        assert_eq!(t.active_since(), Some(now - Duration::minutes(30)));
+
+        assert_eq!(
+            t.time_spent_between(now - Duration::days(1), now),
+            Duration::minutes(90)
+        );
+        assert_eq!(
+            t.time_spent_between(now - Duration::minutes(150), now - Duration::minutes(10)),
+            Duration::minutes(50)
+        );
+        assert_eq!(
+            t.time_spent_between(now - Duration::days(2), now - Duration::days(1)),
+            Duration::zero()
+        );
+    }
+
+    #[test]
+    fn test_wait_scheduled() {
+        use chrono::Duration;
+
+        let now = chrono::Utc::now();
+        let mut t = Task::new("foo");
+        let urgency = t.urgency();
+        assert!(!t.has_tag("WAITING") && !t.has_tag("SCHEDULED"));
+
+        t.set_date(TaskDate::Wait, Some(now + Duration::days(1)));
+        assert!(t.is_waiting() && t.has_tag("WAITING"));
+        t.set_date(TaskDate::Wait, Some(now - Duration::days(1)));
+        assert!(!t.is_waiting());
+
+        t.set_date(TaskDate::Scheduled, Some(now + Duration::days(1)));
+        assert!(t.is_scheduled() && t.has_tag("SCHEDULED"));
+        assert!(t.urgency() < urgency);
+        assert_eq!(t.date(TaskDate::Scheduled), Some(now + Duration::days(1)));
+    }
+
+    #[test]
+    fn test_due() {
+        use chrono::Duration;
+
+        let now = chrono::Utc::now();
+        let mut t = Task::new("foo");
+        t.due = Some(now + Duration::days(3));
+        assert!(!t.is_overdue());
+
+        t.due = Some(now - Duration::days(1));
+        assert!(t.is_overdue());
+        t.status = TaskState::Done(now);
+        assert!(!t.is_overdue());
+    }
+
     #[test]
     fn test_urgency() {
         use chrono::Duration;
@@ -357,5 +600,21 @@ mod tests {
         assert_eq!("foo".ellipsize(100), "foo");
         assert_eq!("foobar".ellipsize(6), "foobar");
         assert_eq!("foobar 123".ellipsize(6), "foo...");
+
+        // Wide characters take two columns, combining ones none
+        assert_eq!("日本語のタスク".ellipsize(7), "日本...");
+        assert_eq!("日本語".ellipsize(3), "日");
+        assert_eq!("cafe\u{301} au lait".ellipsize(7), "cafe\u{301}...");
+        assert_eq!("cafe\u{301}".ellipsize(4), "cafe\u{301}");
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(3, "foo".display_width());
+        assert_eq!(6, "日本語".display_width());
+        assert_eq!(4, "cafe\u{301}".display_width());
+        assert_eq!("日", "日本語".truncate_width(3));
+        assert_eq!("", "日本語".truncate_width(1));
+        assert_eq!("e\u{301}", "e\u{301}x".truncate_width(1));
     }
 }
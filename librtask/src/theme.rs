@@ -0,0 +1,422 @@
+//! Terminal styles of semantic roles like "high priority" or "header",
+//! independent of any terminal library
+//!
+//! Styles are written like `bold red on rgb(40,40,40)`. Colors are one
+//! of the 16 named ones (`red`, `bright-red`, ...), `color0` to
+//! `color255` or `#rrggbb`/`rgb(r,g,b)`. Colors are reduced to what
+//! the terminal supports before printing.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub const THEMES: &'static [&'static str] = &["default", "light", "basic", "none"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Header,
+    GroupHeader,
+    AlternateRow,
+    HighPriority,
+    LowPriority,
+    Active,
+    Overdue,
+}
+
+/// How many colors the terminal can show
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ColorDepth {
+    /// No escape codes at all, e.g. for pipes and dumb terminals
+    None,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// One of the 16 basic colors, 8-15 are the bright variants
+    Ansi(u8),
+    /// An index into the 256 color palette
+    Fixed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Theme {
+    pub styles: BTreeMap<Role, Style>,
+}
+
+#[derive(Debug, PartialEq, Eq, Fail)]
+pub enum ThemeError {
+    #[fail(display = "Unknown theme {:?}", _0)]
+    UnknownTheme(String),
+    #[fail(display = "Unknown role {:?}", _0)]
+    UnknownRole(String),
+    #[fail(display = "Invalid style {:?}", _0)]
+    InvalidStyle(String),
+}
+
+const COLOR_NAMES: &'static [&'static str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// The xterm defaults of the 16 basic colors
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+    fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            Color::Ansi(n) => ANSI_RGB[(n % 16) as usize],
+            Color::Fixed(n) if n < 16 => ANSI_RGB[n as usize],
+            Color::Fixed(n) if n < 232 => {
+                let n = n - 16;
+                (
+                    CUBE_LEVELS[(n / 36) as usize],
+                    CUBE_LEVELS[(n / 6 % 6) as usize],
+                    CUBE_LEVELS[(n % 6) as usize],
+                )
+            }
+            Color::Fixed(n) => {
+                let v = 8 + (n - 232) * 10;
+                (v, v, v)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// The closest color the terminal can show, `None` if it can't
+    /// show colors at all
+    pub fn reduce(&self, depth: ColorDepth) -> Option<Color> {
+        match (depth, *self) {
+            (ColorDepth::None, _) => None,
+            (_, Color::Ansi(_)) | (ColorDepth::TrueColor, _) => Some(*self),
+            (ColorDepth::Ansi256, Color::Fixed(_)) => Some(*self),
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Some(Color::Fixed(nearest_fixed(r, g, b))),
+            (ColorDepth::Ansi16, _) => {
+                let (r, g, b) = self.rgb();
+                let nearest = (0..16)
+                    .min_by_key(|n| distance((r, g, b), ANSI_RGB[*n as usize]))
+                    .unwrap_or(7);
+                Some(Color::Ansi(nearest))
+            }
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// The closest entry of the 6x6x6 color cube or the grayscale ramp
+fn nearest_fixed(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| {
+        (0..6)
+            .min_by_key(|n| (i32::from(CUBE_LEVELS[*n]) - i32::from(v)).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+
+    let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+    let gray = 232 + ((average.max(8) - 8) / 10).min(23) as u8;
+
+    if distance((r, g, b), Color::Fixed(gray).rgb()) < distance((r, g, b), Color::Fixed(cube).rgb()) {
+        gray
+    } else {
+        cube
+    }
+}
+
+impl FromStr for Color {
+    type Err = ThemeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ThemeError::InvalidStyle(s.into());
+        let s = s.trim().to_lowercase();
+
+        let (bright, name) = if s.starts_with("bright-") {
+            (8, &s[7..])
+        } else {
+            (0, &s[..])
+        };
+        let name = if name == "purple" { "magenta" } else { name };
+        if let Some(n) = COLOR_NAMES.iter().position(|c| *c == name) {
+            return Ok(Color::Ansi(n as u8 + bright));
+        }
+
+        if s.starts_with("color") {
+            return s[5..].parse().map(Color::Fixed).map_err(|_| invalid());
+        }
+
+        if s.starts_with('#') && s.len() == 7 {
+            let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| invalid());
+            return Ok(Color::Rgb(channel(1)?, channel(3)?, channel(5)?));
+        }
+
+        if s.starts_with("rgb(") && s.ends_with(')') {
+            let channels = s[4..s.len() - 1]
+                .split(',')
+                .map(|c| c.trim().parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+            if channels.len() == 3 {
+                return Ok(Color::Rgb(channels[0], channels[1], channels[2]));
+            }
+        }
+
+        Err(invalid())
+    }
+}
+
+impl Style {
+    /// `other` on top of `self`, colors and attributes set in `other`
+    /// win
+    pub fn merge(&self, other: &Style) -> Style {
+        Style {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            bold: self.bold || other.bold,
+            dim: self.dim || other.dim,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+            reverse: self.reverse || other.reverse,
+        }
+    }
+
+    /// Reduces the colors to `depth`, nothing is left of the style if
+    /// the terminal can't handle escape codes
+    pub fn reduce(&self, depth: ColorDepth) -> Style {
+        if depth == ColorDepth::None {
+            return Style::default();
+        }
+        Style {
+            fg: self.fg.and_then(|c| c.reduce(depth)),
+            bg: self.bg.and_then(|c| c.reduce(depth)),
+            ..*self
+        }
+    }
+}
+
+impl FromStr for Style {
+    type Err = ThemeError;
+
+    /// Parses styles like `bold red on rgb(40,40,40)`, `none` is the
+    /// empty style
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Style::default();
+        let mut background = false;
+
+        // Keep `rgb(r, g, b)` together even with spaces after commas
+        let normalized = s.replace(", ", ",");
+        for word in normalized.split_whitespace() {
+            match &word.to_lowercase()[..] {
+                "none" => (),
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                "on" => background = true,
+                color => {
+                    let color = Color::from_str(color).map_err(|_| ThemeError::InvalidStyle(s.into()))?;
+                    if background {
+                        style.bg = Some(color);
+                    } else {
+                        style.fg = Some(color);
+                    }
+                    background = false;
+                }
+            }
+        }
+
+        if background {
+            return Err(ThemeError::InvalidStyle(s.into()));
+        }
+        Ok(style)
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let styles: &[(Role, &str)] = match name {
+            "default" => &[
+                (Role::Header, "bold underline"),
+                (Role::GroupHeader, "bold"),
+                (Role::AlternateRow, "on rgb(40,40,40)"),
+                (Role::HighPriority, "rgb(250,50,50)"),
+                (Role::LowPriority, "rgb(150,150,150)"),
+                (Role::Active, "bold"),
+                (Role::Overdue, "underline"),
+            ],
+            "light" => &[
+                (Role::Header, "bold underline"),
+                (Role::GroupHeader, "bold"),
+                (Role::AlternateRow, "on rgb(230,230,230)"),
+                (Role::HighPriority, "rgb(200,0,0)"),
+                (Role::LowPriority, "rgb(120,120,120)"),
+                (Role::Active, "bold"),
+                (Role::Overdue, "underline"),
+            ],
+            // Only the 16 colors every terminal has
+            "basic" => &[
+                (Role::Header, "bold underline"),
+                (Role::GroupHeader, "bold"),
+                (Role::HighPriority, "red"),
+                (Role::LowPriority, "bright-black"),
+                (Role::Active, "bold"),
+                (Role::Overdue, "underline"),
+            ],
+            "none" => &[],
+            _ => return None,
+        };
+
+        Some(Theme {
+            styles: styles
+                .iter()
+                .map(|&(role, style)| (role, Style::from_str(style).unwrap()))
+                .collect(),
+        })
+    }
+
+    pub fn style(&self, role: Role) -> Style {
+        self.styles.get(&role).cloned().unwrap_or_default()
+    }
+
+    /// Replaces the styles of some roles, e.g. `{"overdue": "bold red"}`
+    pub fn with_styles<'a, I>(mut self, styles: I) -> Result<Theme, ThemeError>
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+    {
+        for (role, style) in styles {
+            self.styles.insert(Role::from_str(role)?, Style::from_str(style)?);
+        }
+        Ok(self)
+    }
+}
+
+impl FromStr for Role {
+    type Err = ThemeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "header" => Ok(Role::Header),
+            "group" => Ok(Role::GroupHeader),
+            "row.alternate" => Ok(Role::AlternateRow),
+            "priority.high" => Ok(Role::HighPriority),
+            "priority.low" => Ok(Role::LowPriority),
+            "active" => Ok(Role::Active),
+            "overdue" => Ok(Role::Overdue),
+            _ => Err(ThemeError::UnknownRole(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Role::Header => "header",
+            Role::GroupHeader => "group",
+            Role::AlternateRow => "row.alternate",
+            Role::HighPriority => "priority.high",
+            Role::LowPriority => "priority.low",
+            Role::Active => "active",
+            Role::Overdue => "overdue",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(
+            Ok(Style {
+                fg: Some(Color::Ansi(9)),
+                bg: Some(Color::Rgb(40, 40, 40)),
+                bold: true,
+                ..Default::default()
+            }),
+            Style::from_str("bold bright-red on rgb(40, 40, 40)")
+        );
+        assert_eq!(
+            Ok(Style {
+                fg: Some(Color::Fixed(208)),
+                bg: Some(Color::Rgb(0x12, 0xab, 0xff)),
+                ..Default::default()
+            }),
+            Style::from_str("color208 on #12abFF")
+        );
+        assert_eq!(Ok(Style::default()), Style::from_str("none"));
+        assert!(Style::from_str("bold on").is_err());
+        assert!(Style::from_str("rgb(1,2)").is_err());
+        assert!(Style::from_str("color256").is_err());
+    }
+
+    #[test]
+    fn test_reduce() {
+        let grey = Color::Rgb(40, 40, 40);
+        assert_eq!(Some(grey), grey.reduce(ColorDepth::TrueColor));
+        assert_eq!(Some(Color::Fixed(235)), grey.reduce(ColorDepth::Ansi256));
+        assert_eq!(Some(Color::Ansi(0)), grey.reduce(ColorDepth::Ansi16));
+        assert_eq!(None, grey.reduce(ColorDepth::None));
+
+        let red = Color::Rgb(250, 50, 50);
+        assert_eq!(Some(Color::Fixed(203)), red.reduce(ColorDepth::Ansi256));
+        assert_eq!(Some(Color::Ansi(9)), red.reduce(ColorDepth::Ansi16));
+        assert_eq!(Some(Color::Ansi(1)), Color::Fixed(1).reduce(ColorDepth::Ansi16));
+
+        let style = Style::from_str("bold red").unwrap();
+        assert_eq!(Style::default(), style.reduce(ColorDepth::None));
+        assert_eq!(style, style.reduce(ColorDepth::Ansi16));
+    }
+
+    #[test]
+    fn test_theme() {
+        for name in THEMES {
+            assert!(Theme::builtin(name).is_some());
+        }
+        assert!(Theme::builtin("bogus").is_none());
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert("overdue".to_string(), "bold red".to_string());
+        let theme = Theme::builtin("none").unwrap().with_styles(&overrides).unwrap();
+        assert_eq!(Style::from_str("red bold").unwrap(), theme.style(Role::Overdue));
+        assert_eq!(Style::default(), theme.style(Role::Header));
+
+        overrides.insert("bogus".to_string(), "red".to_string());
+        assert!(Theme::default().with_styles(&overrides).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use chrono::Duration;
+use model::Model;
+use task::{Tag, Time, Uuid};
+
+use std::collections::BTreeMap;
+
+/// Time spent on tasks within a given time range
+pub struct Timesheet {
+    pub from: Time,
+    pub to: Time,
+    /// Time per task, longest first
+    pub tasks: Vec<(Uuid, Duration)>,
+    pub tags: BTreeMap<Tag, Duration>,
+    pub total: Duration,
+}
+
+impl Timesheet {
+    pub fn new(model: &Model, from: Time, to: Time) -> Self {
+        let mut tasks = vec![];
+        let mut tags = BTreeMap::new();
+        let mut total = Duration::zero();
+
+        for task in model.all_tasks() {
+            let spent = task.time_spent_between(from, to);
+            if spent <= Duration::zero() {
+                continue;
+            }
+
+            for tag in task.tags.iter() {
+                let sum = tags.entry(tag.clone()).or_insert(Duration::zero());
+                *sum = *sum + spent;
+            }
+
+            total = total + spent;
+            tasks.push((task.uuid, spent));
+        }
+
+        tasks.sort_by(|a, b| b.1.cmp(&a.1));
+
+        Timesheet {
+            from: from,
+            to: to,
+            tasks: tasks,
+            tags: tags,
+            total: total,
+        }
+    }
+}
+
+/// Formats a duration as hours and minutes, e.g. `3h 05m`
+pub fn format_duration(d: &Duration) -> String {
+    format!("{}h {:02}m", d.num_hours(), d.num_minutes() % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono;
+    use {Effect, Task};
+
+    #[test]
+    fn test_timesheet() {
+        let now = chrono::Utc::now();
+        let mut m = Model::new();
+
+        let mut t1 = Task::new("foo");
+        t1.tags.insert("work".into());
+        let t2 = Task::new("bar");
+        let t3 = Task::new("idle");
+        for t in vec![&t1, &t2, &t3] {
+            m.apply_effect(&Effect::AddTask(t.clone()));
+        }
+
+        m.apply_effect(&Effect::StartTask(t1.uuid, now - Duration::hours(3)));
+        m.apply_effect(&Effect::StopTask(t1.uuid, now - Duration::hours(1)));
+        m.apply_effect(&Effect::StartTask(t2.uuid, now - Duration::minutes(30)));
+
+        let sheet = Timesheet::new(&m, now - Duration::days(1), now);
+        assert_eq!(
+            sheet.tasks,
+            vec![(t1.uuid, Duration::hours(2)), (t2.uuid, Duration::minutes(30))]
+        );
+        assert_eq!(sheet.tags.get("work"), Some(&Duration::hours(2)));
+        assert_eq!(sheet.total, Duration::minutes(150));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(&Duration::minutes(185)), "3h 05m");
+        assert_eq!(format_duration(&Duration::minutes(0)), "0h 00m");
+    }
+}
@@ -1,11 +1,24 @@
+//! The command line is parsed by `Command::parse`, clap only provides
+//! the help and the completion scripts.
+
 extern crate clap;
 
 use self::clap::{Arg, App, AppSettings, SubCommand};
+pub use self::clap::Shell;
 
+use std::env;
+use std::path::Path;
 use std::str::FromStr;
 
-use task_ref::{TaskRef, TaskRefError};
-use ::command::{Command, Flag};
+use color::{ColorChoice, COLOR_CHOICES};
+use completion::{COMPLETE_COMMAND, SHELLS};
+use column::Column;
+use config::Config;
+use date;
+use formats;
+use history::AsOf;
+use task_ref::TaskRef;
+use ::command::{Command, Flag, ListOptions, ParseError, COMMANDS};
 
 fn flags_arg<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("FLAG")
@@ -15,6 +28,20 @@ fn flags_arg<'a, 'b>() -> Arg<'a, 'b> {
                    .ok_or(format!("Invalid Flag: {}", arg)))
 }
 
+// Flags and list options like `sort:due+`
+fn list_args_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("FLAG")
+        .multiple(true)
+        .validator(|arg| {
+            if ListOptions::default().parse_arg(&arg).map_err(|e| e.0)? {
+                return Ok(());
+            }
+            Flag::from_str(&arg)
+                .map(|_| ())
+                .ok_or(format!("Invalid Flag: {}", arg))
+        })
+}
+
 fn task_id_arg<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("TASK")
         .validator(|arg| TaskRef::from_str(&arg)
@@ -22,8 +49,25 @@ fn task_id_arg<'a, 'b>() -> Arg<'a, 'b> {
                    .map_err(|err| format!("{}", err)))
 }
 
-fn app<'a, 'b>() -> App<'a, 'b> {
+fn count_validator(arg: String) -> Result<(), String> {
+    match arg.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("Expected a positive number: {}", arg)),
+    }
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
     App::new("rtask")
+        .after_help("Commands working on tasks take task ids and flags before or after the command, \
+                     e.g. `rtask +work done` or `rtask done 1 2`. Without a command the given tasks \
+                     are shown, or the matching tasks listed. Reports can be used as commands, \
+                     e.g. `rtask +work completed`.")
+        .arg(Arg::with_name("COLOR")
+             .long("color")
+             .takes_value(true)
+             .global(true)
+             .possible_values(COLOR_CHOICES)
+             .help("Whether to use colors, auto only uses them for terminals without NO_COLOR set"))
         .subcommand(SubCommand::with_name("show")
                     .arg(task_id_arg().multiple(true)))
         .subcommand(SubCommand::with_name("cancel")
@@ -32,7 +76,83 @@ fn app<'a, 'b>() -> App<'a, 'b> {
                     .arg(task_id_arg().multiple(true)))
         .subcommand(SubCommand::with_name("delete")
                     .arg(task_id_arg().multiple(true)))
+        .subcommand(SubCommand::with_name("edit")
+                    .visible_alias("modify")
+                    .about("Changes the tags, priority and dates of the given tasks")
+                    .setting(AppSettings::AllowLeadingHyphen)
+                    .arg(Arg::with_name("CHANGES")
+                         .multiple(true)
+                         .required(true)
+                         .help("Task ids followed by changes like +tag, -tag, priority:high or due:none")))
+        .subcommand(SubCommand::with_name("history")
+                    .about("Shows all changes made to the given tasks")
+                    .arg(task_id_arg().multiple(true).required(true)))
+        .subcommand(SubCommand::with_name("start")
+                    .about("Starts tracking time spent on the given tasks")
+                    .arg(Arg::with_name("STOP_OTHERS")
+                         .long("stop-others")
+                         .help("Stop all other active tasks"))
+                    .arg(task_id_arg().multiple(true).required(true)))
+        .subcommand(SubCommand::with_name("stop")
+                    .about("Stops tracking time spent on the given tasks")
+                    .arg(task_id_arg().multiple(true).required(true)))
+        .subcommand(SubCommand::with_name("timesheet")
+                    .about("Sums up time spent per task and tag")
+                    .arg(Arg::with_name("WEEK")
+                         .long("week")
+                         .conflicts_with("SINCE")
+                         .help("Show the current week (default)"))
+                    .arg(Arg::with_name("SINCE")
+                         .long("since")
+                         .takes_value(true)
+                         .help("Show all time spent since the given date")
+                         .validator(|arg| date::parse_date(&arg)
+                                    .map(|_| ())
+                                    .ok_or(format!("Invalid date: {}", arg)))))
+        .subcommand(SubCommand::with_name("import")
+                    .about("Imports tasks from a file, updating already known tasks")
+                    .arg(Arg::with_name("ARGS")
+                         .value_names(&["FORMAT", "FILE"])
+                         .min_values(1)
+                         .max_values(2)
+                         .required(true)
+                         .help("The format is guessed from the file extension if omitted")))
+        .subcommand(SubCommand::with_name("export")
+                    .about("Exports all tasks matching the given flags")
+                    .setting(AppSettings::AllowLeadingHyphen)
+                    .arg(Arg::with_name("FORMAT")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(formats::FORMATS)
+                         .help("Guessed from the output file's extension if omitted, json otherwise"))
+                    .arg(Arg::with_name("OUTPUT")
+                         .short("o")
+                         .long("output")
+                         .takes_value(true)
+                         .help("Write to this file instead of stdout"))
+                    .arg(flags_arg()))
+        .subcommand(SubCommand::with_name("log")
+                    .about("Shows all changes made to any task")
+                    .arg(Arg::with_name("SINCE")
+                         .long("since")
+                         .takes_value(true)
+                         .help("Only show changes made in the given duration (e.g. 1w, 3d)")
+                         .validator(|arg| date::parse_duration(&arg)
+                                    .map(|_| ())
+                                    .ok_or(format!("Invalid duration: {}", arg)))))
 
+        .subcommand(SubCommand::with_name("tui")
+                    .about("Opens a full-screen interactive task list"))
+        .subcommand(SubCommand::with_name("daemon")
+                    .about("Keeps the tasks in memory and serves other rtask invocations")
+                    .arg(Arg::with_name("STOP")
+                         .long("stop")
+                         .help("Stops the running daemon")))
+        .subcommand(SubCommand::with_name("completions")
+                    .about("Prints the completion script for a shell")
+                    .arg(Arg::with_name("SHELL")
+                         .possible_values(SHELLS)
+                         .required(true)))
         .subcommand(SubCommand::with_name("add")
                     .setting(AppSettings::AllowLeadingHyphen)
                     .setting(AppSettings::AllowMissingPositional)
@@ -43,71 +163,353 @@ fn app<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("list")
                     .setting(AppSettings::AllowLeadingHyphen)
                     .setting(AppSettings::AllowMissingPositional)
-                    .arg(flags_arg().index(1)))
-}
-
-pub fn get_command() -> Result<Command, ::command::ParseError> {
-    let matches = app().get_matches();
-    debug!("args: {:?}", matches);
-
-    match matches.subcommand() {
-        ("", None) => Ok(Command::List(vec![])),
-        ("list", args) => {
-            let flags = args
-                .and_then(|args| args.values_of("FLAG"))
-                .map_or(vec![], |args| args.flat_map(Flag::from_str).collect());
-            Ok(Command::List(flags))
-        },
-        ("show", Some(args)) => {
-            let refs = args.values_of("TASK").expect("Couldn't get IDs")
-                .map(TaskRef::from_str)
-                .collect::<Result<Vec<TaskRef>, TaskRefError>>()?;
-
-            Ok(Command::Show(refs))
-        },
-        ("cancel", Some(args)) => {
-            let refs = args.values_of("TASK").expect("Couldn't get IDs")
-                .map(TaskRef::from_str)
-                .collect::<Result<Vec<TaskRef>, TaskRefError>>()?;
-
-            Ok(Command::MarkCanceled(refs))
-        },
-        ("done", Some(args)) => {
-            let refs = args.values_of("TASK").expect("Couldn't get IDs")
-                .map(TaskRef::from_str)
-                .collect::<Result<Vec<TaskRef>, TaskRefError>>()?;
-
-            Ok(Command::MarkDone(refs))
-        },
-        ("delete", Some(args)) => {
-            let refs = args.values_of("TASK").expect("Couldn't get IDs")
-                .map(TaskRef::from_str)
-                .collect::<Result<Vec<TaskRef>, TaskRefError>>()?;
-
-            Ok(Command::Delete(refs))
-        },
-        ("add", args) => {
-            let args: Vec<&str> = args
-                .and_then(|args| args.values_of("TASK_DESCRIPTION"))
-                .map(|args| args.collect())
-                .unwrap_or(vec![]);
-
-            // TODO: Only strip trailing and leading flags
-            let flags: Vec<Flag> = args.iter().flat_map(|s| Flag::from_str(&s)).collect();
-            let title: String = args.iter()
-                .filter(|p| Flag::from_str(p).is_none()) // Ugh
-                .fold(String::new(), |acc, arg| acc + " " + arg.as_ref())
-                .trim()
-                .to_string();
-
-            if title != "" {
-                debug!("title: {:?}, flags: {:?}", title, flags);
-                Ok(Command::Add(title, flags))
-            } else {
-                Err(::command::ParseError("Failed to parse parameters".into()))
+                    .arg(Arg::with_name("AS_OF")
+                         .long("as-of")
+                         .takes_value(true)
+                         .help("Show the task list as of a date or effect id")
+                         .validator(|arg| AsOf::from_str(&arg)
+                                    .map(|_| ())
+                                    .map_err(|err| format!("{}", err))))
+                    .arg(Arg::with_name("REPORT")
+                         .long("report")
+                         .takes_value(true)
+                         .help("Show a named report instead of the default one"))
+                    .arg(Arg::with_name("COLUMNS")
+                         .long("columns")
+                         .takes_value(true)
+                         .help("Comma separated list of columns to show")
+                         .validator(|arg| Column::parse_list(&arg)
+                                    .map(|_| ())
+                                    .map_err(|err| format!("{}", err))))
+                    .arg(Arg::with_name("CSV")
+                         .long("csv")
+                         .help("Print comma separated values"))
+                    .arg(Arg::with_name("TAG_SEPARATOR")
+                         .long("tag-separator")
+                         .takes_value(true)
+                         .help("Separator between tags, ',' by default"))
+                    .arg(Arg::with_name("LIMIT")
+                         .long("limit")
+                         .takes_value(true)
+                         .validator(count_validator)
+                         .help("Show at most this many tasks"))
+                    .arg(Arg::with_name("PAGE")
+                         .long("page")
+                         .takes_value(true)
+                         .validator(count_validator)
+                         .help("Show the given page of --limit tasks or a screen full"))
+                    .arg(Arg::with_name("ALL")
+                         .long("all")
+                         .conflicts_with_all(&["LIMIT", "PAGE"])
+                         .help("Show all tasks, ignoring the report's limit"))
+                    .arg(list_args_arg().index(1)))
+}
+
+/// `app` with the aliases listed among the subcommands, for `--help`
+fn app_with_aliases<'a>(aliases: &'a [(String, String)]) -> App<'a, 'a> {
+    aliases.iter().fold(app(), |app, &(ref name, ref about)| {
+        app.subcommand(SubCommand::with_name(name).about(&about[..]))
+    })
+}
+
+fn is_command(name: &str) -> bool {
+    COMMANDS.contains(&name) || name == "help"
+}
+
+/// The usable aliases with their help texts, commands can't be
+/// overridden
+fn alias_help(config: &Config) -> Vec<(String, String)> {
+    config
+        .aliases
+        .iter()
+        .filter(|&(name, _)| !is_command(name))
+        .map(|(name, args)| (name.clone(), format!("Alias for `{}`", args.join(" "))))
+        .collect()
+}
+
+// Rewrites `rtask <alias> ...` to `rtask <expansion> ...` until the
+// first argument isn't an alias anymore
+fn expand_aliases(mut args: Vec<String>, config: &Config) -> Result<Vec<String>, ParseError> {
+    let mut expanded: Vec<String> = vec![];
+
+    loop {
+        let expansion = match args.get(1) {
+            Some(name) if !is_command(name) => config.aliases.get(name),
+            _ => None,
+        };
+        let expansion = match expansion {
+            Some(expansion) => expansion,
+            None => return Ok(args),
+        };
+
+        let name = args.remove(1);
+        if expanded.contains(&name) {
+            return Err(ParseError(format!("Alias {} refers to itself", name)));
+        }
+        args.splice(1..1, expansion.iter().cloned());
+        expanded.push(name);
+    }
+}
+
+// The position of the command word, the first command or alias, or
+// the end of `args` if there's none. Options of rtask itself come
+// before it, what follows may be free text like a task description.
+fn command_position(args: &[String], config: &Config) -> usize {
+    args.iter()
+        .skip(1)
+        .position(|a| is_command(a) || config.aliases.contains_key(a))
+        .map_or(args.len(), |pos| pos + 1)
+}
+
+// Removes `--color <choice>` given before the command word
+fn take_color_choice(args: &mut Vec<String>, config: &Config) -> Result<ColorChoice, ParseError> {
+    let end = command_position(args, config);
+    let pos = match args[..end].iter().position(|a| a == "--color" || a.starts_with("--color=")) {
+        Some(pos) => pos,
+        None => return Ok(ColorChoice::default()),
+    };
+
+    let arg = args.remove(pos);
+    let value = if arg.starts_with("--color=") {
+        arg["--color=".len()..].to_string()
+    } else if pos < args.len() {
+        args.remove(pos)
+    } else {
+        return Err(ParseError("Missing argument for --color".into()));
+    };
+    ColorChoice::from_str(&value).map_err(|e| ParseError(format!("{}, expected one of {}", e, COLOR_CHOICES.join(", "))))
+}
+
+// The arguments showing clap's help for the command in `args`, if
+// they ask for help: `rtask help [<command>]`, `-h` or `--help`
+// before the command word or as the only argument following it
+fn help_args(args: &[String], config: &Config) -> Option<Vec<String>> {
+    let is_help = |a: &String| a == "-h" || a == "--help";
+    let pos = command_position(args, config);
+    let command = args.get(pos);
+    let following = args.get(pos + 1..).unwrap_or(&[]);
+
+    let asks_for_help = command.map_or(false, |c| c == "help")
+        || args[1..pos].iter().any(&is_help)
+        || following.len() == 1 && is_help(&following[0]);
+    if !asks_for_help {
+        return None;
+    }
+
+    let command = match command {
+        Some(c) if c == "help" => following.first(),
+        c => c,
+    };
+    let mut help = vec!["rtask".to_string()];
+    help.extend(command.filter(|c| COMMANDS.contains(&&c[..])).cloned());
+    help.push("--help".into());
+    Some(help)
+}
+
+// The arguments to complete are taken as they are
+fn completion_candidates(args: &[String]) -> Option<Command> {
+    match args.get(1) {
+        Some(command) if command == COMPLETE_COMMAND => Some(Command::CompletionCandidates(args[2..].to_vec())),
+        _ => None,
+    }
+}
+
+// Makes the files of `import` and `export` absolute. They are given
+// relative to `cwd`, but opened after changing to the rtask directory
+// or by the daemon.
+fn absolute_paths(mut args: Vec<String>, cwd: &Path) -> Vec<String> {
+    let absolute = |path: &str| cwd.join(path).to_string_lossy().into_owned();
+    let pos = match args.iter().skip(1).position(|a| is_command(a)) {
+        Some(pos) => pos + 1,
+        None => return args,
+    };
+
+    let command = args[pos].clone();
+    match &command[..] {
+        "import" if args.len() > pos + 1 => {
+            let last = args.len() - 1;
+            args[last] = absolute(&args[last]);
+        }
+        "export" => {
+            for i in pos + 1..args.len() {
+                if i > pos + 1 && (args[i - 1] == "-o" || args[i - 1] == "--output") {
+                    args[i] = absolute(&args[i]);
+                } else if let Some(prefix) = ["-o=", "--output="].iter().find(|p| args[i].starts_with(*p)) {
+                    args[i] = format!("{}{}", prefix, absolute(&args[i][prefix.len()..]));
+                }
             }
-        },
+        }
+        _ => (),
+    }
+    args
+}
+
+/// Parses the arguments of this process, files are relative to `cwd`.
+/// Also returns the arguments to hand to the daemon, which has another
+/// working directory.
+pub fn get_command(config: &Config, cwd: &Path) -> Result<(Command, ColorChoice, Vec<String>), ParseError> {
+    let mut args: Vec<String> = env::args().collect();
+    if let Some(command) = completion_candidates(&args) {
+        return Ok((command, ColorChoice::Never, args));
+    }
+
+    let color = take_color_choice(&mut args, config)?;
+    if let Some(help) = help_args(&args, config) {
+        // Prints the help and exits
+        app_with_aliases(&alias_help(config)).get_matches_from(help);
+    }
+
+    let args = absolute_paths(expand_aliases(args, config)?, cwd);
+    debug!("args: {:?}", args);
+    Command::parse(&args[1..], config).map(|command| (command, color, args))
+}
+
+/// Like `get_command`, but for the arguments of another process. Asking
+/// for help is an error instead of printing it and exiting.
+pub fn get_command_from(mut args: Vec<String>, config: &Config) -> Result<(Command, ColorChoice), ParseError> {
+    if let Some(command) = completion_candidates(&args) {
+        return Ok((command, ColorChoice::Never));
+    }
+
+    let color = take_color_choice(&mut args, config)?;
+    if let Some(help) = help_args(&args, config) {
+        let aliases = alias_help(config);
+        let error = app_with_aliases(&aliases).get_matches_from_safe(help).err();
+        return Err(ParseError(error.map_or_else(String::new, |e| e.message)));
+    }
+
+    let args = expand_aliases(args, config)?;
+    debug!("args: {:?}", args);
+    Command::parse(&args[1..], config).map(|command| (command, color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_get_command_from() {
+        let config = Config::from_str(r#"{"aliases": {"w": "+work"}}"#).unwrap();
+        let get = |v: &[&str]| get_command_from(args(v), &config);
+
+        assert_eq!(
+            get(&["rtask", "--color", "never", "w", "done"]),
+            Ok((
+                Command::Filtered(vec![Flag::TagPositive("work".into())], Box::new(Command::MarkDone(vec![]))),
+                ColorChoice::Never
+            ))
+        );
+        assert_eq!(get(&["rtask", "42", "history"]), Ok((Command::History(vec![TaskRef::Numerical(42)]), ColorChoice::Auto)));
+        assert!(get(&["rtask", "bogus"]).is_err());
+        // Options after the command word are left to the command
+        match get(&["rtask", "add", "fix", "-h", "handling"]) {
+            Ok((Command::Add(title, _), ColorChoice::Auto)) => assert_eq!("fix handling", title),
+            c => panic!("Unexpected {:?}", c),
+        }
+        match get(&["rtask", "add", "document", "the", "--color", "option"]) {
+            Ok((Command::Add(..), ColorChoice::Auto)) => (),
+            c => panic!("Unexpected {:?}", c),
+        }
+
+        match get(&["rtask", "1", "edit", "-h"]) {
+            Err(ParseError(help)) => assert!(help.contains("due:none")),
+            c => panic!("Unexpected {:?}", c),
+        }
+        match get(&["rtask", "help"]) {
+            Err(ParseError(help)) => assert!(help.contains("rtask +work done") && help.contains("Alias for `+work`")),
+            c => panic!("Unexpected {:?}", c),
+        }
+    }
+
+    #[test]
+    fn test_commands_documented() {
+        for name in COMMANDS {
+            match app().get_matches_from_safe(&["rtask", name, "--help"]) {
+                Err(ref e) if e.kind == clap::ErrorKind::HelpDisplayed => (),
+                _ => panic!("{} has no help", name),
+            }
+        }
+    }
+
+    #[test]
+    fn test_expand_aliases() {
+        let config = Config::from_str(r#"{"aliases": {
+            "t": "list +today", "bug": "add +bug priority:h", "b": "bug",
+            "loop": "again", "again": "loop", "list": "list +work"
+        }}"#).unwrap();
+        let expand = |v: &[&str]| expand_aliases(args(v), &config).map_err(|e| e.0);
+
+        assert_eq!(expand(&["rtask", "t", "-work"]), Ok(args(&["rtask", "list", "+today", "-work"])));
+        assert_eq!(expand(&["rtask", "b", "crash"]),
+                   Ok(args(&["rtask", "add", "+bug", "priority:h", "crash"])));
+        assert_eq!(expand(&["rtask", "add", "t"]), Ok(args(&["rtask", "add", "t"])));
+        // Commands can't be overridden
+        assert_eq!(expand(&["rtask", "list"]), Ok(args(&["rtask", "list"])));
+        assert_eq!(expand(&["rtask", "loop"]), Err("Alias loop refers to itself".to_string()));
+
+        let aliases = alias_help(&config);
+        assert!(!aliases.iter().any(|a| a.0 == "list"));
+        let mut help = vec![];
+        app_with_aliases(&aliases).write_help(&mut help).unwrap();
+        assert!(String::from_utf8(help).unwrap().contains("Alias for `add +bug priority:h`"));
+    }
+
+    #[test]
+    fn test_color_choice() {
+        let config = Config::from_str(r#"{"aliases": {"w": "+work"}}"#).unwrap();
+        let choice = |v: &[&str]| take_color_choice(&mut args(v), &config);
+        assert_eq!(choice(&["rtask"]), Ok(ColorChoice::Auto));
+        assert_eq!(choice(&["rtask", "--color", "never"]), Ok(ColorChoice::Never));
+        assert_eq!(choice(&["rtask", "+foo", "--color=always", "list"]), Ok(ColorChoice::Always));
+        assert_eq!(choice(&["rtask", "--color", "never", "log"]), Ok(ColorChoice::Never));
+        assert!(choice(&["rtask", "--color", "bogus"]).is_err());
+        assert!(choice(&["rtask", "--color"]).is_err());
+
+        let mut list = args(&["rtask", "--color", "never", "list", "+foo"]);
+        take_color_choice(&mut list, &config).unwrap();
+        assert_eq!(args(&["rtask", "list", "+foo"]), list);
+
+        // Anything after the command word is left alone
+        let mut add = args(&["rtask", "add", "document", "the", "--color", "option"]);
+        assert_eq!(take_color_choice(&mut add, &config), Ok(ColorChoice::Auto));
+        assert_eq!(6, add.len());
+        assert_eq!(choice(&["rtask", "w", "log", "--color", "bogus"]), Ok(ColorChoice::Auto));
+    }
+
+    #[test]
+    fn test_absolute_paths() {
+        let cwd = Path::new("/home/user");
+        let absolute = |v: &[&str]| absolute_paths(args(v), cwd);
+        assert_eq!(
+            absolute(&["rtask", "import", "taskwarrior", "backup.json"]),
+            args(&["rtask", "import", "taskwarrior", "/home/user/backup.json"])
+        );
+        assert_eq!(absolute(&["rtask", "import", "/tmp/a.org"]), args(&["rtask", "import", "/tmp/a.org"]));
+        assert_eq!(
+            absolute(&["rtask", "+work", "export", "-o", "tasks.json", "+bar"]),
+            args(&["rtask", "+work", "export", "-o", "/home/user/tasks.json", "+bar"])
+        );
+        assert_eq!(
+            absolute(&["rtask", "export", "--output=../tasks.ics"]),
+            args(&["rtask", "export", "--output=/home/user/../tasks.ics"])
+        );
+        assert_eq!(absolute(&["rtask", "add", "backup.json"]), args(&["rtask", "add", "backup.json"]));
+    }
 
-        command => unimplemented!("subcommand {:?}", command)
+    #[test]
+    fn test_help_args() {
+        let config = Config::from_str(r#"{"aliases": {"w": "+work"}}"#).unwrap();
+        let help = |v: &[&str]| help_args(&args(v), &config);
+        assert_eq!(help(&["rtask", "+work", "done", "--help"]), Some(args(&["rtask", "done", "--help"])));
+        assert_eq!(help(&["rtask", "-h", "+work", "done"]), Some(args(&["rtask", "done", "--help"])));
+        assert_eq!(help(&["rtask", "help", "log"]), Some(args(&["rtask", "log", "--help"])));
+        assert_eq!(help(&["rtask", "-h"]), Some(args(&["rtask", "--help"])));
+        assert_eq!(help(&["rtask", "w", "--help"]), Some(args(&["rtask", "--help"])));
+        assert_eq!(help(&["rtask", "add", "help"]), None);
+        assert_eq!(help(&["rtask", "add", "fix", "-h", "handling"]), None);
     }
 }
@@ -0,0 +1,190 @@
+use ansi_term::{Colour, Style};
+
+use std::env;
+use std::str::FromStr;
+
+use terminal_size::stdout_is_tty;
+use theme::{self, Color, ColorDepth, Role, Theme};
+
+pub const COLOR_CHOICES: &'static [&'static str] = &["auto", "always", "never"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colors if stdout is a terminal and `NO_COLOR` isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Invalid color choice: {}", s)),
+        }
+    }
+}
+
+impl Default for ColorChoice {
+    fn default() -> Self {
+        ColorChoice::Auto
+    }
+}
+
+/// The theme's styles as far as the terminal supports them
+pub struct Colors {
+    theme: Theme,
+    depth: ColorDepth,
+}
+
+impl Colors {
+    pub fn new(theme: Theme, depth: ColorDepth) -> Self {
+        Colors {
+            theme: theme,
+            depth: depth,
+        }
+    }
+
+    pub fn style(&self, role: Role) -> Style {
+        to_ansi(&self.theme.style(role).reduce(self.depth))
+    }
+
+    /// Several roles on top of each other, later ones win
+    pub fn combined(&self, roles: &[Role]) -> Style {
+        let style = roles
+            .iter()
+            .fold(theme::Style::default(), |style, role| style.merge(&self.theme.style(*role)));
+        to_ansi(&style.reduce(self.depth))
+    }
+}
+
+/// The colors of the terminal on stdout, as far as they are wanted
+pub fn detect_color_depth(choice: ColorChoice) -> ColorDepth {
+    let env = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+
+    color_depth(choice, stdout_is_tty(), env("NO_COLOR"), env("TERM"), env("COLORTERM"))
+}
+
+fn color_depth(
+    choice: ColorChoice,
+    is_tty: bool,
+    no_color: Option<String>,
+    term: Option<String>,
+    colorterm: Option<String>,
+) -> ColorDepth {
+    let term = term.unwrap_or_default();
+
+    match choice {
+        ColorChoice::Never => return ColorDepth::None,
+        ColorChoice::Auto if !is_tty || no_color.is_some() || term == "dumb" => {
+            return ColorDepth::None
+        }
+        _ => (),
+    }
+
+    match colorterm.as_ref().map(|c| &c[..]) {
+        Some("truecolor") | Some("24bit") => ColorDepth::TrueColor,
+        _ if term.contains("256color") => ColorDepth::Ansi256,
+        _ => ColorDepth::Ansi16,
+    }
+}
+
+fn to_ansi_colour(color: Color) -> Colour {
+    match color {
+        Color::Ansi(0) => Colour::Black,
+        Color::Ansi(1) => Colour::Red,
+        Color::Ansi(2) => Colour::Green,
+        Color::Ansi(3) => Colour::Yellow,
+        Color::Ansi(4) => Colour::Blue,
+        Color::Ansi(5) => Colour::Purple,
+        Color::Ansi(6) => Colour::Cyan,
+        Color::Ansi(7) => Colour::White,
+        // ansi_term has no names for the bright variants, but terminals
+        // with 16 colors understand these indices, too
+        Color::Ansi(n) | Color::Fixed(n) => Colour::Fixed(n),
+        Color::Rgb(r, g, b) => Colour::RGB(r, g, b),
+    }
+}
+
+fn to_ansi(style: &theme::Style) -> Style {
+    let mut ansi = Style::default();
+    if let Some(fg) = style.fg {
+        ansi = ansi.fg(to_ansi_colour(fg));
+    }
+    if let Some(bg) = style.bg {
+        ansi = ansi.on(to_ansi_colour(bg));
+    }
+    if style.bold {
+        ansi = ansi.bold();
+    }
+    if style.dim {
+        ansi = ansi.dimmed();
+    }
+    if style.italic {
+        ansi = ansi.italic();
+    }
+    if style.underline {
+        ansi = ansi.underline();
+    }
+    if style.reverse {
+        ansi = ansi.reverse();
+    }
+    ansi
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_depth() {
+        let depth = |choice, tty, no_color: Option<&str>, term: &str, colorterm: Option<&str>| {
+            color_depth(
+                choice,
+                tty,
+                no_color.map(String::from),
+                Some(term.into()),
+                colorterm.map(String::from),
+            )
+        };
+
+        assert_eq!(ColorDepth::Ansi16, depth(ColorChoice::Auto, true, None, "xterm", None));
+        assert_eq!(ColorDepth::Ansi256, depth(ColorChoice::Auto, true, None, "xterm-256color", None));
+        assert_eq!(
+            ColorDepth::TrueColor,
+            depth(ColorChoice::Auto, true, None, "xterm-256color", Some("truecolor"))
+        );
+
+        // Pipes, NO_COLOR and dumb terminals get no escape codes
+        assert_eq!(ColorDepth::None, depth(ColorChoice::Auto, false, None, "xterm", None));
+        assert_eq!(ColorDepth::None, depth(ColorChoice::Auto, true, Some("1"), "xterm", None));
+        assert_eq!(ColorDepth::None, depth(ColorChoice::Auto, true, None, "dumb", None));
+        assert_eq!(ColorDepth::None, depth(ColorChoice::Never, true, None, "xterm", None));
+
+        // Unless colors are forced
+        assert_eq!(ColorDepth::Ansi16, depth(ColorChoice::Always, false, Some("1"), "dumb", None));
+    }
+
+    #[test]
+    fn test_colors() {
+        let colors = Colors {
+            theme: Theme::builtin("default").unwrap(),
+            depth: ColorDepth::Ansi256,
+        };
+        assert_eq!(Style::default().bold().underline(), colors.style(Role::Header));
+        assert_eq!(
+            Style::default().fg(Colour::Fixed(203)).on(Colour::Fixed(235)).bold(),
+            colors.combined(&[Role::AlternateRow, Role::HighPriority, Role::Active])
+        );
+
+        let colors = Colors {
+            theme: Theme::builtin("default").unwrap(),
+            depth: ColorDepth::None,
+        };
+        assert_eq!(Style::default(), colors.style(Role::Header));
+    }
+}
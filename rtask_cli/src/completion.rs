@@ -0,0 +1,186 @@
+//! Shell completions. The scripts generated by clap complete commands
+//! and options, everything depending on the tasks is asked for with
+//! `rtask _complete <args>`, which prints one candidate per line with an
+//! optional description after a tab.
+
+use cli::{self, Shell};
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use rtask::*;
+use task_ref::TaskRef;
+
+pub const SHELLS: &'static [&'static str] = &["bash", "zsh", "fish"];
+
+/// The hidden command printing the candidates
+pub const COMPLETE_COMMAND: &'static str = "_complete";
+
+/// Commands taking task ids
+const TASK_COMMANDS: &'static [&'static str] = &[
+    "show", "edit", "modify", "cancel", "done", "delete", "history", "start", "stop",
+];
+
+const BASH: &'static str = r#"
+_rtask_tasks() {
+    _rtask "$@"
+
+    # Words are split at colons in bash, so use the whole word before the cursor
+    local line="${COMP_LINE:0:COMP_POINT}"
+    local cur="${line##*[[:space:]]}"
+    local -a args
+    read -ra args <<< "$line"
+    [[ -z "$cur" ]] && args+=("")
+
+    local IFS=$'\n'
+    local candidates=($(rtask _complete "${args[@]:1}" 2>/dev/null | cut -f1))
+    if [[ "$cur" == *:* ]]; then
+        candidates=("${candidates[@]#${cur%:*}:}")
+    fi
+    COMPREPLY+=("${candidates[@]}")
+}
+
+complete -F _rtask_tasks -o bashdefault -o default rtask
+"#;
+
+const ZSH: &'static str = r#"
+_rtask_tasks() {
+    _rtask "$@"
+
+    local -a candidates lines
+    local line
+    lines=(${(f)"$(rtask _complete "${(@)words[2,CURRENT]}" 2>/dev/null)"})
+    for line in $lines; do
+        if [[ $line == *$'\t'* ]]; then
+            candidates+=("${${line%%$'\t'*}//:/\\:}:${line#*$'\t'}")
+        else
+            candidates+=("${line//:/\\:}")
+        fi
+    done
+    _describe -t tasks 'tasks and tags' candidates
+}
+
+compdef _rtask_tasks rtask
+"#;
+
+const FISH: &'static str = r#"
+function __rtask_complete
+    set -l args (commandline -opc)
+    set -e args[1]
+    rtask _complete $args (commandline -ct)
+end
+
+complete -c rtask -a '(__rtask_complete)'
+"#;
+
+/// Writes the completion script for `shell`, it's meant to be sourced
+pub fn write_script(shell: &str, out: &mut Write) -> io::Result<()> {
+    let parsed = Shell::from_str(shell).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut script = vec![];
+    cli::app().gen_completions_to("rtask", parsed, &mut script);
+    let script = String::from_utf8_lossy(&script);
+
+    let (script, dynamic) = match parsed {
+        Shell::Bash => (&script[..], BASH),
+        // The script completes when it's run as an autoloaded function,
+        // `_rtask_tasks` is registered instead
+        Shell::Zsh => (script.trim_end().trim_end_matches("_rtask \"$@\""), ZSH),
+        Shell::Fish => (&script[..], FISH),
+        _ => (&script[..], ""),
+    };
+    out.write_all(script.as_bytes())?;
+    out.write_all(dynamic.as_bytes())
+}
+
+/// What the last of `args` could be completed to, with descriptions
+pub fn candidates(model: &Model, scope: &Scope, args: &[String]) -> Vec<(String, Option<String>)> {
+    let (current, before) = match args.split_last() {
+        Some((current, before)) => (&current[..], before),
+        None => ("", args),
+    };
+
+    let mut candidates = vec![];
+    if current.starts_with('+') || (current.starts_with('-') && !current.starts_with("--")) {
+        let mut tags: Vec<&str> = model
+            .all_tasks()
+            .flat_map(|t| t.tags.iter())
+            .map(|t| &t[..])
+            .chain(VIRTUAL_TAGS.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        candidates.extend(tags.into_iter().map(|t| (format!("{}{}", &current[..1], t), None)));
+    } else if let Some(i) = current.find(':') {
+        if ["priority", "pri", "p"].contains(&&current[..i]) {
+            for priority in &["high", "default", "low"] {
+                candidates.push((format!("{}:{}", &current[..i], priority), None));
+            }
+        }
+    } else if current.chars().all(|c| c.is_digit(10)) && takes_task_ids(before) {
+        let ids = model.numerical_ids.get(&scope[..]);
+        for (id, uuid) in ids.into_iter().flat_map(|ids| ids.iter()) {
+            if let Some(task) = model.get_task(uuid).filter(|t| t.is_open()) {
+                candidates.push((id.to_string(), Some(task.description.clone())));
+            }
+        }
+    }
+
+    candidates.retain(|c| c.0.starts_with(current));
+    candidates
+}
+
+// Task ids come first or after commands like `done`
+fn takes_task_ids(args: &[String]) -> bool {
+    args.iter().all(|a| TaskRef::from_str(a).is_ok()) || args.iter().any(|a| TASK_COMMANDS.contains(&&a[..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_candidates() {
+        let mut m = Model::new();
+        let scope = Scope(None);
+        let mut tasks = vec![];
+        for (description, tag) in vec![("foo", "work"), ("bar", "home")] {
+            let mut task = Task::new(description);
+            task.tags.insert(tag.into());
+            m.apply_effect(&Effect::AddTask(task.clone()));
+            tasks.push(task.uuid);
+        }
+        m.recalculate_numerical_ids(&scope, &tasks);
+
+        let values = |v: &[&str]| -> Vec<String> {
+            candidates(&m, &scope, &args(v)).into_iter().map(|c| c.0).collect()
+        };
+        assert_eq!(vec!["+home"], values(&["list", "+h"]));
+        assert_eq!(vec!["-WAITING"], values(&["add", "foo", "-W"]));
+        assert_eq!(vec!["-work"], values(&["add", "foo", "-w"]));
+        assert_eq!(vec!["priority:high"], values(&["add", "priority:h"]));
+        assert_eq!(vec!["1", "2"], values(&["done", ""]));
+        assert_eq!(vec!["2"], values(&["1", "2"]));
+        assert!(values(&["add", ""]).is_empty());
+        assert!(values(&["--color"]).is_empty());
+
+        assert_eq!(
+            vec![("1".to_string(), Some("foo".to_string())), ("2".into(), Some("bar".into()))],
+            candidates(&m, &scope, &args(&[""]))
+        );
+    }
+
+    #[test]
+    fn test_write_script() {
+        for shell in SHELLS {
+            let mut script = vec![];
+            write_script(shell, &mut script).unwrap();
+            let script = String::from_utf8(script).unwrap();
+            assert!(script.contains("timesheet") && script.contains(COMPLETE_COMMAND));
+            assert!(!script.contains("\n_rtask \"$@\""));
+        }
+    }
+}
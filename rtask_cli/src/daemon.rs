@@ -0,0 +1,402 @@
+//! The daemon keeps the model in memory and serves JSON-RPC 2.0 on a
+//! Unix socket in the rtask directory, one message per line. Its
+//! methods are
+//!
+//! - `run`: Runs the command line `args` like `["rtask", "list"]` in
+//!   a `session`, the result is its `output` and whether to `page` it
+//! - `tasks`: The tasks matching `filter`, e.g. `"+work pri:h"`
+//! - `stop`: Stops the daemon
+//!
+//! Changes are saved after every request.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{fs, thread};
+
+use rtask::*;
+use rtask::command::Command;
+use rtask::config::{Config, CONFIG_FILE};
+use rtask::report::Report;
+
+use color::Colors;
+use session::{Output, Session};
+
+pub const SOCKET_FILE: &'static str = "rtask.sock";
+
+// Error codes defined by JSON-RPC
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Requests rtask can't handle, like unknown commands
+const COMMAND_ERROR: i64 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Notifications have no id and get no response
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Response {
+    jsonrpc: String,
+    id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Fail, Serialize, Deserialize)]
+#[fail(display = "{}", message)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        RpcError {
+            code: code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RunParams {
+    args: Vec<String>,
+    session: Session,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RunResult {
+    output: String,
+    page: bool,
+}
+
+#[derive(Deserialize)]
+struct TasksParams {
+    #[serde(default)]
+    filter: String,
+}
+
+#[derive(Debug, Fail)]
+pub enum DaemonError {
+    #[fail(display = "IO Error: {}", _0)]
+    IO(io::Error),
+    #[fail(display = "Storage Error: {}", _0)]
+    Storage(String),
+    #[fail(display = "Invalid message: {}", _0)]
+    Json(serde_json::Error),
+    #[fail(display = "{}", _0)]
+    Rpc(RpcError),
+}
+
+impl From<io::Error> for DaemonError {
+    fn from(e: io::Error) -> Self {
+        DaemonError::IO(e)
+    }
+}
+
+impl From<serde_json::Error> for DaemonError {
+    fn from(e: serde_json::Error) -> Self {
+        DaemonError::Json(e)
+    }
+}
+
+struct Daemon {
+    store: Mutex<Storage>,
+    stopping: AtomicBool,
+}
+
+/// Serves requests until the daemon is stopped. Holds the lock of the
+/// rtask directory all the time, so other invocations can't write to
+/// the store behind its back.
+pub fn serve() -> Result<(), DaemonError> {
+    let _lock = FileLock::new(PID_FILE)?;
+
+    // Left over by a daemon that was killed
+    match fs::remove_file(SOCKET_FILE) {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => (),
+        result => result?,
+    }
+    let listener = UnixListener::bind(SOCKET_FILE)?;
+
+    let store = Storage::new().map_err(|e| DaemonError::Storage(e.to_string()))?;
+    let daemon = Arc::new(Daemon {
+        store: Mutex::new(store),
+        stopping: AtomicBool::new(false),
+    });
+    println!("Listening on {}", SOCKET_FILE);
+
+    for stream in listener.incoming() {
+        if daemon.stopping.load(Ordering::SeqCst) {
+            break;
+        }
+
+        match stream {
+            Ok(stream) => {
+                let daemon = daemon.clone();
+                thread::spawn(move || {
+                    if let Err(e) = daemon.handle_connection(stream) {
+                        warn!("Connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept connection: {}", e),
+        }
+    }
+
+    fs::remove_file(SOCKET_FILE)?;
+    let saved = daemon.store().save();
+    saved.map_err(|e| DaemonError::Storage(e.to_string()))
+}
+
+impl Daemon {
+    /// A command panicking in another connection doesn't leave the
+    /// model in a worse state than on the command line
+    fn store<'a>(&'a self) -> MutexGuard<'a, Storage> {
+        self.store.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn handle_connection(&self, stream: UnixStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(response) = self.handle_message(&line) {
+                serde_json::to_writer(&mut writer, &response)?;
+                writer.write_all(b"\n")?;
+            }
+
+            // Wake up the listener once the client knows, it only
+            // notices the flag on a new connection
+            if self.stopping.load(Ordering::SeqCst) {
+                let _ = UnixStream::connect(SOCKET_FILE);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_message(&self, message: &str) -> Option<Response> {
+        let request: Request = match serde_json::from_str(message) {
+            Ok(request) => request,
+            Err(e) => {
+                let code = if e.is_syntax() || e.is_eof() {
+                    PARSE_ERROR
+                } else {
+                    INVALID_REQUEST
+                };
+                return Some(response(Value::Null, Err(RpcError::new(code, e.to_string()))));
+            }
+        };
+        debug!("Request: {:?}", request);
+
+        let result = if request.jsonrpc != "2.0" {
+            Err(RpcError::new(INVALID_REQUEST, "Only JSON-RPC 2.0 is supported"))
+        } else {
+            self.call(&request.method, request.params)
+        };
+
+        request.id.map(|id| response(id, result))
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "run" => to_result(self.run(from_params(params)?)),
+            "tasks" => to_result(self.tasks(from_params(params)?)),
+            "stop" => {
+                self.stopping.store(true, Ordering::SeqCst);
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    fn run(&self, params: RunParams) -> Result<RunResult, RpcError> {
+        // Read for every command, so changes don't need a restart
+        let config = Config::load(CONFIG_FILE).map_err(|e| {
+            RpcError::new(COMMAND_ERROR, format!("Error while loading {}: {}", CONFIG_FILE, e))
+        })?;
+
+        let (command, _) = ::cli::get_command_from(params.args, &config)
+            .map_err(|e| RpcError::new(COMMAND_ERROR, e.0))?;
+        match command {
+            Command::Tui | Command::Daemon { .. } => {
+                return Err(RpcError::new(COMMAND_ERROR, "This command can't run in the daemon"))
+            }
+            _ => (),
+        }
+        let colors = Colors::new(config.theme.clone(), params.session.color_depth);
+
+        let mut store = self.store();
+        let output = ::run_command(store.model(), command, &params.session, &colors)
+            .map_err(|e| RpcError::new(COMMAND_ERROR, e.to_string()))?;
+        store
+            .save()
+            .map_err(|e| RpcError::new(COMMAND_ERROR, format!("Failed to save: {}", e)))?;
+
+        Ok(RunResult {
+            output: String::from_utf8_lossy(&output.buffer).into_owned(),
+            page: output.page,
+        })
+    }
+
+    fn tasks(&self, params: TasksParams) -> Result<Vec<Task>, RpcError> {
+        let flags = Report::parse_filter(&params.filter).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+
+        let mut store = self.store();
+        Ok(::filter_tasks(store.model(), &flags).into_iter().cloned().collect())
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Response {
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+
+    Response {
+        jsonrpc: "2.0".into(),
+        id: id,
+        result: result,
+        error: error,
+    }
+}
+
+fn from_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // Params can be left out if all of them have defaults
+    let params = match params {
+        Value::Null => Value::Object(Default::default()),
+        params => params,
+    };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_result<T: Serialize>(result: Result<T, RpcError>) -> Result<Value, RpcError> {
+    result.and_then(|r| serde_json::to_value(r).map_err(|e| RpcError::new(COMMAND_ERROR, e.to_string())))
+}
+
+/// A connection to the daemon
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+}
+
+impl Client {
+    /// `None` if the daemon isn't running
+    pub fn connect() -> Option<Self> {
+        let stream = UnixStream::connect(SOCKET_FILE).ok()?;
+        let reader = stream.try_clone().ok()?;
+
+        Some(Client {
+            reader: BufReader::new(reader),
+            writer: stream,
+            next_id: 1,
+        })
+    }
+
+    fn call<P: Serialize, R: DeserializeOwned>(&mut self, method: &str, params: P) -> Result<R, DaemonError> {
+        let request = Request {
+            jsonrpc: "2.0".into(),
+            id: Some(self.next_id.into()),
+            method: method.into(),
+            params: serde_json::to_value(params)?,
+        };
+        self.next_id += 1;
+
+        serde_json::to_writer(&mut self.writer, &request)?;
+        self.writer.write_all(b"\n")?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "The daemon closed the connection").into());
+        }
+
+        let response: Response = serde_json::from_str(&line)?;
+        match response.error {
+            Some(error) => Err(DaemonError::Rpc(error)),
+            None => Ok(serde_json::from_value(response.result.unwrap_or(Value::Null))?),
+        }
+    }
+
+    pub fn run(&mut self, args: Vec<String>, session: &Session) -> Result<Output, DaemonError> {
+        let params = RunParams {
+            args: args,
+            session: session.clone(),
+        };
+        let result: RunResult = self.call("run", params)?;
+
+        Ok(Output {
+            buffer: result.output.into_bytes(),
+            page: result.page,
+        })
+    }
+
+    pub fn stop(&mut self) -> Result<(), DaemonError> {
+        let _: Value = self.call("stop", Value::Null)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn daemon() -> Daemon {
+        let mut store = Storage::new_in_memory();
+        store.model().apply_effect(&Effect::AddTask(Task::new("foo")));
+
+        Daemon {
+            store: Mutex::new(store),
+            stopping: AtomicBool::new(false),
+        }
+    }
+
+    fn call(daemon: &Daemon, message: &str) -> Value {
+        serde_json::to_value(daemon.handle_message(message)).unwrap()
+    }
+
+    #[test]
+    fn test_handle_message() {
+        let daemon = daemon();
+
+        let response = call(&daemon, r#"{"jsonrpc": "2.0", "id": 1, "method": "tasks"}"#);
+        assert_eq!(1, response["id"]);
+        assert_eq!("foo", response["result"][0]["description"]);
+
+        let response = call(&daemon, r#"{"jsonrpc": "2.0", "id": 2, "method": "tasks", "params": {"filter": "+bar"}}"#);
+        assert_eq!(Value::Array(vec![]), response["result"]);
+
+        let response = call(&daemon, r#"{"jsonrpc": "2.0", "id": 3, "method": "bogus"}"#);
+        assert_eq!(METHOD_NOT_FOUND, response["error"]["code"]);
+        let response = call(&daemon, r#"{"jsonrpc": "2.0", "id": 4, "method": "tasks", "params": {"filter": 3}}"#);
+        assert_eq!(INVALID_PARAMS, response["error"]["code"]);
+        let response = call(&daemon, r#"{"jsonrpc": "2.0", "id": 5, "#);
+        assert_eq!(PARSE_ERROR, response["error"]["code"]);
+        assert_eq!(Value::Null, response["id"]);
+
+        // Notifications are executed without a response
+        assert_eq!(Value::Null, call(&daemon, r#"{"jsonrpc": "2.0", "method": "stop"}"#));
+        assert!(daemon.stopping.load(Ordering::SeqCst));
+    }
+}
@@ -7,154 +7,337 @@ extern crate failure;
 extern crate env_logger;
 extern crate ansi_term;
 extern crate libc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
 use rtask::*;
-use rtask::command::{Command, Flag};
+use rtask::column::{CellContext, Column, DEFAULT_COLUMNS};
+use rtask::command::{filter_tasks, Command, CommandError, Flag};
+use rtask::config::{Config, CONFIG_FILE};
+use rtask::history::{AsOf, Change};
+use rtask::hooks::{Hooks, HOOKS_DIR};
+use rtask::task_ref::TaskRef;
+use rtask::theme::Role;
+use rtask::timesheet::{format_duration, Timesheet};
 
 mod cli;
 
+mod color;
+use color::Colors;
+
+mod completion;
+
+mod daemon;
+
+mod pager;
+
+mod session;
+use session::{Output, Session};
+
+mod tui;
+
 mod printer;
 use printer::*;
 
 mod terminal_size;
-use terminal_size::*;
 
 
-use std::io::ErrorKind;
-use std::{env, fmt, fs, io, mem};
+use std::collections::HashSet;
+use std::io::Write;
+use std::{env, fs, io, mem};
 
-pub const PID_FILE: &'static str = "tasks.pid";
+// TODO: Use a better error enum
+#[derive(Debug, Fail)]
+enum HandleCommandError {
+    #[fail(display = "Failed to find task: {}", _0)]
+    FindTaskError(FindTaskError),
+    #[fail(display = "{}", _0)]
+    FormatError(formats::Error),
+    #[fail(display = "{}", _0)]
+    Invalid(String),
+}
 
-#[derive(Debug, PartialEq, Eq)]
-struct Scope(Option<String>);
+impl From<FindTaskError> for HandleCommandError {
+    fn from(other: FindTaskError) -> Self {
+        HandleCommandError::FindTaskError(other)
+    }
+}
 
-use std::ops::Deref;
-impl Deref for Scope {
-    type Target = str;
-    fn deref(&self) -> &str {
-        // .as_ref().map(AsRef::as_ref), wtf?!
-        self.0.as_ref().map(AsRef::as_ref).unwrap_or("default")
+impl From<CommandError> for HandleCommandError {
+    fn from(other: CommandError) -> Self {
+        match other {
+            CommandError::FindTask(e) => HandleCommandError::FindTaskError(e),
+            CommandError::Invalid(message) => HandleCommandError::Invalid(message),
+        }
     }
 }
 
-impl fmt::Display for Scope {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        f.write_str(self.as_ref())
+impl From<formats::Error> for HandleCommandError {
+    fn from(other: formats::Error) -> Self {
+        HandleCommandError::FormatError(other)
     }
 }
 
-impl Scope {
-    pub fn as_tag(&self) -> Option<Tag> {
-        self.0.clone()
+impl From<io::Error> for HandleCommandError {
+    fn from(other: io::Error) -> Self {
+        HandleCommandError::FormatError(other.into())
     }
 }
 
-// TODO: Use a better error enum
-#[derive(Debug, Fail)]
-enum HandleCommandError {
-    #[fail(display = "Failed to find task: {}", _0)]
-    FindTaskError(FindTaskError),
+fn print_changes(out: &mut io::Write, changes: &[Change]) -> io::Result<()> {
+    for change in changes {
+        let meta = &change.stored.meta;
+        let prefix = format!(
+            "{}  {}  {}",
+            date::format_local(&meta.time),
+            meta.origin,
+            Task::short_uuid(change.stored.effect.task_id())
+        );
+
+        for (n, line) in change.lines().iter().enumerate() {
+            if n == 0 {
+                writeln!(out, "{}  {}", prefix, line)?;
+            } else {
+                writeln!(out, "{:width$}  {}", "", line, width = prefix.len())?;
+            }
+        }
+    }
+    Ok(())
 }
 
-impl From<FindTaskError> for HandleCommandError {
-    fn from(other: FindTaskError) -> Self {
-        HandleCommandError::FindTaskError(other)
+/// The theme roles of the `n`-th row of a task list
+fn task_roles(task: &Task, n: usize) -> Vec<Role> {
+    let mut roles = vec![];
+    if n % 2 == 0 {
+        roles.push(Role::AlternateRow);
+    }
+    match task.priority {
+        Priority::High => roles.push(Role::HighPriority),
+        Priority::Low => roles.push(Role::LowPriority),
+        Priority::Default => (),
+    }
+    if task.is_active() {
+        roles.push(Role::Active);
+    }
+    if task.is_overdue() {
+        roles.push(Role::Overdue);
+    }
+    roles
+}
+
+fn write_task_details(out: &mut io::Write, task: &Task) -> io::Result<()> {
+    macro_rules! p {
+        ( $( ($k:ident, $v:expr), )* ) => {
+            $(
+                writeln!(out, "{:<15} {}", stringify!($k), $v)?;
+            )*
+        }
+    }
+
+    let tag_list = task.tags
+        .iter()
+        .map(|s| &s[..])
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let spent = task.intervals
+        .iter()
+        .fold(chrono::Duration::zero(), |acc, i| {
+            acc + i.duration_between(i.start, chrono::Utc::now())
+        });
+
+    p!(
+        (uuid, task.uuid),
+        (description, task.description),
+        (priority, task.priority),
+        (created, task.created),
+        (modified, task.modified),
+        (tags, tag_list),
+        (extras, format!("{:?}", task.extras)),
+        (time_spent, format_duration(&spent)),
+    );
+    if let Some(since) = task.active_since() {
+        p!((active_since, since),);
+    }
+    if let Some(wait) = task.wait {
+        p!((wait, date::format_local(&wait)),);
+    }
+    if let Some(scheduled) = task.scheduled {
+        p!((scheduled, date::format_local(&scheduled)),);
     }
+    if let Some(due) = task.due {
+        p!((due, date::format_local(&due)),);
+    }
+    Ok(())
 }
 
 // TODO: move to rtask crate
 fn command_to_effects(
     model: &mut Model,
     command: Command,
+    session: &Session,
+    colors: &Colors,
+    out: &mut Output,
 ) -> Result<Vec<Effect>, HandleCommandError> {
     info!("Command (prior scope handling): {:?}", command);
-    let scope = Scope(env::var("RTASK_SCOPE").ok());
+    let scope = session.scope();
 
     info!("Using scope {:?}", scope);
 
-    match command {
-        Command::List(mut flags) => {
+    match command.resolve(model, &scope)? {
+        Command::List(mut flags, options) => {
             scope.as_tag().map(|t| flags.push(Flag::TagPositive(t)));
 
             info!("Listing filtered by flags {:?}", flags);
 
-            if !flags.is_empty() {
+            // CSV output must not be mixed with informational messages
+            let verbose = !options.csv;
+
+            if !flags.is_empty() && verbose {
                 let flags = flags
                     .iter()
                     .map(|f| format!("{}", f))
                     .collect::<Vec<String>>()
                     .join(", ");
-                println!("Listing all tasks with flags {}", flags);
+                writeln!(out, "Listing all tasks with flags {}", flags)?;
             }
 
-            let task_ids: Vec<_> = model
-                .all_tasks()
-                .into_iter()
-                .filter(|t| t.is_open())
-                .filter(|t| flags.is_empty() || flags.iter().all(|f| f.matches(&t)))
-                .map(|t| t.uuid)
-                .collect();
+            // When looking at the past we work on a reconstructed
+            // model and leave the numerical ids alone
+            match options.as_of {
+                Some(AsOf::Time(t)) if verbose => {
+                    writeln!(out, "Showing tasks as of {}", date::format_local(&t))?
+                }
+                Some(AsOf::Effect(n)) if verbose => writeln!(out, "Showing tasks as of effect #{}", n)?,
+                _ => (),
+            }
+            let snapshot = options.as_of.as_ref().map(|as_of| model.as_of(as_of));
 
-            // Recalculate IDs
-            model.recalculate_numerical_ids(&scope, &task_ids[..]);
+            let mut tasks = filter_tasks(snapshot.as_ref().unwrap_or(model), &flags);
+            if let Some(ref sort) = options.sort {
+                sort.sort(&mut tasks, model, &scope);
+            }
 
-            let terminal_size = terminal_size();
+            // A task may show up in several groups, it keeps the ID of
+            // its first appearance
+            let groups: Vec<(Option<String>, Vec<Uuid>)> = match options.group {
+                Some(grouping) => grouping
+                    .group(&tasks)
+                    .into_iter()
+                    .map(|g| (Some(g.name), g.tasks.iter().map(|t| t.uuid).collect()))
+                    .collect(),
+                None => vec![(None, tasks.iter().map(|t| t.uuid).collect())],
+            };
+            let mut task_ids: Vec<Uuid> = vec![];
+            for uuid in groups.iter().flat_map(|&(_, ref uuids)| uuids) {
+                if !task_ids.contains(uuid) {
+                    task_ids.push(*uuid);
+                }
+            }
+
+            // Recalculate IDs
+            if snapshot.is_none() {
+                model.recalculate_numerical_ids(&scope, &task_ids[..]);
+            }
+            let source: &Model = snapshot.as_ref().unwrap_or(model);
 
             let filtered_tasks: Vec<_> = task_ids
                 .iter()
-                .map(|uuid| model.tasks.get(uuid).unwrap())
+                .map(|uuid| source.tasks.get(uuid).unwrap())
                 .collect();
 
-            let task_limit = terminal_size.rows - 4; // TODO: Use a better number
+            // Terminals without a pager get a screen full of tasks at a
+            // time, everything else isn't truncated unless asked for
+            let interactive = !options.csv && session.is_tty;
+            out.page = interactive && session.pager.is_some();
+            let page_size = if interactive && (!out.page || options.page.is_some()) {
+                Some(session.rows.saturating_sub(4).max(1))
+            } else {
+                None
+            };
+            let range = options.visible_range(task_ids.len(), page_size);
+            let visible: HashSet<&Uuid> = task_ids[range.clone()].iter().collect();
+
+            let mut columns = options.columns.clone();
+            if columns.is_empty() {
+                columns = DEFAULT_COLUMNS.to_vec();
+                // Only show the `act` column if there's something to show
+                if filtered_tasks.iter().any(|t| t.is_active()) {
+                    columns.insert(1, Column::Active);
+                }
+            }
 
-            let rows: Vec<_> = filtered_tasks
-                .iter()
-                .enumerate()
-                .map(|(n, task)| {
-                    let short = model
-                        .short_task_id(&scope, &task.uuid)
-                        .map(|n| n.to_string())
-                        .unwrap_or(task.short_id());
-
-                    let values = vec![
-                        short,
-                        task.priority.to_string(),
-                        task.age().to_string(),
-                        task.description.clone(),
-                        task.tags.iter().cloned().collect::<Vec<_>>().join(","),
-                        format!("{:.2}", task.urgency()),
-                    ];
-
-                    let mut style = Style::default();
-                    if n % 2 == 0 {
-                        style = style.on(Colour::RGB(40, 40, 40))
-                    };
-                    style = match task.priority {
-                        Priority::High => style.fg(Colour::RGB(250, 50, 50)),
-                        Priority::Low => style.fg(Colour::RGB(150, 150, 150)),
-                        _ => style,
-                    };
-
-                    PrintRow {
-                        fields: values,
-                        style: Some(style),
+            let context = CellContext {
+                model: model,
+                scope: &scope,
+                tag_separator: options.tag_separator.as_ref().map_or(",", |s| &s[..]),
+            };
+
+            let task_row = |n: usize, task: &Task| {
+                let values = columns.iter().map(|c| c.value(task, &context)).collect();
+
+                let style = colors.combined(&task_roles(task, n));
+
+                PrintRow {
+                    fields: values,
+                    style: Some(style),
+                    header: false,
+                }
+            };
+
+            let mut rows = vec![];
+            for &(ref name, ref group) in groups.iter() {
+                let uuids: Vec<&Uuid> = group.iter().filter(|u| visible.contains(u)).collect();
+                if uuids.is_empty() {
+                    continue;
+                }
+
+                if let Some(ref name) = *name {
+                    if !options.csv {
+                        let title = format!("{} ({})", name, group.len());
+                        rows.push(PrintRow::group_header(title, colors.style(Role::GroupHeader)));
                     }
-                })
-                .take(task_limit)
-                .collect();
+                }
 
-            if !rows.is_empty() {
-                let mut p = TablePrinter::new();
-                p.titles = vec!["id", "pri", "age", "desc", "tags", "urg"];
-                p.width_limit = Some(terminal_size.columns - (2*p.titles.len()));
-                p.alignments.insert("desc", Alignment::Left);
-                p.print(&mut io::stdout(), &rows).unwrap();
+                for (n, uuid) in uuids.into_iter().enumerate() {
+                    let mut row = task_row(n, source.tasks.get(uuid).unwrap());
+                    // CSV has no headers, the group becomes a column
+                    match *name {
+                        Some(ref name) if options.csv => row.fields.insert(0, name.clone()),
+                        _ => (),
+                    }
+                    rows.push(row);
+                }
+            }
 
-                if filtered_tasks.len() > rows.len() {
-                    println!("There are {} more tasks", filtered_tasks.len() - rows.len());
+            let mut p = TablePrinter::new();
+            p.header_style = colors.style(Role::Header);
+            p.titles = columns.iter().map(|c| c.title()).collect();
+            if options.csv && options.group.is_some() {
+                p.titles.insert(0, "group");
+            }
+            p.width_limit = Some(session.columns.saturating_sub(2 * p.titles.len()));
+            p.alignments.insert(Column::Description.title(), Alignment::Left);
+            p.flexible.insert(Column::Description.title());
+            p.flexible.insert(Column::Tags.title());
+
+            if options.csv {
+                p.print_csv(out, &rows)?;
+            } else if !rows.is_empty() {
+                p.print(out, &rows).unwrap();
+
+                if range.end < task_ids.len() {
+                    writeln!(
+                        out,
+                        "There are {} more tasks, use --page {} or --all to see them",
+                        task_ids.len() - range.end,
+                        options.page.unwrap_or(1) + 1
+                    )?;
                 }
             } else {
-                println!("No matching tasks found");
+                writeln!(out, "No matching tasks found")?;
             }
 
             Ok(vec![])
@@ -164,7 +347,7 @@ fn command_to_effects(
                 let task = model.find_task(&scope, &task_ref);
 
                 if task.is_err() {
-                    println!("Couldn't find task {}", task_ref);
+                    writeln!(out, "Couldn't find task {}", task_ref)?;
                     continue;
                 }
 
@@ -172,180 +355,283 @@ fn command_to_effects(
 
                 match scope.as_tag() {
                     Some(ref t) if !task.tags.contains(t) => {
-                        println!("Note: Task {} isn't in scope {}", task_ref, scope);
+                        writeln!(out, "Note: Task {} isn't in scope {}", task_ref, scope)?;
                     }
                     _ => (),
                 }
 
-                macro_rules! p {
-          ( $( ($k:ident, $v:expr), )* ) => {
-            $(
-              println!("{:<15} {}", stringify!($k), $v);
-            )*
-          }
-        }
-
-                let tag_list = task.tags
-                    .iter()
-                    .map(|s| &s[..])
-                    .collect::<Vec<_>>()
-                    .join(", ");
-
-                println!("==== task {} ====", task_ref);
-
-                p!(
-                    (uuid, task.uuid),
-                    (description, task.description),
-                    (priority, task.priority),
-                    (created, task.created),
-                    (modified, task.modified),
-                    (tags, tag_list),
-                    (extras, format!("{:?}", task.extras)),
-                );
+                writeln!(out, "==== task {} ====", task_ref)?;
+                write_task_details(out, task)?;
             }
 
             Ok(vec![])
         }
-        Command::Add(title, flags) => {
-            // If in a scope, add scope-tag to `tags`
-            let tags = if let Some(tag) = scope.as_tag() {
-                vec![tag]
-            } else {
-                vec![]
-            };
-
-            info!("Got flags: {:?}", flags);
+        Command::History(refs) => {
+            for task_ref in refs {
+                // Deleted tasks can still be referenced by their full UUID
+                let uuid = match (model.find_task(&scope, &task_ref), &task_ref) {
+                    (Ok(task), _) => task.uuid,
+                    (Err(_), &TaskRef::FullUUID(uuid)) => uuid,
+                    (Err(_), _) => {
+                        writeln!(out, "Couldn't find task {}", task_ref)?;
+                        continue;
+                    }
+                };
 
-            let mut task = Task::new_with_tags(&title, tags.into_iter().collect());
-            for flag in flags {
-                flag.apply_to(&mut task);
+                writeln!(out, "==== history of task {} ====", task_ref)?;
+                print_changes(out, &model.task_history(&uuid))?;
             }
 
-            Ok(vec![Effect::AddTask(task)])
+            Ok(vec![])
         }
-        Command::Delete(refs) => {
-            let effects = refs.iter()
-                .flat_map(|tr| model.find_task(&scope, tr))
-                .map(|t| Effect::DeleteTask(t.uuid.clone()))
-                .collect();
+        Command::Import { format, path } => {
+            let input = fs::read_to_string(&path)?;
+            let modified = fs::metadata(&path)?.modified()?;
+            let tasks = formats::read(format, &input, modified.into())?;
+            let n_tasks = tasks.len();
+            let effects = formats::import_effects(model, tasks, format)?;
+
+            let n_added = effects
+                .iter()
+                .filter(|e| if let Effect::AddTask(_) = e { true } else { false })
+                .count();
+            let n_updated = effects
+                .iter()
+                .map(|e| e.task_id())
+                .filter(|uuid| model.get_task(uuid).is_some())
+                .collect::<HashSet<_>>()
+                .len();
+            writeln!(
+                out,
+                "Importing {} tasks from {}: {} new, {} changed",
+                n_tasks, path, n_added, n_updated
+            )?;
 
             Ok(effects)
         }
-        Command::MarkDone(refs) => {
-            let state = TaskState::Done(chrono::Utc::now());
-            let effects = refs.iter()
-                .flat_map(|tr| model.find_task(&scope, tr))
-                .map(|t| Effect::ChangeTaskState(t.uuid.clone(), state.clone()))
+        Command::Export {
+            format,
+            flags,
+            output,
+        } => {
+            let tasks: Vec<&Task> = model
+                .all_tasks()
+                .filter(|t| flags.iter().all(|f| f.matches(&t)))
                 .collect();
+            match output {
+                Some(path) => {
+                    let mut file = fs::File::create(&path)?;
+                    formats::write(format, &tasks, &mut file)?;
+                    writeln!(out, "Exported {} tasks to {}", tasks.len(), path)?;
+                }
+                None => formats::write(format, &tasks, out)?,
+            }
 
-            Ok(effects)
+            Ok(vec![])
         }
-        Command::MarkCanceled(refs) => {
-            //
-            let state = TaskState::Canceled(chrono::Utc::now());
-            let effects = refs.iter()
-                .flat_map(|tr| model.find_task(&scope, tr))
-                .map(|t| Effect::ChangeTaskState(t.uuid.clone(), state.clone()))
-                .collect();
+        Command::Log(since) => {
+            let changes = model.changes_since(since);
+            if changes.is_empty() {
+                writeln!(out, "No changes found")?;
+            } else {
+                print_changes(out, &changes)?;
+            }
 
-            Ok(effects)
+            Ok(vec![])
         }
-        Command::ChangeTaskProperties {
-            task_refs,
-            added_tags,
-            removed_tags,
-            priority,
-        } => {
-            let mut effects = vec![];
+        Command::Timesheet(since) => {
+            let sheet = Timesheet::new(model, since, chrono::Utc::now());
+            writeln!(out, "Time spent since {}", date::format_local(&since))?;
 
-            for task_ref in task_refs {
-                let task = model.find_task(&scope, &task_ref)?;
+            if sheet.tasks.is_empty() {
+                writeln!(out, "No time tracked")?;
+                return Ok(vec![]);
+            }
 
-                if let Some(p) = priority {
-                    effects.push(Effect::ChangeTaskPriority(task.uuid.clone(), p));
-                }
+            let mut p = TablePrinter::new();
+            p.header_style = colors.style(Role::Header);
+            p.titles = vec!["task", "time"];
+            p.alignments.insert("task", Alignment::Left);
+            let rows: Vec<_> = sheet
+                .tasks
+                .iter()
+                .map(|&(ref uuid, ref spent)| PrintRow {
+                    fields: vec![
+                        model.get_task(uuid).unwrap().description.clone(),
+                        format_duration(spent),
+                    ],
+                    style: None,
+                    header: false,
+                })
+                .collect();
+            p.print(out, &rows).unwrap();
+
+            if !sheet.tags.is_empty() {
+                writeln!(out)?;
+                let mut p = TablePrinter::new();
+                p.header_style = colors.style(Role::Header);
+                p.titles = vec!["tag", "time"];
+                p.alignments.insert("tag", Alignment::Left);
+                let rows: Vec<_> = sheet
+                    .tags
+                    .iter()
+                    .map(|(tag, spent)| PrintRow {
+                        fields: vec![tag.clone(), format_duration(spent)],
+                        style: None,
+                        header: false,
+                    })
+                    .collect();
+                p.print(out, &rows).unwrap();
+            }
 
-                if !added_tags.is_empty() || !removed_tags.is_empty() {
-                    effects.push(Effect::ChangeTaskTags {
-                        uuid: task.uuid.clone(),
-                        added: added_tags.clone(),
-                        removed: removed_tags.clone(),
-                    });
+            writeln!(out, "\nTotal: {}", format_duration(&sheet.total))?;
+
+            Ok(vec![])
+        }
+        Command::Tui => {
+            tui::run(model, session, colors)?;
+            Ok(vec![])
+        }
+        Command::Completions(shell) => {
+            completion::write_script(&shell, out)?;
+            Ok(vec![])
+        }
+        Command::CompletionCandidates(args) => {
+            for (value, description) in completion::candidates(model, &scope, &args) {
+                match description {
+                    Some(description) => writeln!(out, "{}\t{}", value, description)?,
+                    None => writeln!(out, "{}", value)?,
                 }
             }
+            Ok(vec![])
+        }
+        Command::Daemon { .. } => {
+            writeln!(out, "The daemon is already running")?;
+            Ok(vec![])
+        }
+        command => {
+            let changes = command.effects(model, &scope)?;
+            for note in changes.notes {
+                writeln!(out, "{}", note)?;
+            }
 
-            Ok(effects)
+            Ok(changes.effects)
         }
     }
 }
 
-fn main() {
-    env_logger::init();
-    chdir();
-
-    let mut lock = FileLock::new(PID_FILE).expect("Failed to acquire lock");
-    lock.delete_on_drop = true;
+/// Runs `command` and applies its effects, everything it prints ends up
+/// in the returned output
+fn run_command(model: &mut Model, command: Command, session: &Session, colors: &Colors) -> io::Result<Output> {
+    let mut out = Output::default();
+    model.origin = session.device.clone().unwrap_or_else(hostname);
 
-    let command = cli::get_command();
-
-    let mut store = Storage::new().expect("Failed to open store");
-    // let command = Command::from_args();
-
-    match command {
+    match command_to_effects(model, command, session, colors, &mut out) {
+        // TODO: Store TaskRef in these errors (and simply the naming)
+        Err(HandleCommandError::FindTaskError(FindTaskError::MultipleResults)) => {
+            writeln!(out, "Multiple matching tasks found")?;
+        }
+        Err(HandleCommandError::FindTaskError(FindTaskError::TaskNotFound)) => {
+            writeln!(out, "No matching task found")?;
+        }
         Err(error) => {
-            println!("Error while parsing command: {}", error.0);
-            return;
+            writeln!(out, "Error: {}", error)?;
         }
-        Ok(command) => {
-            let mut model = store.model();
-            match command_to_effects(&mut model, command) {
-                // TODO: Store TaskRef in these errors (and simply the naming)
-                Err(HandleCommandError::FindTaskError(FindTaskError::MultipleResults)) => {
-                    println!("Multiple matching tasks found");
-                }
-                Err(HandleCommandError::FindTaskError(FindTaskError::TaskNotFound)) => {
-                    println!("No matching task found");
+        Ok(effects) => match Hooks::load(HOOKS_DIR)?.apply(model, effects) {
+            Ok(changes) => {
+                for effect in changes.effects {
+                    effect.print(model, &mut out)?;
                 }
-                Ok(effects) => {
-                    for effect in effects {
-                        info!("Applying Effect: {:?}", effect);
-
-                        model.apply_effect(&effect);
-                        effect.print(&model, &mut io::stdout()).unwrap();
-                    }
+                for note in changes.notes {
+                    writeln!(out, "{}", note)?;
                 }
             }
-        }
+            Err(error) => {
+                writeln!(out, "Error: {}", error)?;
+            }
+        },
     }
 
-    mem::drop(store);
+    Ok(out)
 }
 
-fn chdir() {
-    let dir = env::var("RTASK_DIRECTORY")
-        .map(Into::into)
-        .unwrap_or_else(|_| {
-            let mut dir = env::home_dir().expect("Couldn't get home dir");
-            dir.push(".rtask/");
-            dir
-        });
-    fs::create_dir_all(&dir).expect("Failed to create directory");
+fn main() {
+    env_logger::init();
+    // Files given on the command line are relative to this
+    let cwd = env::current_dir().expect("Failed to get the working directory");
+    chdir();
+
+    let config = match Config::load(CONFIG_FILE) {
+        Ok(config) => config,
+        Err(error) => {
+            println!("Error while loading {}: {}", CONFIG_FILE, error);
+            return;
+        }
+    };
 
-    let dir = dir.canonicalize().expect("Failed to get absolute path");
+    let (command, color_choice, args) = match cli::get_command(&config, &cwd) {
+        Ok(command) => command,
+        Err(error) => {
+            println!("Error while parsing command: {}", error.0);
+            return;
+        }
+    };
+    let session = Session::current(config.pager.as_ref().map(|p| &p[..]), color_choice);
+
+    // Everything else is handed to the daemon if it's running
+    let daemon = daemon::Client::connect();
+    let output = match (command, daemon) {
+        (Command::Daemon { stop: false }, Some(_)) => {
+            println!("The daemon is already running");
+            return;
+        }
+        (Command::Daemon { stop: false }, None) => {
+            if let Err(error) = daemon::serve() {
+                println!("Error: {}", error);
+            }
+            return;
+        }
+        (Command::Daemon { stop: true }, Some(mut daemon)) => {
+            match daemon.stop() {
+                Ok(()) => println!("Stopped the daemon"),
+                Err(error) => println!("Error: {}", error),
+            }
+            return;
+        }
+        (Command::Daemon { stop: true }, None) => {
+            println!("The daemon isn't running");
+            return;
+        }
+        (Command::Tui, Some(_)) => {
+            println!("The TUI can't be used while the daemon is running, stop it with `rtask daemon --stop`");
+            return;
+        }
+        (_, Some(mut daemon)) => match daemon.run(args, &session) {
+            Ok(output) => output,
+            Err(error) => {
+                println!("Error: {}", error);
+                return;
+            }
+        },
+        (command, None) => {
+            let _lock = FileLock::new(PID_FILE).expect("Failed to acquire lock");
 
-    info!("Working directory: {}", dir.display());
+            let mut store = Storage::new().expect("Failed to open store");
+            let colors = Colors::new(config.theme.clone(), session.color_depth);
+            let output = run_command(store.model(), command, &session, &colors).expect("Failed to run command");
 
-    match fs::create_dir(&dir) {
-        Err(ref err) if err.kind() == ErrorKind::AlreadyExists => (),
-        Ok(_) => (),
-        Err(effect) => panic!("Couldn't create {}: {}", &dir.display(), effect),
-    }
+            mem::drop(store);
+            output
+        }
+    };
 
-    env::set_current_dir(&dir).expect("Couldn't chdir");
+    output.show(&session).expect("Failed to print output");
 }
 
 #[cfg(test)]
 mod tests {
+    use super::color::{ColorChoice, Colors};
+    use super::session::{Output, Session};
     use super::rtask::command::*;
     use super::rtask::*;
 
@@ -360,9 +646,13 @@ mod tests {
             added_tags: Tags::new(),
             removed_tags: Tags::new(),
             priority: None,
+            dates: Default::default(),
         };
 
-        let effects = super::command_to_effects(&mut m, c).unwrap();
+        let session = Session::current(None, ColorChoice::Never);
+        let colors = Colors::new(Default::default(), session.color_depth);
+        let mut out = Output::default();
+        let effects = super::command_to_effects(&mut m, c, &session, &colors, &mut out).unwrap();
         assert!(effects.is_empty());
     }
 }
@@ -0,0 +1,58 @@
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// The configured pager, `$PAGER` or `less`. `None` if paging is
+/// disabled by an empty command.
+pub fn pager_command(configured: Option<&str>) -> Option<String> {
+    let command = configured
+        .map(String::from)
+        .or_else(|| env::var("PAGER").ok())
+        .unwrap_or_else(|| "less".into());
+
+    if command.trim().is_empty() {
+        None
+    } else {
+        Some(command)
+    }
+}
+
+/// Shows `output` in the pager, or prints it if the pager can't be
+/// started
+pub fn page(command: &str, output: &[u8]) -> io::Result<()> {
+    let mut pager = Command::new("sh");
+    pager.arg("-c").arg(command).stdin(Stdio::piped());
+    // Quit if everything fits on one screen and keep the colors
+    if env::var_os("LESS").is_none() {
+        pager.env("LESS", "FRX");
+    }
+
+    let mut child = match pager.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to start pager {:?}: {}", command, e);
+            return print(output);
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        ignore_broken_pipe(stdin.write_all(output))?;
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Prints `output` to stdout. Readers like `head` that stop early
+/// aren't an error.
+pub fn print(output: &[u8]) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    ignore_broken_pipe(stdout.write_all(output).and_then(|_| stdout.flush()))
+}
+
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
@@ -1,6 +1,6 @@
-pub use ansi_term::{Colour, Style};
-use std::collections::HashMap;
-use std::io;
+pub use ansi_term::Style;
+use std::collections::{HashMap, HashSet};
+use std::{io, mem};
 
 use task::StringExt;
 
@@ -14,20 +14,40 @@ pub enum Alignment {
 pub struct TablePrinter {
     pub titles: Vec<&'static str>,
     pub alignments: HashMap<&'static str, Alignment>,
+    /// Columns that get narrower and wrap their text when the table
+    /// doesn't fit into `width_limit`, all others keep their width
+    pub flexible: HashSet<&'static str>,
+    /// Cut flexible columns instead of wrapping them, so every row
+    /// takes a single line
+    pub ellipsize: bool,
     pub width_limit: Option<usize>,
+    pub header_style: Style,
 }
 
+/// Flexible columns aren't shrunk below this width or their title
+const MIN_FLEXIBLE_WIDTH: usize = 10;
+
 pub struct PrintRow {
     pub fields: Vec<String>,
     pub style: Option<Style>,
+    /// Group headers span the whole table, their only field is the title
+    pub header: bool,
+}
+
+impl PrintRow {
+    pub fn group_header(title: String, style: Style) -> Self {
+        PrintRow {
+            fields: vec![title],
+            style: Some(style),
+            header: true,
+        }
+    }
 }
 
 #[derive(Debug, Fail)]
 pub enum PrintError {
     #[fail(display = "IO Error: {}", _0)]
     IO(io::Error),
-    #[fail(display = "Terminal not wide enough for output")]
-    TerminalTooNarrow,
 }
 
 impl From<io::Error> for PrintError {
@@ -41,60 +61,328 @@ impl TablePrinter {
         TablePrinter {
             titles: vec![],
             alignments: HashMap::new(),
+            flexible: HashSet::new(),
+            ellipsize: false,
             width_limit: None,
+            header_style: Style::default().bold().underline(),
         }
     }
 
     pub fn print(&self, writer: &mut io::Write, rows: &[PrintRow]) -> Result<(), PrintError> {
-        // TODO: Implement "dumb" output for dumb terminals
-
-        let mut widths: Vec<usize> = self.titles.iter().map(|x| x.len()).collect();
-        for row in rows.iter() {
+        let mut widths: Vec<usize> = self.titles.iter().map(|x| x.display_width()).collect();
+        for row in rows.iter().filter(|r| !r.header) {
             for (n, field) in row.fields.iter().enumerate() {
                 use std::cmp;
-                widths[n] = cmp::max(widths[n], field.len());
+                widths[n] = cmp::max(widths[n], field.display_width());
             }
         }
 
-        if let Some(width_limit) = self.width_limit {
-            let max_width: usize = widths.iter().sum();
-            if max_width >= width_limit {
-                return Err(PrintError::TerminalTooNarrow);
-            }
-        }
+        self.fit_widths(&mut widths);
 
-        let header_style = Style::default().bold().underline();
-        for (title, width) in self.titles.iter().zip(widths.iter()) {
-            // TODO: Report this issues to ansi_term
-            // TODO: styled_title and styled_title.to_string() have different .len()?!
-            let styled_title = header_style.paint(*title).to_string();
-            let diff = styled_title.len() - title.len();
-            try!(write!(writer, " {0:^1$} ", styled_title, width + diff));
+        for (title, &width) in self.titles.iter().zip(widths.iter()) {
+            // Only the title itself is underlined, not the padding
+            let (left, right) = padding(title, width, Alignment::Center);
+            try!(write!(
+                writer,
+                " {}{}{} ",
+                left,
+                self.header_style.paint(*title),
+                right
+            ));
         }
         try!(write!(writer, "\n"));
 
+        let table_width = (widths.iter().map(|w| w + 2).sum::<usize>()).saturating_sub(2).max(1);
         for row in rows.iter() {
             let style = row.style.unwrap_or(Style::default());
 
-            for (n, (text, title)) in row.fields.iter().zip(self.titles.iter()).enumerate() {
-                let width = widths[n];
-                let alignment = self.alignments
-                    .get(title)
-                    .map(|a| *a)
-                    .unwrap_or(Alignment::Center);
-
-                use self::Alignment::*;
-                let line = match alignment {
-                    Left => format!(" {0:<1$} ", text.ellipsize(width), width),
-                    Center => format!(" {0:^1$} ", text.ellipsize(width), width),
-                    Right => format!(" {0:>1$} ", text.ellipsize(width), width),
-                };
-                try!(write!(writer, "{}", style.paint(line)));
+            if row.header {
+                let title = row.fields.get(0).map_or("", |t| &t[..]);
+                let title = title.ellipsize(table_width);
+                let line = format!(" {} ", pad(&title, table_width, Alignment::Left));
+                try!(write!(writer, "{}\n", style.paint(line)));
+                continue;
             }
 
-            try!(write!(writer, "\n"));
+            // Only flexible columns can be narrower than their text
+            let cells: Vec<Vec<String>> = row.fields
+                .iter()
+                .zip(widths.iter())
+                .map(|(text, &width)| {
+                    if text.display_width() > width && self.ellipsize {
+                        vec![text.ellipsize(width).into_owned()]
+                    } else if text.display_width() > width {
+                        wrap(text, width)
+                    } else {
+                        vec![text.clone()]
+                    }
+                })
+                .collect();
+            let height = cells.iter().map(|c| c.len()).max().unwrap_or(1);
+
+            for line_no in 0..height {
+                for (n, (cell, title)) in cells.iter().zip(self.titles.iter()).enumerate() {
+                    let width = widths[n];
+                    let text = cell.get(line_no).map_or("", |l| &l[..]);
+                    let alignment = self.alignments
+                        .get(title)
+                        .map(|a| *a)
+                        .unwrap_or(Alignment::Center);
+
+                    let line = format!(" {} ", pad(text, width, alignment));
+                    try!(write!(writer, "{}", style.paint(line)));
+                }
+
+                try!(write!(writer, "\n"));
+            }
         }
 
         Ok(())
     }
+
+    /// Shrinks the widest flexible column until the table fits into
+    /// `width_limit`. If that isn't enough the table gets too wide.
+    fn fit_widths(&self, widths: &mut [usize]) {
+        let width_limit = match self.width_limit {
+            Some(width_limit) => width_limit,
+            None => return,
+        };
+
+        while widths.iter().sum::<usize>() >= width_limit {
+            let widest = self.titles
+                .iter()
+                .enumerate()
+                .filter(|&(n, title)| {
+                    self.flexible.contains(title) && widths[n] > MIN_FLEXIBLE_WIDTH.max(title.display_width())
+                })
+                .max_by_key(|&(n, _)| widths[n])
+                .map(|(n, _)| n);
+
+            match widest {
+                Some(n) => widths[n] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    /// Prints the rows as comma separated values (RFC 4180), ignoring
+    /// styles, group headers and the width limit
+    pub fn print_csv(&self, writer: &mut io::Write, rows: &[PrintRow]) -> io::Result<()> {
+        let titles: Vec<String> = self.titles.iter().map(|t| t.to_string()).collect();
+        try!(write_csv_record(writer, &titles));
+        for row in rows.iter().filter(|r| !r.header) {
+            try!(write_csv_record(writer, &row.fields));
+        }
+        Ok(())
+    }
+}
+
+/// The spaces before and after `text` to fill `width` columns
+fn padding(text: &str, width: usize, alignment: Alignment) -> (String, String) {
+    let fill = width.saturating_sub(text.display_width());
+    let left = match alignment {
+        Alignment::Left => 0,
+        Alignment::Center => fill / 2,
+        Alignment::Right => fill,
+    };
+    (" ".repeat(left), " ".repeat(fill - left))
+}
+
+/// Pads `text` to `width` columns. Unlike `format!` this counts wide
+/// characters as two columns and combining ones as none.
+fn pad(text: &str, width: usize, alignment: Alignment) -> String {
+    let (left, right) = padding(text, width, alignment);
+    format!("{}{}{}", left, text, right)
+}
+
+/// Breaks `text` into lines of at most `width` columns at whitespace,
+/// words longer than a line are split
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for mut word in text.split_whitespace() {
+        loop {
+            let line_len = line.display_width();
+            let word_len = word.display_width();
+
+            if line_len == 0 && word_len <= width {
+                line.push_str(word);
+            } else if line_len > 0 && line_len + 1 + word_len <= width {
+                line.push(' ');
+                line.push_str(word);
+            } else if line_len > 0 {
+                lines.push(mem::replace(&mut line, String::new()));
+                continue;
+            } else {
+                let mut split = word.truncate_width(width).len();
+                // Always make progress, even if a wide character doesn't fit
+                if split == 0 {
+                    split = word.chars().next().map_or(word.len(), |c| c.len_utf8());
+                }
+                lines.push(word[..split].to_string());
+                word = &word[split..];
+                continue;
+            }
+            break;
+        }
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_csv_record(writer: &mut io::Write, fields: &[String]) -> io::Result<()> {
+    let record: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    write!(writer, "{}\r\n", record.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(vec!["foo bar", "baz"], wrap("foo bar baz", 7));
+        assert_eq!(vec!["foo", "bar", "baz"], wrap("foo  bar\nbaz", 6));
+        assert_eq!(vec!["abcd", "efgh", "ij k"], wrap("abcdefghij k", 4));
+        assert_eq!(vec![""], wrap("", 4));
+        assert_eq!(vec!["日本", "語"], wrap("日本語", 5));
+        assert_eq!(vec!["日", "本"], wrap("日本", 1));
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!("日本  ", pad("日本", 6, Alignment::Left));
+        assert_eq!(" 日本 ", pad("日本", 6, Alignment::Center));
+        assert_eq!("  cafe\u{301}", pad("cafe\u{301}", 6, Alignment::Right));
+        assert_eq!("日本語", pad("日本語", 2, Alignment::Left));
+    }
+
+    #[test]
+    fn test_print_wide() {
+        let mut p = TablePrinter::new();
+        p.titles = vec!["id", "desc"];
+        p.alignments.insert("desc", Alignment::Left);
+        let rows = vec![
+            PrintRow {
+                fields: vec!["1".into(), "日本語".into()],
+                style: None,
+                header: false,
+            },
+            PrintRow {
+                fields: vec!["2".into(), "cafe\u{301}".into()],
+                style: None,
+                header: false,
+            },
+        ];
+
+        let mut out = vec![];
+        p.print(&mut out, &rows).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(vec![" 1   日本語 ", " 2   cafe\u{301}   "], lines);
+    }
+
+    #[test]
+    fn test_print_wrapped() {
+        let mut p = TablePrinter::new();
+        p.titles = vec!["id", "desc"];
+        p.alignments.insert("desc", Alignment::Left);
+        p.flexible.insert("desc");
+        p.width_limit = Some(18);
+        let rows = vec![PrintRow {
+            fields: vec!["123".into(), "a rather long description".into()],
+            style: None,
+            header: false,
+        }];
+
+        // The id column keeps its width, the description wraps
+        let mut out = vec![];
+        p.print(&mut out, &rows).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(
+            vec![
+                " 123  a rather long  ",
+                "      description    ",
+            ],
+            lines
+        );
+
+        p.ellipsize = true;
+        let mut out = vec![];
+        p.print(&mut out, &rows).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(vec![" 123  a rather lo... "], out.lines().skip(1).collect::<Vec<_>>());
+        p.ellipsize = false;
+
+        // Never narrower than the minimum, even if that doesn't fit
+        p.width_limit = Some(5);
+        let mut out = vec![];
+        p.print(&mut out, &rows).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(" 123  a rather   ", out.lines().nth(1).unwrap());
+    }
+
+    #[test]
+    fn test_print_csv() {
+        let mut p = TablePrinter::new();
+        p.titles = vec!["id", "desc"];
+        let rows = vec![
+            PrintRow {
+                fields: vec!["1".into(), "plain".into()],
+                style: None,
+                header: false,
+            },
+            PrintRow {
+                fields: vec!["2".into(), "a, \"quoted\"\nline".into()],
+                style: None,
+                header: false,
+            },
+        ];
+
+        let mut out = vec![];
+        p.print_csv(&mut out, &rows).unwrap();
+        assert_eq!(
+            "id,desc\r\n1,plain\r\n2,\"a, \"\"quoted\"\"\nline\"\r\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_print_group_header() {
+        let mut p = TablePrinter::new();
+        p.titles = vec!["id", "desc"];
+        let rows = vec![
+            PrintRow::group_header("work (1)".into(), Style::default()),
+            PrintRow {
+                fields: vec!["1".into(), "foo".into()],
+                style: None,
+                header: false,
+            },
+        ];
+
+        // Headers span all columns without widening them
+        let mut out = vec![];
+        p.print(&mut out, &rows).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(" work (1) ", lines[1]);
+        assert_eq!(" 1   foo  ", lines[2]);
+
+        let mut out = vec![];
+        p.print_csv(&mut out, &rows).unwrap();
+        assert_eq!("id,desc\r\n1,foo\r\n", String::from_utf8(out).unwrap());
+    }
 }
@@ -0,0 +1,74 @@
+use std::env;
+use std::io::{self, Write};
+
+use color::{self, ColorChoice};
+use pager::{self, pager_command};
+use terminal_size::{stdout_is_tty, terminal_size};
+use theme::ColorDepth;
+use Scope;
+
+/// The environment a command runs in. The daemon gets it from the
+/// client, so commands behave the same with and without a daemon.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// `RTASK_SCOPE`
+    pub scope: Option<String>,
+    /// `RTASK_DEVICE`, the hostname if unset
+    pub device: Option<String>,
+    pub columns: usize,
+    pub rows: usize,
+    /// False if the output is redirected to a file or pipe
+    pub is_tty: bool,
+    /// The pager for output that doesn't fit on the screen
+    pub pager: Option<String>,
+    pub color_depth: ColorDepth,
+}
+
+impl Session {
+    pub fn current(pager: Option<&str>, color_choice: ColorChoice) -> Self {
+        let size = terminal_size();
+        let is_tty = stdout_is_tty();
+
+        Session {
+            scope: env::var("RTASK_SCOPE").ok(),
+            device: env::var("RTASK_DEVICE").ok(),
+            columns: size.columns,
+            rows: size.rows,
+            is_tty: is_tty,
+            pager: if is_tty { pager_command(pager) } else { None },
+            color_depth: color::detect_color_depth(color_choice),
+        }
+    }
+
+    pub fn scope(&self) -> Scope {
+        Scope(self.scope.clone())
+    }
+}
+
+/// Everything a command prints, shown once it's done
+#[derive(Default)]
+pub struct Output {
+    pub buffer: Vec<u8>,
+    /// Show the output in the pager if it doesn't fit on the screen
+    pub page: bool,
+}
+
+impl Output {
+    pub fn show(&self, session: &Session) -> io::Result<()> {
+        let lines = self.buffer.iter().filter(|b| **b == b'\n').count();
+        match session.pager {
+            Some(ref pager) if self.page && lines > session.rows => pager::page(pager, &self.buffer),
+            _ => pager::print(&self.buffer),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -13,6 +13,11 @@ pub fn terminal_size() -> TerminalSize {
     terminal_size_internal().unwrap_or(DEFAULT_SIZE)
 }
 
+/// False if stdout is redirected to a file or pipe
+pub fn stdout_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
 const DEFAULT_SIZE: TerminalSize = TerminalSize {
     columns: 80,
     rows: 24,
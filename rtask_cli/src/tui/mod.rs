@@ -0,0 +1,375 @@
+//! A full-screen, keyboard driven task list. Changes are made with the
+//! same commands and effects as on the command line.
+
+mod term;
+
+use self::term::{Key, RawTerminal};
+
+use rtask::*;
+use rtask::column::{CellContext, Column, DEFAULT_COLUMNS};
+use rtask::command::{Command, Flag};
+use rtask::report::Report;
+use rtask::task_ref::TaskRef;
+use rtask::theme::Role;
+
+use std::io::{self, Write};
+
+use color::Colors;
+use printer::*;
+use session::{Output, Session};
+use task::StringExt;
+use terminal_size::*;
+use Scope;
+
+const HELP: &'static str =
+    "q quit  j/k move  enter details  d done  x cancel  s start/stop  +/- priority  t tag  m modify  / filter";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Prompt {
+    Filter,
+    Modify,
+}
+
+struct Tui<'a> {
+    model: &'a mut Model,
+    session: &'a Session,
+    colors: &'a Colors,
+    scope: Scope,
+    filter: Vec<Flag>,
+    filter_text: String,
+    /// The listed tasks, most urgent first
+    tasks: Vec<Uuid>,
+    selected: usize,
+    /// The first task on screen
+    offset: usize,
+    /// The number of tasks that fit on screen, as of the last draw
+    height: usize,
+    details: bool,
+    prompt: Option<(Prompt, String)>,
+    message: String,
+}
+
+/// Runs the TUI until the user quits
+pub fn run(model: &mut Model, session: &Session, colors: &Colors) -> io::Result<()> {
+    let stdin_is_tty = unsafe { ::libc::isatty(::libc::STDIN_FILENO) == 1 };
+    if !stdin_is_tty || !stdout_is_tty() {
+        return Err(io::Error::new(io::ErrorKind::Other, "The TUI needs a terminal"));
+    }
+
+    let mut tui = Tui {
+        model: model,
+        session: session,
+        colors: colors,
+        scope: session.scope(),
+        filter: vec![],
+        filter_text: String::new(),
+        tasks: vec![],
+        selected: 0,
+        offset: 0,
+        height: 1,
+        details: false,
+        prompt: None,
+        message: String::new(),
+    };
+    tui.refresh();
+
+    let _terminal = RawTerminal::enter()?;
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+
+    loop {
+        let mut screen = vec![];
+        tui.draw(&mut screen)?;
+        let mut out = stdout.lock();
+        out.write_all(&screen)?;
+        out.flush()?;
+
+        if !tui.handle_key(term::read_key(&mut input)?) {
+            return Ok(());
+        }
+    }
+}
+
+impl<'a> Tui<'a> {
+    fn selected_task(&self) -> Option<&Task> {
+        self.tasks
+            .get(self.selected)
+            .and_then(|uuid| self.model.get_task(uuid))
+    }
+
+    fn selected_ref(&self) -> Option<TaskRef> {
+        self.tasks.get(self.selected).map(|uuid| TaskRef::FullUUID(*uuid))
+    }
+
+    /// Lists the tasks again, the selection stays on the same task if
+    /// it is still listed
+    fn refresh(&mut self) {
+        let selected = self.tasks.get(self.selected).cloned();
+
+        let mut flags = self.filter.clone();
+        if let Some(tag) = self.scope.as_tag() {
+            flags.push(Flag::TagPositive(tag));
+        }
+        self.tasks = ::filter_tasks(self.model, &flags)
+            .iter()
+            .map(|t| t.uuid)
+            .collect();
+        self.model.recalculate_numerical_ids(&self.scope, &self.tasks);
+
+        let last = self.tasks.len().saturating_sub(1);
+        self.selected = selected
+            .and_then(|uuid| self.tasks.iter().position(|u| *u == uuid))
+            .unwrap_or_else(|| self.selected.min(last));
+    }
+
+    /// Applies the effects of `command` like the command line does,
+    /// its output becomes the status message
+    fn execute(&mut self, command: Command) {
+        let output = match ::run_command(self.model, command, self.session, self.colors) {
+            Ok(output) => output,
+            Err(error) => Output {
+                buffer: format!("Error: {}", error).into_bytes(),
+                page: false,
+            },
+        };
+        self.message = String::from_utf8_lossy(&output.buffer)
+            .lines()
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        self.refresh();
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let last = self.tasks.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).max(0).min(last) as usize;
+    }
+
+    fn change_priority(&mut self, up: bool) {
+        let priority = match self.selected_task() {
+            Some(task) => task.priority,
+            None => return,
+        };
+        let changed = match (priority, up) {
+            (Priority::Low, true) | (Priority::High, false) => Priority::Default,
+            (Priority::Default, true) => Priority::High,
+            (Priority::Default, false) => Priority::Low,
+            (p, _) => {
+                self.message = format!("Priority is already {}", p);
+                return;
+            }
+        };
+
+        let command = Command::ChangeTaskProperties {
+            task_refs: self.selected_ref().into_iter().collect(),
+            added_tags: Tags::new(),
+            removed_tags: Tags::new(),
+            priority: Some(changed),
+            dates: Default::default(),
+        };
+        self.execute(command);
+    }
+
+    fn submit(&mut self, prompt: Prompt, text: String) {
+        match prompt {
+            Prompt::Filter => match Report::parse_filter(&text) {
+                Ok(flags) => {
+                    self.filter = flags;
+                    self.filter_text = text;
+                    self.selected = 0;
+                    self.refresh();
+                }
+                Err(error) => self.message = format!("Error: {}", error),
+            },
+            Prompt::Modify => {
+                let task_ref = match self.selected_ref() {
+                    Some(task_ref) => task_ref,
+                    None => return,
+                };
+                let mut args = vec![task_ref.to_string(), "edit".to_string()];
+                args.extend(text.split_whitespace().map(String::from));
+
+                match Command::from_slice(&args) {
+                    Ok(command) => self.execute(command),
+                    Err(error) => self.message = format!("Error: {}", error.0),
+                }
+            }
+        }
+    }
+
+    /// Returns false if the user wants to quit
+    fn handle_key(&mut self, key: Key) -> bool {
+        if let Some((prompt, mut text)) = self.prompt.take() {
+            match key {
+                Key::Esc | Key::Ctrl('c') => (),
+                Key::Enter => self.submit(prompt, text),
+                Key::Backspace => {
+                    text.pop();
+                    self.prompt = Some((prompt, text));
+                }
+                Key::Char(c) => {
+                    text.push(c);
+                    self.prompt = Some((prompt, text));
+                }
+                _ => self.prompt = Some((prompt, text)),
+            }
+            return true;
+        }
+
+        self.message.clear();
+        let page = self.height.max(1) as isize;
+        let task_ref = self.selected_ref();
+
+        match key {
+            Key::Char('q') | Key::Esc | Key::Ctrl('c') => return false,
+            Key::Char('j') | Key::Down => self.move_selection(1),
+            Key::Char('k') | Key::Up => self.move_selection(-1),
+            Key::PageDown | Key::Ctrl('f') => self.move_selection(page),
+            Key::PageUp | Key::Ctrl('b') => self.move_selection(-page),
+            Key::Char('g') | Key::Home => self.selected = 0,
+            Key::Char('G') | Key::End => self.move_selection(isize::max_value() / 2),
+            Key::Enter => self.details = !self.details,
+            Key::Char('/') => self.prompt = Some((Prompt::Filter, self.filter_text.clone())),
+            Key::Char('m') if task_ref.is_some() => self.prompt = Some((Prompt::Modify, String::new())),
+            Key::Char('t') if task_ref.is_some() => self.prompt = Some((Prompt::Modify, "+".into())),
+            Key::Char('+') => self.change_priority(true),
+            Key::Char('-') => self.change_priority(false),
+            Key::Char(c) => {
+                let task_ref = match task_ref {
+                    Some(task_ref) => task_ref,
+                    None => return true,
+                };
+                let active = self.selected_task().map_or(false, |t| t.is_active());
+                let command = match c {
+                    'd' => Command::MarkDone(vec![task_ref]),
+                    'x' => Command::MarkCanceled(vec![task_ref]),
+                    's' if active => Command::Stop(vec![task_ref]),
+                    's' => Command::Start {
+                        task_refs: vec![task_ref],
+                        stop_others: false,
+                    },
+                    _ => return true,
+                };
+                self.execute(command);
+            }
+            _ => (),
+        }
+
+        true
+    }
+
+    fn draw(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        let size = terminal_size();
+        // Title and status line
+        let mut free = size.rows.saturating_sub(2);
+        let table_rows = if self.details { free / 2 } else { free };
+        free -= table_rows;
+        // Minus the table header
+        self.height = table_rows.saturating_sub(1).max(1);
+
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + self.height {
+            self.offset = self.selected + 1 - self.height;
+        }
+        self.offset = self.offset.min(self.tasks.len().saturating_sub(self.height));
+
+        write!(out, "{}", term::HOME)?;
+
+        let mut title = format!("rtask  {} tasks", self.tasks.len());
+        if self.scope.as_tag().is_some() {
+            title.push_str(&format!("  scope: {}", self.scope));
+        }
+        if !self.filter_text.is_empty() {
+            title.push_str(&format!("  filter: {}", self.filter_text));
+        }
+        let title = title.ellipsize(size.columns).into_owned();
+        write!(out, "{}{}\n", self.colors.style(Role::Header).paint(title), term::CLEAR_LINE)?;
+
+        let mut table = vec![];
+        self.draw_table(&mut table, size.columns)?;
+        for line in String::from_utf8_lossy(&table).lines().take(table_rows) {
+            write!(out, "{}{}\n", line, term::CLEAR_LINE)?;
+        }
+
+        if self.details {
+            let mut details = vec![];
+            if let Some(task) = self.selected_task() {
+                let id = self.model
+                    .short_task_id(&self.scope, &task.uuid)
+                    .map_or_else(|| task.short_id(), |id| id.to_string());
+                writeln!(details, "\n==== task {} ====", id)?;
+                ::write_task_details(&mut details, task)?;
+            }
+            for line in String::from_utf8_lossy(&details).lines().take(free) {
+                write!(out, "{}{}\n", line, term::CLEAR_LINE)?;
+            }
+        }
+
+        write!(out, "{}", term::CLEAR_BELOW)?;
+        write!(out, "\x1b[{};1H", size.rows)?;
+        match self.prompt {
+            Some((Prompt::Filter, ref text)) => write!(out, "filter: {}", text)?,
+            Some((Prompt::Modify, ref text)) => write!(out, "modify: {}", text)?,
+            None if !self.message.is_empty() => write!(out, "{}", self.message.ellipsize(size.columns))?,
+            None => write!(out, "{}", HELP.ellipsize(size.columns))?,
+        }
+        write!(out, "{}", term::CLEAR_LINE)?;
+
+        Ok(())
+    }
+
+    fn draw_table(&self, out: &mut Vec<u8>, width: usize) -> io::Result<()> {
+        if self.tasks.is_empty() {
+            return writeln!(out, "No matching tasks found");
+        }
+
+        let visible: Vec<&Task> = self.tasks
+            .iter()
+            .skip(self.offset)
+            .take(self.height)
+            .flat_map(|uuid| self.model.get_task(uuid))
+            .collect();
+
+        let mut columns = DEFAULT_COLUMNS.to_vec();
+        if visible.iter().any(|t| t.is_active()) {
+            columns.insert(1, Column::Active);
+        }
+
+        let context = CellContext {
+            model: self.model,
+            scope: &self.scope,
+            tag_separator: ",",
+        };
+
+        let rows: Vec<PrintRow> = visible
+            .iter()
+            .enumerate()
+            .map(|(n, task)| {
+                let mut style = self.colors.combined(&::task_roles(task, self.offset + n));
+                if self.offset + n == self.selected {
+                    style = style.reverse();
+                }
+                PrintRow {
+                    fields: columns.iter().map(|c| c.value(task, &context)).collect(),
+                    style: Some(style),
+                    header: false,
+                }
+            })
+            .collect();
+
+        let mut p = TablePrinter::new();
+        p.header_style = self.colors.style(Role::Header);
+        p.titles = columns.iter().map(|c| c.title()).collect();
+        p.width_limit = Some(width.saturating_sub(2 * p.titles.len()));
+        p.alignments.insert(Column::Description.title(), Alignment::Left);
+        p.flexible.insert(Column::Description.title());
+        p.flexible.insert(Column::Tags.title());
+        p.ellipsize = true;
+
+        p.print(out, &rows).map_err(|e| match e {
+            PrintError::IO(e) => e,
+        })
+    }
+}
@@ -0,0 +1,175 @@
+//! Raw terminal input and the escape codes the TUI needs
+
+use libc;
+
+use std::io::{self, Read, Write};
+use std::mem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Esc,
+    Backspace,
+    Char(char),
+    Ctrl(char),
+    /// An escape sequence without a binding
+    Unknown,
+}
+
+/// Puts the terminal into raw mode on the alternate screen, everything
+/// is undone when dropped
+pub struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    pub fn enter() -> io::Result<Self> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        // Reads return after a tenth of a second without input, which
+        // tells a lone Esc from the start of an escape sequence
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 1;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut stdout = io::stdout();
+        // Lines longer than the terminal are cut instead of wrapped
+        write!(stdout, "\x1b[?1049h\x1b[?25l\x1b[?7l")?;
+        stdout.flush()?;
+
+        Ok(RawTerminal { original: original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original) };
+
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\x1b[?7h\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+    }
+}
+
+/// Moves the cursor to the top left corner
+pub const HOME: &'static str = "\x1b[H";
+/// Clears the rest of the line
+pub const CLEAR_LINE: &'static str = "\x1b[K";
+/// Clears everything below the cursor
+pub const CLEAR_BELOW: &'static str = "\x1b[J";
+
+fn read_byte(input: &mut Read) -> io::Result<Option<u8>> {
+    let mut buf = [0];
+    match input.read(&mut buf)? {
+        0 => Ok(None),
+        _ => Ok(Some(buf[0])),
+    }
+}
+
+/// Waits for the next key press
+pub fn read_key(input: &mut Read) -> io::Result<Key> {
+    loop {
+        if let Some(byte) = read_byte(input)? {
+            return parse_key(byte, input);
+        }
+    }
+}
+
+fn parse_key(byte: u8, input: &mut Read) -> io::Result<Key> {
+    Ok(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => return parse_escape(input),
+        1..=26 => Key::Ctrl((b'a' + byte - 1) as char),
+        0..=0x7f => Key::Char(byte as char),
+        _ => {
+            // The rest of an UTF-8 encoded character
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let mut bytes = vec![byte];
+            while bytes.len() < len {
+                match read_byte(input)? {
+                    Some(b) => bytes.push(b),
+                    None => break,
+                }
+            }
+            String::from_utf8(bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+                .map_or(Key::Unknown, Key::Char)
+        }
+    })
+}
+
+fn parse_escape(input: &mut Read) -> io::Result<Key> {
+    let kind = match read_byte(input)? {
+        None => return Ok(Key::Esc),
+        Some(kind) => kind,
+    };
+    if kind != b'[' && kind != b'O' {
+        return Ok(Key::Unknown);
+    }
+
+    // Parameters up to the final byte, like `5~` or `A`
+    let mut sequence = vec![];
+    while let Some(byte) = read_byte(input)? {
+        sequence.push(byte);
+        if byte >= 0x40 && byte <= 0x7e {
+            break;
+        }
+    }
+
+    Ok(match &sequence[..] {
+        b"A" => Key::Up,
+        b"B" => Key::Down,
+        b"H" | b"1~" | b"7~" => Key::Home,
+        b"F" | b"4~" | b"8~" => Key::End,
+        b"5~" => Key::PageUp,
+        b"6~" => Key::PageDown,
+        _ => Key::Unknown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(mut input: &[u8]) -> Vec<Key> {
+        let mut keys = vec![];
+        while !input.is_empty() {
+            keys.push(read_key(&mut input).unwrap());
+        }
+        keys
+    }
+
+    #[test]
+    fn test_read_key() {
+        assert_eq!(
+            vec![Key::Char('j'), Key::Enter, Key::Ctrl('c'), Key::Backspace],
+            keys(b"j\r\x03\x7f")
+        );
+        assert_eq!(
+            vec![Key::Up, Key::Down, Key::PageDown, Key::Home, Key::End, Key::Unknown],
+            keys(b"\x1b[A\x1bOB\x1b[6~\x1b[H\x1b[4~\x1b[15~")
+        );
+        assert_eq!(vec![Key::Char('ä'), Key::Char('日')], keys("ä日".as_bytes()));
+        assert_eq!(vec![Key::Esc], keys(b"\x1b"));
+    }
+}
@@ -0,0 +1,360 @@
+//! The routes of the REST API. Tasks and effects are sent in their
+//! serde representation, changes are made with the same commands as on
+//! the command line.
+//!
+//! - `GET /tasks?filter=...`: The tasks matching a filter like
+//!   `+work pri:h`, most urgent first
+//! - `GET /tasks/<ref>`: A single task
+//! - `POST /tasks`: Adds a task, see `NewTask`
+//! - `PATCH /tasks/<ref>`: Changes a task, see `TaskChanges`
+//! - `POST /tasks/<ref>/done`, `POST /tasks/<ref>/cancel`
+//! - `DELETE /tasks/<ref>`
+//! - `GET /effects?since=n`: The effects applied after the first `n`
+//!
+//! All routes take an optional `scope`. Writes answer with the effects
+//! they applied, hooks can change or reject them.
+
+use serde::{Deserialize, Deserializer};
+use serde_json::{self, Value};
+use tiny_http::Method;
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+
+use rtask::*;
+use rtask::command::{filter_tasks, Command, CommandError, Flag};
+use rtask::hooks::{HookError, Hooks, HOOKS_DIR};
+use rtask::report::Report;
+use rtask::task_ref::TaskRef;
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+    /// Where a created task can be found
+    pub location: Option<String>,
+}
+
+impl Response {
+    fn ok<T: ::serde::Serialize + ?Sized>(body: &T) -> Result<Self, ApiError> {
+        Ok(Response {
+            status: 200,
+            body: serde_json::to_value(body).map_err(|e| ApiError::Internal(e.to_string()))?,
+            location: None,
+        })
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Response {
+            status: status,
+            body: json!({ "error": message }),
+            location: None,
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum ApiError {
+    #[fail(display = "Not found")]
+    NotFound,
+    #[fail(display = "Method not allowed")]
+    MethodNotAllowed,
+    #[fail(display = "{}", _0)]
+    BadRequest(String),
+    #[fail(display = "{}", _0)]
+    Command(CommandError),
+    #[fail(display = "{}", _0)]
+    Hook(HookError),
+    #[fail(display = "{}", _0)]
+    Internal(String),
+}
+
+impl From<CommandError> for ApiError {
+    fn from(e: CommandError) -> Self {
+        ApiError::Command(e)
+    }
+}
+
+impl From<HookError> for ApiError {
+    fn from(e: HookError) -> Self {
+        ApiError::Hook(e)
+    }
+}
+
+impl From<FindTaskError> for ApiError {
+    fn from(e: FindTaskError) -> Self {
+        ApiError::Command(CommandError::FindTask(e))
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> u16 {
+        match *self {
+            ApiError::NotFound => 404,
+            ApiError::MethodNotAllowed => 405,
+            ApiError::BadRequest(_) => 400,
+            ApiError::Command(CommandError::FindTask(FindTaskError::TaskNotFound)) => 404,
+            ApiError::Command(CommandError::FindTask(FindTaskError::MultipleResults)) => 409,
+            ApiError::Command(CommandError::Invalid(_)) => 422,
+            ApiError::Hook(HookError::Run(..)) => 500,
+            ApiError::Hook(_) => 422,
+            ApiError::Internal(_) => 500,
+        }
+    }
+}
+
+/// The body of `POST /tasks`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewTask {
+    pub description: Title,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub tags: Tags,
+    #[serde(default)]
+    pub wait: Option<Time>,
+    #[serde(default)]
+    pub scheduled: Option<Time>,
+    #[serde(default)]
+    pub due: Option<Time>,
+}
+
+impl NewTask {
+    fn into_command(self) -> Command {
+        let mut flags: Vec<Flag> = self.priority.into_iter().map(Flag::Priority).collect();
+        flags.extend(self.tags.into_iter().map(Flag::TagPositive));
+        for (kind, date) in vec![
+            (TaskDate::Wait, self.wait),
+            (TaskDate::Scheduled, self.scheduled),
+            (TaskDate::Due, self.due),
+        ] {
+            if date.is_some() {
+                flags.push(Flag::Date(kind, date));
+            }
+        }
+
+        Command::Add(self.description, flags)
+    }
+}
+
+/// The body of `PATCH /tasks/<ref>`, fields which are left out stay
+/// as they are
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskChanges {
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// All tags of the task, tags which aren't listed are removed
+    #[serde(default)]
+    pub tags: Option<Tags>,
+    /// `null` removes the date
+    #[serde(default, deserialize_with = "present")]
+    pub wait: Option<Option<Time>>,
+    #[serde(default, deserialize_with = "present")]
+    pub scheduled: Option<Option<Time>>,
+    #[serde(default, deserialize_with = "present")]
+    pub due: Option<Option<Time>>,
+}
+
+/// Tells a field set to `null` apart from one that is left out
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl TaskChanges {
+    fn into_command(self, task: &Task) -> Command {
+        let tags = self.tags.unwrap_or_else(|| task.tags.clone());
+        let dates = vec![
+            (TaskDate::Wait, self.wait),
+            (TaskDate::Scheduled, self.scheduled),
+            (TaskDate::Due, self.due),
+        ];
+
+        Command::ChangeTaskProperties {
+            task_refs: vec![task.uuid.into()],
+            added_tags: tags.difference(&task.tags).cloned().collect(),
+            removed_tags: task.tags.difference(&tags).cloned().collect(),
+            priority: self.priority.filter(|p| *p != task.priority),
+            dates: dates
+                .into_iter()
+                .filter_map(|(kind, date)| date.map(|d| (kind, d)))
+                .filter(|&(kind, date)| task.date(kind) != date)
+                .collect(),
+        }
+    }
+}
+
+/// Answers a request, changes are applied to `model`
+pub fn handle(model: &mut Model, method: &Method, url: &str, body: &str) -> Response {
+    match route(model, method, url, body) {
+        Ok(response) => response,
+        Err(error) => Response::error(error.status(), &error.to_string()),
+    }
+}
+
+fn route(model: &mut Model, method: &Method, url: &str, body: &str) -> Result<Response, ApiError> {
+    let (path, query) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => (url, ""),
+    };
+    let query: HashMap<String, String> = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+    let scope = Scope(query.get("scope").cloned());
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match (method, &segments[..]) {
+        (&Method::Get, &["tasks"]) => {
+            let mut flags = match query.get("filter") {
+                Some(filter) => Report::parse_filter(filter).map_err(ApiError::BadRequest)?,
+                None => vec![],
+            };
+            if let Some(tag) = scope.as_tag() {
+                flags.push(Flag::TagPositive(tag));
+            }
+
+            let tasks: Vec<Task> = filter_tasks(model, &flags).into_iter().cloned().collect();
+            // Numerical references follow the latest list, like on the
+            // command line
+            let uuids: Vec<Uuid> = tasks.iter().map(|t| t.uuid).collect();
+            model.recalculate_numerical_ids(&scope, &uuids);
+
+            Response::ok(&tasks)
+        }
+        (&Method::Post, &["tasks"]) => {
+            let new_task: NewTask = parse_body(body)?;
+            let effects = apply(model, new_task.into_command(), &scope)?;
+            let location = effects.first().map(|e| format!("/tasks/{}", e.task_id()));
+
+            let mut response = Response::ok(&effects)?;
+            response.status = 201;
+            response.location = location;
+            Ok(response)
+        }
+        (&Method::Get, &["tasks", task_ref]) => Response::ok(find_task(model, &scope, task_ref)?),
+        (&Method::Patch, &["tasks", task_ref]) => {
+            let changes: TaskChanges = parse_body(body)?;
+            let command = changes.into_command(find_task(model, &scope, task_ref)?);
+            Response::ok(&apply(model, command, &scope)?)
+        }
+        (&Method::Delete, &["tasks", task_ref]) => {
+            let uuid = find_task(model, &scope, task_ref)?.uuid;
+            Response::ok(&apply(model, Command::Delete(vec![uuid.into()]), &scope)?)
+        }
+        (&Method::Post, &["tasks", task_ref, action @ "done"])
+        | (&Method::Post, &["tasks", task_ref, action @ "cancel"]) => {
+            let refs = vec![find_task(model, &scope, task_ref)?.uuid.into()];
+            let command = if action == "done" {
+                Command::MarkDone(refs)
+            } else {
+                Command::MarkCanceled(refs)
+            };
+            Response::ok(&apply(model, command, &scope)?)
+        }
+        (&Method::Get, &["effects"]) => {
+            let since = match query.get("since") {
+                Some(since) => since
+                    .parse()
+                    .map_err(|_| ApiError::BadRequest(format!("Invalid offset {:?}", since)))?,
+                None => 0,
+            };
+            let since = since.min(model.applied_effects.len());
+            Response::ok(&model.applied_effects[since..])
+        }
+        (_, &["tasks"]) | (_, &["tasks", _]) | (_, &["tasks", _, "done"]) | (_, &["tasks", _, "cancel"])
+        | (_, &["effects"]) => Err(ApiError::MethodNotAllowed),
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|e| ApiError::BadRequest(format!("Invalid body: {}", e)))
+}
+
+fn find_task<'a>(model: &'a Model, scope: &Scope, task_ref: &str) -> Result<&'a Task, ApiError> {
+    let task_ref: TaskRef = task_ref.parse().map_err(|_| ApiError::NotFound)?;
+    Ok(model.find_task(scope, &task_ref)?)
+}
+
+/// Applies the effects of `command` as a single change, after running
+/// them past the hooks
+fn apply(model: &mut Model, command: Command, scope: &Scope) -> Result<Vec<Effect>, ApiError> {
+    let effects = command.effects(model, scope)?.effects;
+    let hooks = Hooks::load(HOOKS_DIR).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    Ok(hooks.apply(model, effects)?.effects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(model: &mut Model, method: Method, url: &str, body: Value) -> Response {
+        let body = if body.is_null() { String::new() } else { body.to_string() };
+        handle(model, &method, url, &body)
+    }
+
+    #[test]
+    fn test_tasks() {
+        let mut m = Model::new();
+
+        let response = request(
+            &mut m,
+            Method::Post,
+            "/tasks",
+            json!({"description": "foo", "tags": ["bar"], "priority": "High"}),
+        );
+        assert_eq!(201, response.status);
+        let effects: Vec<Effect> = serde_json::from_value(response.body).unwrap();
+        let task = match effects[..] {
+            [Effect::AddTask(ref task)] => task.clone(),
+            ref effects => panic!("Unexpected effects {:?}", effects),
+        };
+        assert_eq!(Some(format!("/tasks/{}", task.uuid)), response.location);
+        assert_eq!(Priority::High, task.priority);
+
+        let url = format!("/tasks/{}", task.short_id());
+        let response = request(&mut m, Method::Get, &url, Value::Null);
+        assert_eq!(200, response.status);
+        assert_eq!(task, serde_json::from_value(response.body).unwrap());
+
+        let response = request(&mut m, Method::Get, "/tasks?filter=-bar", Value::Null);
+        assert_eq!(0, response.body.as_array().unwrap().len());
+        let response = request(&mut m, Method::Get, "/tasks?filter=%2Bbar", Value::Null);
+        assert_eq!(1, response.body.as_array().unwrap().len());
+
+        // Tags are replaced, dates left out stay as they are
+        let response = request(&mut m, Method::Patch, "/tasks/1", json!({"tags": ["baz"], "due": null}));
+        assert_eq!(200, response.status);
+        let changed = m.get_task(&task.uuid).unwrap();
+        assert!(changed.has_tag("baz") && !changed.has_tag("bar"));
+
+        let response = request(&mut m, Method::Post, &format!("{}/done", url), Value::Null);
+        assert_eq!(200, response.status);
+        assert!(!m.get_task(&task.uuid).unwrap().is_open());
+
+        let response = request(&mut m, Method::Get, "/effects?since=1", Value::Null);
+        assert_eq!(2, response.body.as_array().unwrap().len());
+
+        let response = request(&mut m, Method::Delete, &url, Value::Null);
+        assert_eq!(200, response.status);
+        assert!(m.get_task(&task.uuid).is_none());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut m = Model::new();
+        let mut status = |method, url: &str, body| request(&mut m, method, url, body).status;
+
+        assert_eq!(404, status(Method::Get, "/tasks/1", Value::Null));
+        assert_eq!(404, status(Method::Get, "/tags", Value::Null));
+        assert_eq!(405, status(Method::Put, "/tasks", Value::Null));
+        assert_eq!(400, status(Method::Post, "/tasks", json!({"title": "foo"})));
+        assert_eq!(400, status(Method::Get, "/tasks?filter=due%3Asoonish", Value::Null));
+        assert_eq!(422, status(Method::Post, "/tasks", json!({"description": " "})));
+        assert_eq!(422, status(Method::Post, "/tasks", json!({"description": "foo", "tags": ["ACTIVE"]})));
+    }
+}
@@ -0,0 +1,107 @@
+extern crate env_logger;
+extern crate rtask;
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate log;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate tiny_http;
+extern crate url;
+
+use rtask::*;
+
+mod api;
+
+use tiny_http::{Header, Request, Server};
+
+use std::time::{Duration, Instant};
+use std::{env, io, thread};
+
+const DEFAULT_ADDRESS: &'static str = "127.0.0.1:8080";
+
+/// How long a request waits for the command line to finish, the daemon
+/// holds the lock all the time
+const LOCK_TIMEOUT_MS: u64 = 2000;
+
+fn main() {
+    env_logger::init();
+    chdir();
+
+    let address = env::args().nth(1).unwrap_or_else(|| DEFAULT_ADDRESS.into());
+    let server = match Server::http(&address[..]) {
+        Ok(server) => server,
+        Err(error) => {
+            println!("Error while listening on {}: {}", address, error);
+            return;
+        }
+    };
+    println!("Listening on http://{}", address);
+
+    for request in server.incoming_requests() {
+        if let Err(error) = respond(request) {
+            warn!("Failed to respond: {}", error);
+        }
+    }
+}
+
+fn respond(mut request: Request) -> io::Result<()> {
+    let mut body = String::new();
+    let response = match request.as_reader().read_to_string(&mut body) {
+        Ok(_) => handle(&request, &body),
+        Err(error) => api::Response::error(400, &error.to_string()),
+    };
+    info!("{} {} {}", request.method(), request.url(), response.status);
+
+    let mut http_response = tiny_http::Response::from_data(response.body.to_string())
+        .with_status_code(response.status)
+        .with_header(header("Content-Type", "application/json"));
+    if let Some(location) = response.location {
+        http_response = http_response.with_header(header("Location", &location));
+    }
+
+    request.respond(http_response)
+}
+
+/// The store is only opened while a request is handled, so the command
+/// line can be used next to the server
+fn handle(request: &Request, body: &str) -> api::Response {
+    let _lock = match lock() {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            return api::Response::error(503, "The tasks are in use, stop the rtask daemon to use the HTTP API")
+        }
+        Err(error) => return api::Response::error(500, &error.to_string()),
+    };
+
+    let mut store = match Storage::new() {
+        Ok(store) => store,
+        Err(error) => return api::Response::error(500, &error.to_string()),
+    };
+    store.model().origin = hostname();
+
+    let response = api::handle(store.model(), request.method(), request.url(), body);
+    match store.save() {
+        Ok(()) => response,
+        Err(error) => api::Response::error(500, &error.to_string()),
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("Invalid header")
+}
+
+/// Waits a moment for the lock, `None` if it's still taken
+fn lock() -> io::Result<Option<FileLock>> {
+    let start = Instant::now();
+    loop {
+        match FileLock::try_new(PID_FILE)? {
+            Some(lock) => return Ok(Some(lock)),
+            None if start.elapsed() > Duration::from_millis(LOCK_TIMEOUT_MS) => return Ok(None),
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}